@@ -0,0 +1,61 @@
+use std::{fs::File, path::Path};
+
+use rust_ml_toolkit::{
+    adapter::{
+        context::Context,
+        img::png::png2mat,
+        session::{PostX, SessionOption, TrainingMethod},
+    },
+    core::{
+        matrix::matrix::__Matrix,
+        nn::{
+            dataset::DataSet,
+            layer::{
+                conv::Conv2D, dense::Dense, flatten::Flatten, pool::MaxPool2D, Activation, Shape,
+            },
+            nn::NN,
+            sequential::Sequential,
+        },
+    },
+};
+
+fn main() {
+    // this is example of creating convolutional digit model
+
+    let img = png2mat(File::open("data/img/n9.png").unwrap()).unwrap();
+    let input: Vec<f64> = (0..img.mat.len_row()).map(|idx| img.mat.at(idx, 2)).collect();
+
+    // one-hot label of digit 9
+    let mut output = vec![0_f64; 10];
+    output[9] = 1.0;
+
+    let image = Shape::new(1, img.height as usize, img.width as usize);
+
+    let mut net = Sequential::new();
+    let conv = Conv2D::new(image, 4, 3, 1, 0, Activation::Sigmoid);
+    let features = conv.output_shape();
+    net.push(Box::new(conv));
+    let pool = MaxPool2D::new(features, 2, 2);
+    let pooled = pool.output_shape();
+    net.push(Box::new(pool));
+    net.push(Box::new(Flatten::new(pooled)));
+    net.push(Box::new(Dense::new(pooled.size(), 10, Activation::Sigmoid)));
+
+    let mut model = NN::from_net(net);
+    model.rand();
+
+    let mut ctx = Context::default();
+    ctx.create_session(
+        model,
+        SessionOption {
+            train_method: TrainingMethod::BackProp,
+            post_x: PostX::Sigmoid,
+            cycle: 10,
+            ..Default::default()
+        },
+    );
+
+    ctx.attach_dataset(DataSet::new(vec![input], vec![output]));
+
+    ctx.save_session(Path::new("save/digit"));
+}
@@ -1,5 +1,36 @@
+use rust_ml_toolkit::adapter::callback::{Callback, Flow};
+use rust_ml_toolkit::adapter::session::{Session, SessionOption, TrainingMethod};
+use rust_ml_toolkit::adapter::stopping::RunEnd;
+use rust_ml_toolkit::core::nn::cost::CostInfo;
+use rust_ml_toolkit::core::nn::dataset::DataSet;
 use rust_ml_toolkit::core::nn::nn;
 
+/// prints cost of every cycle, stops once it is below `target`
+struct Progress {
+    target: f64,
+}
+
+impl Callback for Progress {
+    fn on_cycle_end(
+        &mut self,
+        cycle: usize,
+        cost: &CostInfo,
+        _validation: Option<&CostInfo>,
+        _session: &Session,
+    ) -> Flow {
+        println!("{}@cost: {}", cycle, cost.avg);
+        if cost.avg < self.target {
+            Flow::Stop
+        } else {
+            Flow::Continue
+        }
+    }
+
+    fn on_train_end(&mut self, _session: &Session, end: &RunEnd) {
+        println!("trained {} cycles, stopped: {:?}", end.cycle, end.stopped);
+    }
+}
+
 fn main() {
     // using finite_diff
 
@@ -12,8 +43,6 @@ fn main() {
     let outputs = vec![vec![1.0], vec![0.0], vec![0.0], vec![1.0]];
     let layers = [2, 4, 4, 1];
 
-    let eps = 1e-4;
-    let rate = 1e-2;
     let mut nt = nn::NN::new(&layers);
 
     nt.rand();
@@ -21,27 +50,28 @@ fn main() {
 
     for input in inputs.clone() {
         println!("input : {:?}", input);
-        nt.set(input.as_slice());
-        nt.process();
-        println!("output : {:?}", nt.output());
+        println!("output : {:?}", nt.predict(&input));
     }
 
-    for idx in 0..1000000 {
-        let mut delta = nt.finite_diff(&inputs, &outputs, &eps);
-        delta.mul(&rate);
-
-        nt.learn(&delta);
-        if idx % 1000 == 0 {
-            println!("{}@cost: {}", idx, nt.cost(&inputs, &outputs));
-        }
-    }
+    let mut session = Session {
+        model: nt,
+        dataset: Some(DataSet::new(inputs.clone(), outputs)),
+        option: SessionOption {
+            train_method: TrainingMethod::FiniteDiff {
+                rate: 1e-2,
+                eps: 1e-4,
+            },
+            cycle: 1000,
+            ..Default::default()
+        },
+        split: None,
+    };
+    session.fit(1000000, &mut [&mut Progress { target: 1e-3 }]);
 
     for input in inputs {
         println!("input : {:?}", input);
-        nt.set(input.as_slice());
-        nt.process();
-        println!("output : {:?}", nt.output());
+        println!("output : {:?}", session.model.predict(&input));
     }
 
-    println!("{:?}", nt);
+    println!("{:?}", session.model);
 }
@@ -0,0 +1,75 @@
+use std::path::Path;
+
+use rust_ml_toolkit::{
+    adapter::{
+        context::Context,
+        session::{PostX, SessionOption, TrainingMethod},
+    },
+    core::nn::{
+        dataset::SequenceSet,
+        layer::{dense::Dense, recurrent::Gru, time_distributed::TimeDistributed, Activation},
+        nn::NN,
+        sequential::Sequential,
+    },
+};
+
+fn main() {
+    // this is example of creating bit-serial adder model.
+    // bits of both operands are fed one step at a time (lowest first),
+    // and every step expects the matching bit of the sum, read out of
+    // the state of that step by the same dense layer
+
+    const BITS: usize = 4;
+    // one more step to emit the carry
+    const STEPS: usize = BITS + 1;
+    const HIDDEN: usize = 8;
+
+    let max: usize = 1 << BITS;
+
+    let mut inputs: Vec<Vec<Vec<f64>>> = vec![];
+    let mut outputs: Vec<Vec<Vec<f64>>> = vec![];
+
+    for input_x in 0..max {
+        for input_y in 0..max {
+            let sum = input_x + input_y;
+
+            let mut cur_input = vec![];
+            let mut cur_output = vec![];
+            for bit in 0..STEPS {
+                cur_input.push(vec![
+                    ((input_x >> bit) & 1) as f64,
+                    ((input_y >> bit) & 1) as f64,
+                ]);
+                cur_output.push(vec![((sum >> bit) & 1) as f64]);
+            }
+
+            inputs.push(cur_input);
+            outputs.push(cur_output);
+        }
+    }
+
+    let mut net = Sequential::new();
+    net.push(Box::new(Gru::new(STEPS, 2, HIDDEN, true)));
+    net.push(Box::new(TimeDistributed::new(
+        STEPS,
+        Box::new(Dense::new(HIDDEN, 1, Activation::Sigmoid)),
+    )));
+
+    let mut model = NN::from_net(net);
+    model.rand();
+
+    let mut ctx = Context::default();
+    ctx.create_session(
+        model,
+        SessionOption {
+            train_method: TrainingMethod::BackProp,
+            post_x: PostX::Sigmoid,
+            cycle: 50,
+            ..Default::default()
+        },
+    );
+
+    ctx.attach_sequences(SequenceSet::new(inputs, outputs));
+
+    ctx.save_session(Path::new("save/serial_adder"));
+}
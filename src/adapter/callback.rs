@@ -0,0 +1,93 @@
+use crate::core::nn::cost::CostInfo;
+
+use super::{session::Session, stopping::RunEnd};
+
+/// whether training goes on after a callback
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flow {
+    Continue,
+    Stop,
+}
+
+/// hooks called by `Session::fit` along training, to log, checkpoint or stop it.
+/// cycles are counted in training steps, an epoch is a pass over the training samples
+pub trait Callback {
+    fn on_train_begin(&mut self, _session: &Session) {}
+    fn on_epoch_end(&mut self, _epoch: usize, _session: &Session) -> Flow {
+        Flow::Continue
+    }
+    /// after every chunk of `option.cycle` steps, with costs of the model
+    fn on_cycle_end(
+        &mut self,
+        _cycle: usize,
+        _cost: &CostInfo,
+        _validation: Option<&CostInfo>,
+        _session: &Session,
+    ) -> Flow {
+        Flow::Continue
+    }
+    fn on_train_end(&mut self, _session: &Session, _end: &RunEnd) {}
+}
+
+#[test]
+fn callback_test_hooks_and_stop() {
+    use super::{session::SessionOption, stopping::StopReason};
+    use crate::core::nn::{dataset::DataSet, nn::NN};
+
+    #[derive(Default)]
+    struct Record {
+        begins: usize,
+        epochs: Vec<usize>,
+        cycles: Vec<usize>,
+        end: Option<RunEnd>,
+    }
+    impl Callback for Record {
+        fn on_train_begin(&mut self, _session: &Session) {
+            self.begins += 1;
+        }
+        fn on_epoch_end(&mut self, epoch: usize, _session: &Session) -> Flow {
+            self.epochs.push(epoch);
+            Flow::Continue
+        }
+        fn on_cycle_end(
+            &mut self,
+            cycle: usize,
+            cost: &CostInfo,
+            _validation: Option<&CostInfo>,
+            _session: &Session,
+        ) -> Flow {
+            assert_eq!(cost.costs.len(), 4);
+            self.cycles.push(cycle);
+            if self.cycles.len() == 3 {
+                Flow::Stop
+            } else {
+                Flow::Continue
+            }
+        }
+        fn on_train_end(&mut self, _session: &Session, end: &RunEnd) {
+            self.end = Some(end.clone());
+        }
+    }
+
+    let inputs: Vec<Vec<f64>> = (0..4).map(|idx| vec![(idx % 2) as f64]).collect();
+    let mut session = Session {
+        model: NN::new(&[1, 1]),
+        dataset: Some(DataSet::new(inputs.clone(), inputs)),
+        // two steps per epoch
+        option: SessionOption {
+            cycle: 4,
+            batch: Some(2),
+            ..Default::default()
+        },
+        split: None,
+    };
+    let mut record = Record::default();
+    let end = session.fit(100, &mut [&mut record]);
+
+    assert_eq!(end.cycle, 12);
+    assert_eq!(end.stopped, Some(StopReason::Callback));
+    assert_eq!(record.begins, 1);
+    assert_eq!(record.epochs, vec![1, 2, 3, 4, 5, 6]);
+    assert_eq!(record.cycles, vec![4, 8, 12]);
+    assert_eq!(record.end, Some(end));
+}
@@ -2,15 +2,21 @@
 use std::{
     path::Path,
     sync::mpsc::{channel, Receiver, Sender},
+    thread::JoinHandle,
 };
 
-use crate::core::nn::{cost::CostInfo, dataset::DataSet, nn::NN};
+use crate::core::nn::{
+    cost::CostInfo,
+    dataset::{DataSet, SequenceSet},
+    metrics::Metrics,
+    nn::NN,
+};
 
 use super::{
     data::{Readable, Savable},
-    learner::{spawn_learner, ControlSignal, G2w, W2g},
+    learner::{spawn_learner, ControlSignal, G2w, LearnerEvent, W2g},
     nodes::Nodes,
-    session::{PostX, Session, SessionOption, TrainingMethod},
+    session::{OptionUpdate, PostX, Session, SessionOption, TrainingMethod},
 };
 
 #[derive(PartialEq, Debug, Clone)]
@@ -19,11 +25,17 @@ pub enum State {
     Loading,
     Ready,
     Running,
+    /// learner is held, keeping its session
+    Paused,
 }
 
 pub struct Transceiver {
     pub snd: Sender<G2w>,
     pub rec: Receiver<W2g>,
+    /// learner thread, giving back its final session
+    pub learner: JoinHandle<Session>,
+    /// training steps plotted before this learner started
+    pub offset: usize,
 }
 
 pub struct Context {
@@ -35,8 +47,16 @@ pub struct Context {
     pub nodes: Option<Nodes>,
     /// transceiver between learnner thread
     pub trcv: Option<Transceiver>,
-    /// costs history
+    /// costs history (on training samples)
     pub cost_info: Vec<CostInfo>,
+    /// training steps done at every cost_info, counted across learners
+    pub cycles: Vec<usize>,
+    /// validation costs history, same cycles as cost_info
+    pub validation_cost: Vec<Option<CostInfo>>,
+    /// metrics history, same cycles as cost_info
+    pub metrics: Vec<Metrics>,
+    /// last event reported by learner
+    pub event: Option<LearnerEvent>,
 }
 
 impl Clone for Context {
@@ -46,6 +66,10 @@ impl Clone for Context {
             state: self.state.clone(),
             nodes: self.nodes.clone(),
             cost_info: self.cost_info.clone(),
+            cycles: self.cycles.clone(),
+            validation_cost: self.validation_cost.clone(),
+            metrics: self.metrics.clone(),
+            event: self.event.clone(),
             trcv: None,
         }
     }
@@ -56,9 +80,13 @@ impl Default for Context {
         Self {
             state: State::Empty,
             cost_info: Vec::new(),
+            cycles: Vec::new(),
+            validation_cost: Vec::new(),
+            metrics: Vec::new(),
             session: None,
             nodes: None,
             trcv: None,
+            event: None,
         }
     }
 }
@@ -72,19 +100,39 @@ impl Context {
         post_x: PostX,
         cycle: usize,
     ) {
-        let session = Session {
-            model: NN::new(layers),
-            dataset: None,
-            option: SessionOption {
+        self.create_session(
+            NN::new(layers),
+            SessionOption {
                 train_method,
                 post_x,
                 cycle,
+                ..Default::default()
             },
-        };
-        self.session = Some(session.clone());
-        self.state = State::Loading;
+        );
+    }
+    /// attach given model (of any layers) with options to self
+    pub fn create_session(&mut self, model: NN, option: SessionOption) {
+        self.set_session(Session {
+            model,
+            dataset: None,
+            option,
+            split: None,
+        });
+    }
+    /// attach given session (with its dataset, if any) to self
+    pub fn set_session(&mut self, session: Session) {
         self.nodes = Some(Nodes::from(&session.model));
+        self.state = if session.dataset.is_none() {
+            State::Loading
+        } else {
+            State::Ready
+        };
+        self.session = Some(session);
         self.cost_info = Vec::new();
+        self.cycles = Vec::new();
+        self.validation_cost = Vec::new();
+        self.metrics = Vec::new();
+        self.event = None;
     }
     /// load session from saved file
     pub fn load_session(path: &Path) -> Option<Self> {
@@ -104,6 +152,10 @@ impl Context {
             nodes: Some(Nodes::from(&session.unwrap().model)),
             trcv: None,
             cost_info: Vec::new(),
+            cycles: Vec::new(),
+            validation_cost: Vec::new(),
+            metrics: Vec::new(),
+            event: None,
         })
     }
     /// save model
@@ -115,33 +167,37 @@ impl Context {
             Session::save(self.session.as_ref().unwrap(), path)
         }
     }
-    /// attach data set to self.session
+    /// attach data set to self.session (split by the ratio of the last split)
     pub fn attach_dataset(&mut self, data_set: DataSet<f64>) {
-        if self.session.is_none() {
-            println!("Context>>attach_dataset: Session is None");
-        } else {
-            self.session.as_mut().unwrap().dataset = Some(data_set);
+        match self.session.as_mut() {
+            None => println!("Context>>attach_dataset: Session is None"),
+            Some(session) => session.set_dataset(data_set),
         }
     }
+    /// attach sequence data set to self.session, flattened into rows
+    pub fn attach_sequences(&mut self, sequence_set: SequenceSet<f64>) {
+        self.attach_dataset(sequence_set.flatten());
+    }
     /// load training data and attach to self.session;
     pub fn load_dataset(&mut self, path: &Path) -> Option<bool> {
-        if self.session.is_none() {
-            println!("Context>>load_dataset: Session is None");
-            return None;
-        } else {
-            let dataset = DataSet::read(path);
-            match dataset {
-                None => {
-                    println!(
-                        "Context>>load_dataset: dataset is None 
+        let session = match self.session.as_mut() {
+            None => {
+                println!("Context>>load_dataset: Session is None");
+                return None;
+            }
+            Some(session) => session,
+        };
+        match DataSet::read(path) {
+            None => {
+                println!(
+                    "Context>>load_dataset: dataset is None 
                     (faild to load dataset)"
-                    );
-                    return None;
-                }
-                Some(dataset) => {
-                    self.session.as_mut().unwrap().dataset = Some(dataset);
-                    return Some(true);
-                }
+                );
+                None
+            }
+            Some(dataset) => {
+                session.set_dataset(dataset);
+                Some(true)
             }
         }
     }
@@ -166,28 +222,143 @@ impl Context {
             State::Loading | State::Empty => {
                 println!("Context>>start: Not Readied");
             }
-            State::Running => {
+            State::Running | State::Paused => {
                 println!("Context>>start: Already Running");
             }
             State::Ready => {
                 let (snd, rx) = channel();
-                let rec = spawn_learner(self.session.clone().unwrap(), rx);
+                let (rec, learner) = spawn_learner(self.session.clone().unwrap(), rx);
                 self.state = State::Running;
-                self.trcv = Some(Transceiver { snd, rec })
+                self.trcv = Some(Transceiver {
+                    snd,
+                    rec,
+                    learner,
+                    offset: self.cycles.last().copied().unwrap_or(0),
+                });
+                self.event = None;
             }
         }
     }
-    /// stop training
-    pub fn stop(&mut self) {
+    /// stop training, returns the final session of the learner
+    pub fn stop(&mut self) -> Option<&Session> {
+        match self.state {
+            State::Running | State::Paused => self.terminate(),
+            _ => None,
+        }
+    }
+    /// hold training, the model can be inspected until resumed
+    pub fn pause(&mut self) {
         match self.state {
             State::Running => {
-                let res = self.trcv.as_ref().unwrap().snd.send(G2w {
-                    sig: ControlSignal::Stop,
-                });
-                self.state = State::Ready;
+                self.signal(ControlSignal::Pause);
+                self.state = State::Paused;
+            }
+            _ => println!("Context>>pause: Not Running"),
+        }
+    }
+    pub fn resume(&mut self) {
+        match self.state {
+            State::Paused => {
+                self.signal(ControlSignal::Resume);
+                self.state = State::Running;
+            }
+            _ => println!("Context>>resume: Not Paused"),
+        }
+    }
+    /// run `cycles` learner cycles while paused
+    pub fn step(&mut self, cycles: usize) {
+        match self.state {
+            State::Paused => self.signal(ControlSignal::Step(cycles)),
+            _ => println!("Context>>step: Not Paused"),
+        }
+    }
+    /// change options of the session, a learner applies them on its next cycle
+    /// and acknowledges with the options in use
+    pub fn update_option(&mut self, update: OptionUpdate) {
+        if self.is_training() {
+            self.signal(ControlSignal::Update(update));
+        } else if let Some(session) = self.session.as_mut() {
+            update.apply(&mut session.option);
+        }
+    }
+    /// whether a learner holds the session (running or paused)
+    pub fn is_training(&self) -> bool {
+        matches!(self.state, State::Running | State::Paused)
+    }
+    fn signal(&self, sig: ControlSignal) {
+        if let Some(trcv) = self.trcv.as_ref() {
+            if trcv.snd.send(G2w { sig }).is_err() {
+                println!("Context>>signal: learner is gone");
+            }
+        }
+    }
+    /// take every report of the learner, applying them in order
+    pub fn poll(&mut self) {
+        let received: Vec<W2g> = match self.trcv.as_ref() {
+            None => return,
+            Some(trcv) => trcv.rec.try_iter().collect(),
+        };
+        for w2g in received {
+            self.receive(w2g);
+        }
+    }
+    fn receive(&mut self, w2g: W2g) {
+        let offset = self.trcv.as_ref().map_or(0, |trcv| trcv.offset);
+        if let Some(nodes) = w2g.nodes {
+            self.nodes = Some(nodes);
+        }
+        if let Some(session) = self.session.as_mut() {
+            if let Some(model) = w2g.model {
+                session.model = model;
+            }
+            if let Some(option) = w2g.option {
+                session.option = option;
+            }
+        }
+        for point in w2g.costs {
+            self.cost_info.push(point.cost_info);
+            self.cycles.push(offset + point.cycle);
+            self.validation_cost.push(point.validation);
+            if let Some(metrics) = point.metrics {
+                self.metrics.push(metrics);
+            }
+        }
+        if let Some(event) = w2g.event {
+            self.on_event(event);
+        }
+    }
+    /// handle event reported by learner
+    pub fn on_event(&mut self, event: LearnerEvent) {
+        match event {
+            LearnerEvent::Diverged { .. }
+            | LearnerEvent::Finished { .. }
+            | LearnerEvent::Failed { .. } => {
+                // learner has stopped by itself
+                self.terminate();
+            }
+        }
+        self.event = Some(event);
+    }
+    /// end the learner and wait for it (up to the end of its current cycle),
+    /// its final session replaces the current one
+    pub fn terminate(&mut self) -> Option<&Session> {
+        let trcv = self.trcv.take()?;
+        self.state = State::Ready;
+        let _ = trcv.snd.send(G2w {
+            sig: ControlSignal::Stop,
+        });
+        // the learner may be waiting for reports to be taken
+        drop(trcv.rec);
+        match trcv.learner.join() {
+            Err(_) => {
+                println!("Context>>terminate: learner panicked");
+                None
+            }
+            Ok(session) => {
+                self.nodes = Some(Nodes::from(&session.model));
+                self.session = Some(session);
+                self.session.as_ref()
             }
-            _ => {}
         }
     }
-    fn terminate(&mut self) {}
 }
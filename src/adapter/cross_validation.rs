@@ -0,0 +1,220 @@
+use std::thread;
+
+use crate::core::nn::{
+    dataset::{DataSet, Split, SplitMethod, SplitRatio},
+    ensemble::{Combine, Ensemble},
+    metrics::Metrics,
+    nn::NN,
+};
+
+use super::{
+    session::{Divergence, Session, SessionOption},
+    stopping::StopReason,
+};
+
+/// k-fold cross-validation setup.
+/// every fold is held out once while a fresh model trains on the others
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrossValidation {
+    pub folds: usize,
+    pub method: SplitMethod,
+    /// training steps of each fold at most, stop conditions
+    /// of the session option may end a fold earlier
+    pub max_cycles: usize,
+}
+
+/// mean and standard deviation of a metric over folds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aggregate {
+    pub mean: f64,
+    pub std: f64,
+}
+
+impl Aggregate {
+    /// None if there are no values
+    pub fn of(values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+        Some(Aggregate {
+            mean,
+            std: var.sqrt(),
+        })
+    }
+}
+
+/// outcome of a single fold
+#[derive(Debug, Clone)]
+pub struct FoldResult {
+    /// training steps done
+    pub cycle: usize,
+    /// avg cost on the training folds
+    pub train_cost: f64,
+    /// avg cost on the held out fold
+    pub validation_cost: f64,
+    /// metrics on the held out fold
+    pub metrics: Metrics,
+    /// stop condition which ended the fold before max_cycles
+    pub stopped: Option<StopReason>,
+    pub diverged: Option<Divergence>,
+    /// model trained on the fold
+    pub model: NN,
+}
+
+/// per fold results, and their aggregates over folds which did not diverge
+#[derive(Debug, Clone)]
+pub struct CrossValidationReport {
+    pub folds: Vec<FoldResult>,
+    pub validation_cost: Option<Aggregate>,
+    pub accuracy: Option<Aggregate>,
+    /// macro f1
+    pub f1: Option<Aggregate>,
+    pub bit_accuracy: Option<Aggregate>,
+    pub rmse: Option<Aggregate>,
+    pub r2: Option<Aggregate>,
+}
+
+impl CrossValidationReport {
+    fn new(folds: Vec<FoldResult>) -> Self {
+        let kept: Vec<&FoldResult> = folds.iter().filter(|f| f.diverged.is_none()).collect();
+        let collect = |value: fn(&FoldResult) -> Option<f64>| {
+            Aggregate::of(&kept.iter().filter_map(|f| value(f)).collect::<Vec<f64>>())
+        };
+        CrossValidationReport {
+            validation_cost: collect(|f| Some(f.validation_cost)),
+            accuracy: collect(|f| f.metrics.classification.as_ref().map(|c| c.accuracy)),
+            f1: collect(|f| f.metrics.classification.as_ref().map(|c| c.macro_avg.f1)),
+            bit_accuracy: collect(|f| f.metrics.bit_accuracy),
+            rmse: collect(|f| Some(f.metrics.regression.rmse)),
+            r2: collect(|f| Some(f.metrics.regression.r2)),
+            folds,
+        }
+    }
+
+    /// models of folds which did not diverge, combined as a single one
+    pub fn ensemble(&self, combine: Combine) -> Option<Ensemble> {
+        let models = self
+            .folds
+            .iter()
+            .filter(|f| f.diverged.is_none())
+            .map(|f| f.model.clone())
+            .collect();
+        Ensemble::new(models, combine)
+    }
+}
+
+impl CrossValidation {
+    /// train a randomized copy of `model` on every fold, each on its own thread
+    pub fn run(
+        &self,
+        model: &NN,
+        dataset: &DataSet<f64>,
+        option: &SessionOption,
+    ) -> Result<CrossValidationReport, String> {
+        if self.folds < 2 || self.folds > dataset.len() {
+            return Err(format!(
+                "CrossValidation>>run: can not make {} folds of {} samples",
+                self.folds,
+                dataset.len()
+            ));
+        }
+
+        Session {
+            model: model.clone(),
+            dataset: None,
+            option: option.clone(),
+            split: None,
+        }
+        .check_option()?;
+
+        let folds = dataset.folds(self.folds, self.method);
+        let handles: Vec<_> = (0..self.folds)
+            .map(|held_out| {
+                let train = folds
+                    .iter()
+                    .enumerate()
+                    .filter(|(idx, _)| *idx != held_out)
+                    .flat_map(|(_, fold)| fold.iter().copied())
+                    .collect();
+                let mut model = model.clone();
+                model.rand();
+                let session = Session {
+                    model,
+                    dataset: Some(dataset.clone()),
+                    option: option.clone(),
+                    split: Some(Split {
+                        ratio: SplitRatio {
+                            validation: 1.0 / self.folds as f64,
+                            test: 0.0,
+                            method: self.method,
+                        },
+                        train,
+                        validation: folds[held_out].clone(),
+                        test: Vec::new(),
+                    }),
+                };
+                let max_cycles = self.max_cycles;
+                thread::spawn(move || train_fold(session, max_cycles))
+            })
+            .collect();
+
+        let mut results = Vec::with_capacity(self.folds);
+        for (fold, handle) in handles.into_iter().enumerate() {
+            match handle.join() {
+                Ok(result) => results.push(result),
+                Err(_) => return Err(format!("CrossValidation>>run: fold {} panicked", fold)),
+            }
+        }
+        Ok(CrossValidationReport::new(results))
+    }
+}
+
+fn train_fold(mut session: Session, max_cycles: usize) -> FoldResult {
+    let end = session.fit(max_cycles, &mut []);
+    FoldResult {
+        cycle: end.cycle,
+        train_cost: session.cost().unwrap().avg,
+        validation_cost: session.validation_cost().unwrap().avg,
+        metrics: session.metrics().unwrap(),
+        stopped: end.stopped,
+        diverged: end.diverged,
+        model: session.model,
+    }
+}
+
+#[test]
+fn cross_validation_test_folds() {
+    let inputs: Vec<Vec<f64>> = (0..12).map(|idx| vec![(idx % 2) as f64]).collect();
+    let outputs: Vec<Vec<f64>> = (0..12).map(|idx| vec![(idx % 2) as f64]).collect();
+    let dataset = DataSet::new(inputs, outputs);
+
+    let cv = CrossValidation {
+        folds: 3,
+        method: SplitMethod::Stratified,
+        max_cycles: 200,
+    };
+    let option = SessionOption {
+        cycle: 50,
+        ..Default::default()
+    };
+    let report = cv.run(&NN::new(&[1, 1]), &dataset, &option).unwrap();
+
+    assert_eq!(report.folds.len(), 3);
+    assert!(report.folds.iter().all(|f| f.cycle == 200));
+    // identity is easy to learn on every fold
+    let accuracy = report.accuracy.unwrap();
+    assert_eq!(accuracy.mean, 1.0);
+    assert_eq!(accuracy.std, 0.0);
+    assert!(report.validation_cost.unwrap().mean < 0.25);
+
+    let ensemble = report.ensemble(Combine::Vote).unwrap();
+    assert_eq!(ensemble.models.len(), 3);
+    let metrics = ensemble.metrics(&dataset.inputs, &dataset.outputs);
+    assert_eq!(metrics.classification.unwrap().accuracy, 1.0);
+
+    let too_many = CrossValidation { folds: 13, ..cv };
+    assert!(too_many.run(&NN::new(&[1, 1]), &dataset, &option).is_err());
+}
@@ -4,7 +4,7 @@ use std::{
     path::Path,
 };
 
-use crate::core::nn::nn::NN;
+use crate::core::nn::{ensemble::Ensemble, nn::NN};
 
 // TODO: rename this file
 
@@ -91,6 +91,51 @@ impl Readable for NN {
     }
 }
 
+impl Stringfiable for Ensemble {
+    type Struct = Ensemble;
+    fn stringfy(src: &Self::Struct) -> Option<String> {
+        serde_json::to_string_pretty(&src).ok()
+    }
+}
+
+impl Buildable for Ensemble {
+    type Struct = Ensemble;
+    fn build(str: String) -> Option<Self::Struct> {
+        serde_json::from_str(&str).ok()
+    }
+}
+
+impl Savable for Ensemble {
+    type Struct = Ensemble;
+    fn save(data: &Self::Struct, path: &Path) -> Option<bool> {
+        let mut file = match File::create(path) {
+            Err(e) => panic!("could not create at {}: {}", path.display(), e),
+            Ok(file) => file,
+        };
+        let str = Ensemble::stringfy(data)?;
+        if file.write_all(str.as_bytes()).is_ok() {
+            return Some(true);
+        }
+        None
+    }
+}
+
+impl Readable for Ensemble {
+    type Struct = Ensemble;
+
+    fn read(path: &Path) -> Option<Self::Struct> {
+        let mut file = match File::open(path) {
+            Err(e) => panic!("could not open {}: {}", path.display(), e),
+            Ok(file) => file,
+        };
+        let mut buf = String::new();
+        if file.read_to_string(&mut buf).is_err() {
+            return None;
+        }
+        Ensemble::build(buf)
+    }
+}
+
 #[test]
 fn test_data_save_and_read() {
     let layers = [2, 4, 4, 1];
@@ -113,25 +158,16 @@ fn test_data_save_and_read() {
     for (idx, l) in orgin.layers.iter().enumerate() {
         assert!(saved.layers[idx] == *l)
     }
-    for (idx, matrix) in orgin.weights.iter().enumerate() {
-        for (widx, weight) in matrix.el.iter().enumerate() {
-            for (wwidx, val) in weight.iter().enumerate() {
-                assert!(
-                    (f64::trunc(saved.weights[idx].el[widx][wwidx] * PRSIZE)
-                        / PRSIZE)
-                        == (f64::trunc(*val * PRSIZE) / PRSIZE)
-                )
-            }
-        }
-    }
-    for (idx, matrix) in orgin.biases.iter().enumerate() {
-        for (bidx, biase) in matrix.el.iter().enumerate() {
-            for (bbidx, val) in biase.iter().enumerate() {
-                assert!(
-                    (f64::trunc(saved.biases[idx].el[bidx][bbidx] * PRSIZE)
-                        / PRSIZE)
-                        == (f64::trunc(*val * PRSIZE) / PRSIZE)
-                )
+    for (level, layer) in orgin.net.layers.iter().enumerate() {
+        for (pidx, matrix) in layer.params().into_iter().enumerate() {
+            for (ridx, row) in matrix.el.iter().enumerate() {
+                for (cidx, val) in row.iter().enumerate() {
+                    assert!(
+                        (f64::trunc(saved.net.layers[level].params()[pidx].el[ridx][cidx] * PRSIZE)
+                            / PRSIZE)
+                            == (f64::trunc(*val * PRSIZE) / PRSIZE)
+                    )
+                }
             }
         }
     }
@@ -1,12 +1,20 @@
 use std::{
-    sync::mpsc::{channel, Receiver, Sender},
-    thread,
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
+    thread::{self, JoinHandle},
     time::Duration,
 };
 
-use crate::core::nn::{cost::CostInfo, nn::NN};
+use serde::{Deserialize, Serialize};
 
-use super::{nodes::Nodes, session::Session};
+use crate::core::nn::{cost::CostInfo, metrics::Metrics, nn::NN};
+
+use super::{
+    nodes::Nodes,
+    session::{Divergence, OptionUpdate, Session, SessionOption},
+    stopping::{Monitor, StopReason},
+};
 
 // Learner Process <- DATA(A) -- Gui Process
 //
@@ -56,7 +64,13 @@ use super::{nodes::Nodes, session::Session};
 /// control signal (gui => worker)
 #[derive(Debug)]
 pub enum ControlSignal {
+    /// hold training, keeping the session and stop monitor as they are
     Pause,
+    Resume,
+    /// run given learner cycles then hold again
+    Step(usize),
+    /// change options from the next cycle on
+    Update(OptionUpdate),
     Stop,
 }
 /// data (gui => worker)
@@ -65,58 +79,502 @@ pub struct G2w {
     pub sig: ControlSignal,
 }
 
-/// data (worker => gui)
+/// notable event of the learner (worker => gui)
+#[derive(Debug, Clone)]
+pub enum LearnerEvent {
+    /// training stopped, model was restored to the one of given cycle
+    Diverged { cycle: usize, reason: Divergence },
+    /// a stop condition was met
+    Finished { cycle: usize, reason: StopReason },
+    /// training panicked, model was restored to the one of given cycle
+    Failed { cycle: usize, message: String },
+}
+
+/// cost of the model after a cycle
+#[derive(Clone)]
+pub struct CostPoint {
+    /// training steps done
+    pub cycle: usize,
+    /// cost on training samples
+    pub cost_info: CostInfo,
+    /// cost on validation samples, if the dataset is split
+    pub validation: Option<CostInfo>,
+    /// evaluation on the dataset
+    pub metrics: Option<Metrics>,
+}
+
+/// how often the learner reports, in learner cycles
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Reporting {
+    /// cycles between cost points
+    pub costs: usize,
+    /// cycles between snapshots of the model (and its nodes view)
+    pub snapshot: usize,
+}
+
+impl Default for Reporting {
+    fn default() -> Self {
+        Reporting {
+            costs: 1,
+            snapshot: 1,
+        }
+    }
+}
+
+/// data (worker => gui).
+/// reports not yet taken by the gui are merged into a single one
 pub struct W2g {
+    /// training steps done
     pub cycle: usize,
-    pub cost_info: Option<CostInfo>,
+    /// cost points since last message, oldest first
+    pub costs: Vec<CostPoint>,
     pub nodes: Option<Nodes>,
     pub model: Option<NN>,
+    pub event: Option<LearnerEvent>,
+    /// options in use after an update, acknowledging it
+    pub option: Option<SessionOption>,
 }
 
-pub fn spawn_learner(session: Session, rx: Receiver<G2w>) -> Receiver<W2g> {
-    let (snd, rec) = channel();
+/// messages waiting for the gui at most
+const BOUND: usize = 1;
+/// cost points kept while the gui is behind, older ones are dropped
+const MAX_POINTS: usize = 100;
 
-    thread::spawn(move || handle(rx, snd, session));
+impl W2g {
+    fn new(cycle: usize) -> Self {
+        W2g {
+            cycle,
+            costs: Vec::new(),
+            nodes: None,
+            model: None,
+            event: None,
+            option: None,
+        }
+    }
 
-    return rec;
+    /// report of the current model (and its nodes view)
+    fn snapshot(cycle: usize, model: &NN) -> Self {
+        W2g {
+            nodes: Some(Nodes::from(model)),
+            model: Some(model.clone()),
+            ..W2g::new(cycle)
+        }
+    }
+
+    /// put a later report on top of this one, keeping its latest values
+    fn merge(&mut self, later: W2g) {
+        self.cycle = later.cycle;
+        self.costs.extend(later.costs);
+        if self.costs.len() > MAX_POINTS {
+            self.costs.drain(..self.costs.len() - MAX_POINTS);
+        }
+        if later.model.is_some() {
+            self.model = later.model;
+            self.nodes = later.nodes;
+        }
+        if later.event.is_some() {
+            self.event = later.event;
+        }
+        if later.option.is_some() {
+            self.option = later.option;
+        }
+    }
+}
+
+/// sends reports without waiting for the gui, merging those it has not taken
+struct Outbox {
+    snd: SyncSender<W2g>,
+    pending: Option<W2g>,
 }
 
-pub fn handle(rx: Receiver<G2w>, snd: Sender<W2g>, session: Session) {
+impl Outbox {
+    /// false if the gui is gone
+    fn push(&mut self, w2g: W2g) -> bool {
+        let w2g = match self.pending.take() {
+            None => w2g,
+            Some(mut pending) => {
+                pending.merge(w2g);
+                pending
+            }
+        };
+        match self.snd.try_send(w2g) {
+            Ok(_) => true,
+            Err(TrySendError::Full(w2g)) => {
+                self.pending = Some(w2g);
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+
+    /// wait until the gui takes pending reports, false if it is gone
+    fn flush(&mut self) -> bool {
+        match self.pending.take() {
+            None => true,
+            Some(w2g) => self.snd.send(w2g).is_ok(),
+        }
+    }
+}
+
+/// spawn a learner training `session`, joining it gives back the final session
+pub fn spawn_learner(session: Session, rx: Receiver<G2w>) -> (Receiver<W2g>, JoinHandle<Session>) {
+    let (snd, rec) = sync_channel(BOUND);
+
+    let handle = thread::spawn(move || handle(rx, snd, session));
+
+    (rec, handle)
+}
+
+pub fn handle(rx: Receiver<G2w>, snd: SyncSender<W2g>, session: Session) -> Session {
     let mut session = session;
+    let mut outbox = Outbox { snd, pending: None };
     let mut cycle: usize = 0;
+    // training steps done, chunk size may change between cycles
+    let mut trained: usize = 0;
+    // model of last cycle whose weights and cost were finite
+    let mut last_good = session.model.clone();
+    let mut monitor = Monitor::new(session.option.stop.clone());
+    let mut paused = false;
+    // cycles left to run while paused
+    let mut steps: usize = 0;
+
+    // a setup which can not train ends before the first cycle
+    if let Err(message) = session.check_option() {
+        println!("Learner>>failed : {} ", message);
+        outbox.push(W2g {
+            event: Some(LearnerEvent::Failed { cycle: 0, message }),
+            ..W2g::snapshot(0, &session.model)
+        });
+        outbox.flush();
+        return session;
+    }
 
     loop {
-        let g2w = rx.recv_timeout(Duration::from_micros(1000));
-
-        match g2w {
-            Ok(g2w) => {
-                println!("got signal from gui thread {:?}", g2w);
-                match g2w.sig {
-                    ControlSignal::Pause => loop {
-                        // TODO: control start here
-                    },
-                    ControlSignal::Stop => {
-                        // manage Stop here
-                        return;
+        let g2w = if paused && steps == 0 {
+            // time held does not count against the time budget
+            monitor.pause();
+            // nothing to do until told, gui is gone if disconnected
+            if !outbox.flush() {
+                return session;
+            }
+            match rx.recv() {
+                Err(_) => return session,
+                Ok(g2w) => Some(g2w),
+            }
+        } else {
+            rx.recv_timeout(Duration::from_micros(1000)).ok()
+        };
+
+        if let Some(g2w) = g2w {
+            println!("got signal from gui thread {:?}", g2w);
+            let report = match g2w.sig {
+                ControlSignal::Pause => {
+                    paused = true;
+                    steps = 0;
+                    // latest model to inspect while paused
+                    W2g::snapshot(trained, &session.model)
+                }
+                ControlSignal::Resume => {
+                    paused = false;
+                    steps = 0;
+                    monitor.resume();
+                    continue;
+                }
+                ControlSignal::Step(n) => {
+                    paused = true;
+                    steps += n;
+                    monitor.resume();
+                    continue;
+                }
+                ControlSignal::Update(update) => {
+                    update.apply(&mut session.option);
+                    W2g {
+                        option: Some(session.option.clone()),
+                        ..W2g::new(trained)
                     }
                 }
+                ControlSignal::Stop => return session,
+            };
+            if !outbox.push(report) {
+                return session;
             }
-            Err(_) => {}
+            continue;
         }
+        steps = steps.saturating_sub(1);
 
-        session.train();
+        // a panic (ex. dataset not fitting the model) ends training
+        // as a divergence does, without taking the learner down
+        let going_on = panic::catch_unwind(AssertUnwindSafe(|| {
+            let result = session.train();
 
-        println!("Learner>>run cycle : {} ", cycle);
-        cycle += 1;
+            println!("Learner>>run cycle : {} ", cycle);
+            cycle += 1;
 
-        let nodes = Some(Nodes::from(&session.model));
-        let cost_info = session.cost();
+            let cost_info = session.cost();
+            let diverged = match result {
+                Err(reason) => Some(reason),
+                Ok(_) => match cost_info.as_ref() {
+                    Some(cost_info) if !cost_info.loss().is_finite() => Some(Divergence::Cost),
+                    _ => None,
+                },
+            };
 
-        let snd_res = snd.send(W2g {
-            cycle: cycle * session.option.cycle,
-            cost_info,
-            nodes,
-            model: Some(session.model.clone()),
-        });
+            if let Some(reason) = diverged {
+                println!("Learner>>diverged : {} ", reason);
+                session.model = last_good.clone();
+                outbox.push(W2g {
+                    event: Some(LearnerEvent::Diverged {
+                        cycle: trained,
+                        reason,
+                    }),
+                    ..W2g::snapshot(trained, &session.model)
+                });
+                outbox.flush();
+                return false;
+            }
+            last_good = session.model.clone();
+            trained += session.option.cycle;
+
+            let validation = session.validation_cost();
+            let finished = cost_info.as_ref().and_then(|cost_info| {
+                monitor.check(
+                    trained,
+                    cost_info.avg,
+                    validation.as_ref().map(|validation| validation.avg),
+                    &session.model,
+                )
+            });
+            if let Some(best) = finished.as_ref().and_then(|_| monitor.take_best()) {
+                session.model = best;
+            }
+
+            // the last cycle is always reported
+            let report = &session.option.report;
+            let due = |every: usize| finished.is_some() || cycle.is_multiple_of(every.max(1));
+            let mut w2g = if due(report.snapshot) {
+                W2g::snapshot(trained, &session.model)
+            } else {
+                W2g::new(trained)
+            };
+            if let Some(cost_info) = cost_info.filter(|_| due(report.costs)) {
+                w2g.costs.push(CostPoint {
+                    cycle: trained,
+                    cost_info,
+                    validation,
+                    metrics: session.metrics(),
+                });
+            }
+            w2g.event = finished.clone().map(|reason| LearnerEvent::Finished {
+                cycle: trained,
+                reason,
+            });
+
+            if !outbox.push(w2g) {
+                return false;
+            }
+            if let Some(reason) = finished {
+                println!("Learner>>finished : {} ", reason);
+                outbox.flush();
+                return false;
+            }
+            true
+        }));
+
+        match going_on {
+            Ok(true) => {}
+            Ok(false) => return session,
+            Err(panic) => {
+                let message = panic_message(panic.as_ref());
+                println!("Learner>>failed : {} ", message);
+                session.model = last_good;
+                outbox.push(W2g {
+                    event: Some(LearnerEvent::Failed {
+                        cycle: trained,
+                        message,
+                    }),
+                    ..W2g::snapshot(trained, &session.model)
+                });
+                outbox.flush();
+                return session;
+            }
+        }
     }
 }
+
+/// message a panic was raised with
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown panic")
+    }
+}
+
+#[test]
+fn learner_test_pause_step_resume() {
+    use super::session::SessionOption;
+    use crate::core::nn::dataset::DataSet;
+    use std::sync::mpsc::channel;
+
+    let inputs: Vec<Vec<f64>> = (0..4).map(|idx| vec![(idx % 2) as f64]).collect();
+    let session = Session {
+        model: NN::new(&[1, 1]),
+        dataset: Some(DataSet::new(inputs.clone(), inputs)),
+        option: SessionOption {
+            cycle: 10,
+            ..Default::default()
+        },
+        split: None,
+    };
+    let (snd, rx) = channel();
+    // paused before the first cycle
+    snd.send(G2w {
+        sig: ControlSignal::Pause,
+    })
+    .unwrap();
+    let (rec, learner) = spawn_learner(session, rx);
+    let quiet = Duration::from_millis(200);
+    let paused = rec.recv().unwrap();
+    assert_eq!(paused.cycle, 0);
+    assert!(paused.model.is_some() && paused.costs.is_empty());
+    assert!(rec.recv_timeout(quiet).is_err());
+
+    snd.send(G2w {
+        sig: ControlSignal::Step(2),
+    })
+    .unwrap();
+    assert_eq!(rec.recv().unwrap().costs[0].cycle, 10);
+    assert_eq!(rec.recv().unwrap().costs[0].cycle, 20);
+    assert!(rec.recv_timeout(quiet).is_err());
+
+    // acknowledged while paused, the next cycle runs the new chunk size
+    snd.send(G2w {
+        sig: ControlSignal::Update(OptionUpdate {
+            cycle: Some(5),
+            ..Default::default()
+        }),
+    })
+    .unwrap();
+    let ack = rec.recv().unwrap();
+    assert_eq!(ack.cycle, 20);
+    assert_eq!(ack.option.unwrap().cycle, 5);
+    snd.send(G2w {
+        sig: ControlSignal::Step(1),
+    })
+    .unwrap();
+    assert_eq!(rec.recv().unwrap().cycle, 25);
+
+    snd.send(G2w {
+        sig: ControlSignal::Resume,
+    })
+    .unwrap();
+    assert_eq!(rec.recv().unwrap().cycle, 30);
+
+    snd.send(G2w {
+        sig: ControlSignal::Stop,
+    })
+    .unwrap();
+    // learner hangs up once stopped
+    while rec.recv().is_ok() {}
+    let session = learner.join().unwrap();
+    assert_eq!(session.option.cycle, 5);
+}
+
+#[test]
+fn learner_test_panic_ends_training() {
+    use super::session::SessionOption;
+    use crate::core::nn::dataset::DataSet;
+    use std::sync::mpsc::channel;
+
+    let mut model = NN::new(&[1, 1]);
+    model.rand();
+    let session = Session {
+        model: model.clone(),
+        // two inputs do not fit the model
+        dataset: Some(DataSet::new(vec![vec![0.0, 1.0]], vec![vec![1.0]])),
+        option: SessionOption::default(),
+        split: None,
+    };
+    let (_snd, rx) = channel();
+    let (rec, learner) = spawn_learner(session, rx);
+
+    let last = rec.iter().last().unwrap();
+    match last.event {
+        Some(LearnerEvent::Failed { cycle: 0, .. }) => {}
+        _ => panic!("learner did not report the failure"),
+    }
+    let session = learner.join().unwrap();
+    assert_eq!(
+        session.model.net.layers[0].params()[0].el,
+        model.net.layers[0].params()[0].el
+    );
+}
+
+#[test]
+fn learner_test_rejects_untraceable_autodiff() {
+    use super::session::{SessionOption, TrainingMethod};
+    use crate::core::nn::dataset::DataSet;
+    use crate::core::nn::layer::norm::Normalization;
+    use std::sync::mpsc::channel;
+
+    let session = Session {
+        model: NN::new(&[1, 2, 1]).normalized(Normalization::Batch),
+        dataset: Some(DataSet::new(vec![vec![0.0]], vec![vec![1.0]])),
+        option: SessionOption {
+            train_method: TrainingMethod::AutoDiff,
+            ..Default::default()
+        },
+        split: None,
+    };
+    let (_snd, rx) = channel();
+    let (rec, learner) = spawn_learner(session, rx);
+
+    match rec.iter().last().unwrap().event {
+        Some(LearnerEvent::Failed { cycle: 0, message }) => {
+            assert!(message.contains("BatchNorm(2)"))
+        }
+        _ => panic!("learner did not reject the option"),
+    }
+    learner.join().unwrap();
+}
+
+#[test]
+fn learner_test_reports_coalesce() {
+    let (snd, rec) = sync_channel(BOUND);
+    let mut outbox = Outbox { snd, pending: None };
+    let point = |cycle: usize| CostPoint {
+        cycle,
+        cost_info: CostInfo::new(),
+        validation: None,
+        metrics: None,
+    };
+    let report = |cycle: usize, snapshot: bool| {
+        let mut w2g = if snapshot {
+            W2g::snapshot(cycle, &NN::new(&[1, cycle]))
+        } else {
+            W2g::new(cycle)
+        };
+        w2g.costs.push(point(cycle));
+        w2g
+    };
+
+    // the gui takes nothing while these are sent
+    assert!(outbox.push(report(1, true)));
+    for cycle in 2..=MAX_POINTS + 10 {
+        assert!(outbox.push(report(cycle, cycle == 3)));
+    }
+    assert_eq!(rec.try_iter().count(), 1);
+    assert!(outbox.flush());
+
+    let merged = rec.recv().unwrap();
+    assert_eq!(merged.cycle, MAX_POINTS + 10);
+    assert_eq!(merged.costs.len(), MAX_POINTS);
+    assert_eq!(merged.costs.last().unwrap().cycle, MAX_POINTS + 10);
+    // latest snapshot is kept
+    assert_eq!(merged.model.unwrap().layers, vec![1, 3]);
+
+    drop(rec);
+    assert!(!outbox.push(report(0, false)));
+}
@@ -1,6 +1,10 @@
+pub mod callback;
 pub mod context;
+pub mod cross_validation;
 pub mod data;
 pub mod img;
 pub mod learner;
 pub mod nodes;
+pub mod search;
 pub mod session;
+pub mod stopping;
@@ -1,4 +1,7 @@
-use crate::core::{matrix::matrix::__Matrix, nn::nn::NN};
+use crate::core::{
+    matrix::matrix::{Matrix, __Matrix},
+    nn::{layer::LayerData, nn::NN, sequential::Merge},
+};
 
 #[derive(Debug, Clone)]
 pub enum Nodetype {
@@ -12,6 +15,8 @@ pub struct Nodes {
     pub layers: Vec<usize>,
     pub nodes: Vec<Vec<Node>>,
     pub connections: Vec<Vec<Vec<Connection>>>,
+    pub blocks: Vec<Block>,
+    pub skips: Vec<SkipEdge>,
 }
 
 #[derive(Debug, Clone)]
@@ -29,30 +34,61 @@ pub struct Connection {
     pub src_level: usize,
     pub weight: f64,
 }
+/// level drawn as a single block instead of nodes
+/// (output of a layer which is not made of neurons)
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub level: usize,
+    pub name: String,
+    pub size: usize,
+}
+
+/// skip connection from activation `src_level` into `dst_level`
+#[derive(Debug, Clone)]
+pub struct SkipEdge {
+    pub src_level: usize,
+    pub dst_level: usize,
+    pub merge: Merge,
+    /// (src node, dst node) pairs, empty if either side is a block
+    pub pairs: Vec<(usize, usize)>,
+}
 
 impl Nodes {
     pub fn from(src: &NN) -> Nodes {
         let layers = src.layers.clone();
         let mut nodes = Vec::new();
         let mut connections = Vec::new();
+        let mut blocks = Vec::new();
         for (level, layer) in layers.iter().enumerate() {
             if level == 0 {
                 let mut input_nodes = Vec::new();
-                for col in 0..*layer {
-                    input_nodes.push(Node {
-                        nodetype: Nodetype::Input,
+                if neurons(src, 1).is_some() {
+                    for col in 0..*layer {
+                        input_nodes.push(Node {
+                            nodetype: Nodetype::Input,
+                            level,
+                            bias: 0.0,
+                            value: src.apps[0].at(0, col),
+                        })
+                    }
+                } else {
+                    blocks.push(Block {
                         level,
-                        bias: 0.0,
-                        value: src.apps[0].at(0, col),
-                    })
+                        name: String::from("Input"),
+                        size: *layer,
+                    });
                 }
                 nodes.push(input_nodes);
-            } else {
+            } else if let Some((weights, biases)) = neurons(src, level) {
                 let mut mid_nodes = Vec::new();
                 let mut mid_connections = Vec::new();
+                // connections from a block are not drawn one by one
+                let connected = !nodes[level - 1].is_empty();
 
                 for node_idx in 0..*layer {
                     let mut node_connections = Vec::new();
+                    // nodes past the layer output are concatenated by skips
+                    let own = node_idx < biases.len_col();
                     mid_nodes.push({
                         Node {
                             nodetype: if level < layers.len() - 1 {
@@ -61,33 +97,85 @@ impl Nodes {
                                 Nodetype::Output
                             },
                             level,
-                            bias: src.biases[level - 1].at(0, node_idx),
+                            bias: if own { biases.at(0, node_idx) } else { 0.0 },
                             value: src.apps[level].at(0, node_idx),
                         }
                     });
 
-                    for (src_idx, w) in
-                        src.weights[level - 1].col(node_idx).iter().enumerate()
-                    {
-                        node_connections.push(Connection {
-                            dst: node_idx,
-                            dst_level: level,
-                            src: src_idx,
-                            src_level: level - 1,
-                            weight: **w,
-                        })
+                    if connected && own {
+                        for (src_idx, w) in weights.col(node_idx).iter().enumerate() {
+                            node_connections.push(Connection {
+                                dst: node_idx,
+                                dst_level: level,
+                                src: src_idx,
+                                src_level: level - 1,
+                                weight: **w,
+                            })
+                        }
                     }
                     mid_connections.push(node_connections);
                 }
                 nodes.push(mid_nodes);
                 connections.push(mid_connections);
+            } else {
+                blocks.push(Block {
+                    level,
+                    name: src.net.layers[level - 1].name(),
+                    size: *layer,
+                });
+                nodes.push(Vec::new());
+                connections.push(Vec::new());
             }
         }
+        let skips = skip_edges(src, &nodes);
         Nodes {
             layers,
             nodes,
             connections,
+            blocks,
+            skips,
+        }
+    }
+}
+
+fn skip_edges(src: &NN, nodes: &[Vec<Node>]) -> Vec<SkipEdge> {
+    let net = &src.net;
+    let mut edges = Vec::new();
+    for (idx, skip) in net.skips.iter().enumerate() {
+        let mut pairs = Vec::new();
+        if !nodes[skip.from].is_empty() && !nodes[skip.to].is_empty() {
+            let offset = match skip.merge {
+                Merge::Add => 0,
+                // concatenated after the layer output and earlier skips
+                Merge::Concat => {
+                    net.layers[skip.to - 1].output_size()
+                        + net.skips[..idx]
+                            .iter()
+                            .filter(|s| s.to == skip.to && s.merge == Merge::Concat)
+                            .map(|s| net.width(s.from))
+                            .sum::<usize>()
+                }
+            };
+            pairs = (0..net.width(skip.from)).map(|n| (n, offset + n)).collect();
         }
+        edges.push(SkipEdge {
+            src_level: skip.from,
+            dst_level: skip.to,
+            merge: skip.merge,
+            pairs,
+        });
+    }
+    edges
+}
+
+/// weights and biases of the layer ending at given level,
+/// None if the layer is not made of neurons
+fn neurons(src: &NN, level: usize) -> Option<(Matrix<f64>, Matrix<f64>)> {
+    match src.net.layers[level - 1].serialize() {
+        LayerData::Dense {
+            weights, biases, ..
+        } => Some((weights, biases)),
+        _ => None,
     }
 }
 
@@ -104,3 +192,48 @@ fn test_nodes_from() {
 
     println!("{:#?}", nodes);
 }
+
+#[test]
+fn test_nodes_from_blocks() {
+    use crate::core::nn::layer::{
+        conv::Conv2D, dense::Dense, flatten::Flatten, pool::MaxPool2D, Activation, Shape,
+    };
+    use crate::core::nn::sequential::Sequential;
+
+    let mut net = Sequential::new();
+    net.push(Box::new(Conv2D::new(
+        Shape::new(1, 6, 6),
+        2,
+        3,
+        1,
+        0,
+        Activation::Sigmoid,
+    )));
+    net.push(Box::new(MaxPool2D::new(Shape::new(2, 4, 4), 2, 2)));
+    net.push(Box::new(Flatten::new(Shape::new(2, 2, 2))));
+    net.push(Box::new(Dense::new(8, 3, Activation::Sigmoid)));
+    let nodes = Nodes::from(&NN::from_net(net));
+
+    assert_eq!(nodes.blocks.len(), 4);
+    assert_eq!(nodes.nodes[4].len(), 3);
+    assert!(nodes.connections[3].iter().all(|c| c.is_empty()));
+}
+
+#[test]
+fn test_nodes_from_skips() {
+    use crate::core::nn::layer::{dense::Dense, Activation};
+    use crate::core::nn::sequential::Sequential;
+
+    let mut net = Sequential::new();
+    net.push(Box::new(Dense::new(2, 2, Activation::Sigmoid)));
+    net.connect(0, Merge::Add);
+    net.push(Box::new(Dense::new(2, 3, Activation::Sigmoid)));
+    net.connect(0, Merge::Concat);
+    let nodes = Nodes::from(&NN::from_net(net));
+
+    assert_eq!(nodes.nodes[2].len(), 5);
+    assert_eq!(nodes.skips[0].pairs, vec![(0, 0), (1, 1)]);
+    assert_eq!(nodes.skips[1].pairs, vec![(0, 3), (1, 4)]);
+    // concatenated nodes have no incoming weights
+    assert!(nodes.connections[1][4].is_empty());
+}
@@ -0,0 +1,271 @@
+use std::{
+    sync::{mpsc::channel, Arc, Mutex},
+    thread,
+};
+
+use rand::seq::SliceRandom;
+
+use crate::core::nn::{
+    dataset::{DataSet, SplitMethod, SplitRatio},
+    nn::NN,
+    regularization::Regularization,
+};
+
+use super::{
+    session::{Divergence, Session, SessionOption, TrainingMethod},
+    stopping::StopReason,
+};
+
+/// values tried for each setting, every combination is a candidate
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchSpace {
+    /// dense layouts, input first
+    pub layouts: Vec<Vec<usize>>,
+    pub train_methods: Vec<TrainingMethod>,
+    /// step scales of BackProp and AutoDiff
+    /// (finite difference methods carry their own rate)
+    pub rates: Vec<f64>,
+    pub cycles: Vec<usize>,
+    pub regularizations: Vec<Regularization>,
+}
+
+impl SearchSpace {
+    /// finite difference methods of every rate and eps
+    pub fn finite_diff(rates: &[f64], eps: &[f64]) -> Vec<TrainingMethod> {
+        rates
+            .iter()
+            .flat_map(|rate| {
+                eps.iter().map(|eps| TrainingMethod::FiniteDiff {
+                    rate: *rate,
+                    eps: *eps,
+                })
+            })
+            .collect()
+    }
+
+    /// every combination of values
+    pub fn grid(&self, base: &SessionOption) -> Vec<Candidate> {
+        let mut candidates = Vec::new();
+        for layout in self.layouts.iter() {
+            for train_method in self.train_methods.iter() {
+                let rates = match train_method {
+                    TrainingMethod::FiniteDiff { rate, .. } => vec![*rate],
+                    _ => self.rates.clone(),
+                };
+                for rate in rates {
+                    for cycle in self.cycles.iter() {
+                        for regularization in self.regularizations.iter() {
+                            candidates.push(Candidate {
+                                layout: layout.clone(),
+                                option: SessionOption {
+                                    train_method: train_method.clone(),
+                                    rate,
+                                    cycle: *cycle,
+                                    regularization: regularization.clone(),
+                                    ..base.clone()
+                                },
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        candidates
+    }
+}
+
+/// how candidates are drawn from the space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    Grid,
+    /// `samples` distinct combinations drawn at random
+    Random { samples: usize },
+}
+
+/// model layout and options of a single trial
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub layout: Vec<usize>,
+    pub option: SessionOption,
+}
+
+/// trained candidate
+#[derive(Debug, Clone)]
+pub struct Trial {
+    pub candidate: Candidate,
+    /// training steps done
+    pub cycle: usize,
+    pub train_cost: f64,
+    pub validation_cost: f64,
+    pub stopped: Option<StopReason>,
+    pub diverged: Option<Divergence>,
+    /// trained session, ready to be saved
+    pub session: Session,
+}
+
+/// hyperparameter search setup
+#[derive(Debug, Clone, PartialEq)]
+pub struct Search {
+    pub space: SearchSpace,
+    pub strategy: Strategy,
+    /// training steps of each candidate at most
+    pub budget: usize,
+    /// worker threads
+    pub threads: usize,
+}
+
+impl Search {
+    /// train every candidate on the training samples of `base` and rank
+    /// them by validation cost, best first (diverged ones last).
+    /// options not searched are taken from `base`. the dataset is split
+    /// with a random 20% validation if `base` is not split
+    pub fn run(&self, base: &Session) -> Result<Vec<Trial>, String> {
+        let dataset = match base.dataset.as_ref() {
+            None => return Err(String::from("Search>>run: Dataset is None")),
+            Some(dataset) => dataset,
+        };
+        let mut template = Session {
+            model: NN::new(&[1, 1]),
+            dataset: Some(dataset.clone()),
+            option: base.option.clone(),
+            split: base.split.clone(),
+        };
+        if template.validation_set().is_none() {
+            template.split_dataset(Some(SplitRatio {
+                validation: 0.2,
+                test: 0.0,
+                method: SplitMethod::Random,
+            }));
+        }
+        if template.validation_set().is_none() {
+            return Err(String::from("Search>>run: too few samples to validate"));
+        }
+
+        let mut candidates = self.space.grid(&base.option);
+        if let Strategy::Random { samples } = self.strategy {
+            candidates.shuffle(&mut rand::thread_rng());
+            candidates.truncate(samples);
+        }
+        if let Some(bad) = candidates.iter().find(|c| !fits(&c.layout, dataset)) {
+            return Err(format!(
+                "Search>>run: layout {:?} does not fit the dataset",
+                bad.layout
+            ));
+        }
+
+        let queue = Arc::new(Mutex::new(candidates));
+        let (snd, rec) = channel();
+        let workers: Vec<_> = (0..self.threads.max(1))
+            .map(|_| {
+                let queue = Arc::clone(&queue);
+                let snd = snd.clone();
+                let template = template.clone();
+                let budget = self.budget;
+                thread::spawn(move || loop {
+                    let candidate = match queue.lock().unwrap().pop() {
+                        None => return,
+                        Some(candidate) => candidate,
+                    };
+                    let _ = snd.send(trial(&template, candidate, budget));
+                })
+            })
+            .collect();
+        drop(snd);
+
+        let mut trials: Vec<Trial> = rec.iter().collect();
+        for worker in workers {
+            if worker.join().is_err() {
+                return Err(String::from("Search>>run: a worker panicked"));
+            }
+        }
+        trials.sort_by(|a, b| rank(a).total_cmp(&rank(b)));
+        Ok(trials)
+    }
+}
+
+fn fits(layout: &[usize], dataset: &DataSet<f64>) -> bool {
+    layout.len() >= 2
+        && layout.first() == dataset.inputs.first().map(|row| row.len()).as_ref()
+        && layout.last() == dataset.outputs.first().map(|row| row.len()).as_ref()
+}
+
+/// validation cost, infinite if the trial did not end well
+fn rank(trial: &Trial) -> f64 {
+    if trial.diverged.is_some() || !trial.validation_cost.is_finite() {
+        f64::INFINITY
+    } else {
+        trial.validation_cost
+    }
+}
+
+fn trial(template: &Session, candidate: Candidate, budget: usize) -> Trial {
+    let mut model = NN::new(&candidate.layout);
+    model.rand();
+    let mut session = Session {
+        model,
+        option: candidate.option.clone(),
+        ..template.clone()
+    };
+    let end = session.fit(budget, &mut []);
+    Trial {
+        candidate,
+        cycle: end.cycle,
+        train_cost: session.cost().unwrap().avg,
+        validation_cost: session.validation_cost().unwrap().avg,
+        stopped: end.stopped,
+        diverged: end.diverged,
+        session,
+    }
+}
+
+#[test]
+fn search_test_ranks_by_validation_cost() {
+    let inputs: Vec<Vec<f64>> = (0..10).map(|idx| vec![(idx % 2) as f64]).collect();
+    let base = Session {
+        model: NN::new(&[1, 1]),
+        dataset: Some(DataSet::new(inputs.clone(), inputs)),
+        option: SessionOption::default(),
+        split: None,
+    };
+    let search = Search {
+        space: SearchSpace {
+            layouts: vec![vec![1, 1], vec![1, 2, 1]],
+            train_methods: [
+                vec![TrainingMethod::BackProp],
+                SearchSpace::finite_diff(&[1e-9], &[1e-3]),
+            ]
+            .concat(),
+            rates: vec![0.0, 1.0],
+            cycles: vec![50],
+            regularizations: vec![Regularization::default()],
+        },
+        strategy: Strategy::Grid,
+        budget: 300,
+        threads: 3,
+    };
+    let trials = search.run(&base).unwrap();
+
+    assert_eq!(trials.len(), 6);
+    assert!(trials
+        .windows(2)
+        .all(|pair| pair[0].validation_cost <= pair[1].validation_cost));
+    // a tiny finite difference rate barely learns, a zero rate does not
+    assert_eq!(trials[0].candidate.option.train_method, TrainingMethod::BackProp);
+    assert_eq!(trials[0].candidate.option.rate, 1.0);
+    assert!(trials[0].session.split.is_some());
+
+    let sampled = Search {
+        strategy: Strategy::Random { samples: 2 },
+        ..search.clone()
+    };
+    assert_eq!(sampled.run(&base).unwrap().len(), 2);
+
+    let wrong = Search {
+        space: SearchSpace {
+            layouts: vec![vec![2, 1]],
+            ..search.space.clone()
+        },
+        ..search
+    };
+    assert!(wrong.run(&base).is_err());
+}
@@ -4,31 +4,155 @@ use std::io::Read;
 use std::io::Write;
 use std::path::Path;
 
+use crate::core::autodiff::tape::Tape;
+use crate::core::matrix::matrix::{Matrix, __Matrix};
+use crate::core::nn::clipping::GradientClip;
 use crate::core::nn::cost::CostInfo;
-use crate::core::nn::{dataset::DataSet, nn::NN};
+use crate::core::nn::metrics::Metrics;
+use crate::core::nn::regularization::Regularization;
+use crate::core::nn::{
+    dataset::{DataSet, Split, SplitRatio},
+    nn::NN,
+};
 use serde::Deserialize;
 use serde::Serialize;
 
+use super::callback::{Callback, Flow};
 use super::data::Buildable;
 use super::data::Readable;
 use super::data::Savable;
 use super::data::Stringfiable;
+use super::learner::Reporting;
+use super::stopping::{Monitor, RunEnd, StopCondition, StopReason};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum TrainingMethod {
     FiniteDiff { rate: f64, eps: f64 },
     BackProp,
+    AutoDiff,
 }
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum PostX {
     Sigmoid,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct SessionOption {
     pub train_method: TrainingMethod,
     pub post_x: PostX,
     pub cycle: usize,
+    /// scale of BackProp and AutoDiff steps (FiniteDiff has its own rate)
+    #[serde(default = "default_rate")]
+    pub rate: f64,
+    /// samples drawn at random for each training step,
+    /// the whole training set if None
+    #[serde(default)]
+    pub batch: Option<usize>,
+    /// threads sharing each BackProp step, samples are split between them
+    #[serde(default = "default_threads")]
+    pub threads: usize,
+    /// L1/L2 penalty (weight decay)
+    #[serde(default)]
+    pub regularization: Regularization,
+    /// clipping of gradients before learning
+    #[serde(default)]
+    pub clip: GradientClip,
+    /// conditions ending training by itself
+    #[serde(default)]
+    pub stop: StopCondition,
+    /// how often a learner reports to the gui
+    #[serde(default)]
+    pub report: Reporting,
+}
+
+/// reason training can not go on without breaking the model
+#[derive(Debug, Clone, PartialEq)]
+pub enum Divergence {
+    /// gradients were NaN or infinite (and not learned)
+    Gradient,
+    /// weights became NaN or infinite by learning
+    Weights,
+    /// cost of the model was NaN or infinite
+    Cost,
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Divergence::Gradient => write!(f, "non-finite gradient"),
+            Divergence::Weights => write!(f, "non-finite weights"),
+            Divergence::Cost => write!(f, "non-finite cost"),
+        }
+    }
+}
+
+impl Default for SessionOption {
+    fn default() -> Self {
+        SessionOption {
+            train_method: TrainingMethod::BackProp,
+            post_x: PostX::Sigmoid,
+            cycle: 1000,
+            rate: default_rate(),
+            batch: None,
+            threads: default_threads(),
+            regularization: Regularization::default(),
+            clip: GradientClip::default(),
+            stop: StopCondition::default(),
+            report: Reporting::default(),
+        }
+    }
+}
+
+impl SessionOption {
+    /// scale of steps of the training method in use
+    pub fn learning_rate(&self) -> f64 {
+        match self.train_method {
+            TrainingMethod::FiniteDiff { rate, .. } => rate,
+            _ => self.rate,
+        }
+    }
+}
+
+fn default_rate() -> f64 {
+    1.0
+}
+
+fn default_threads() -> usize {
+    1
+}
+
+/// option fields to change on a running learner, None keeps the current value.
+/// `rate` sets the step scale of any training method
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OptionUpdate {
+    pub rate: Option<f64>,
+    pub batch: Option<Option<usize>>,
+    pub cycle: Option<usize>,
+    pub regularization: Option<Regularization>,
+    pub report: Option<Reporting>,
+}
+
+impl OptionUpdate {
+    pub fn apply(&self, option: &mut SessionOption) {
+        if let Some(rate) = self.rate {
+            option.rate = rate;
+            if let TrainingMethod::FiniteDiff { rate: own, .. } = &mut option.train_method {
+                *own = rate;
+            }
+        }
+        if let Some(batch) = self.batch {
+            option.batch = batch.map(|batch| batch.max(1));
+        }
+        if let Some(cycle) = self.cycle {
+            option.cycle = cycle.max(1);
+        }
+        if let Some(regularization) = self.regularization.as_ref() {
+            option.regularization = regularization.clone();
+        }
+        if let Some(report) = self.report.as_ref() {
+            option.report = report.clone();
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,47 +163,177 @@ pub struct Session {
     pub dataset: Option<DataSet<f64>>,
     /// option of session
     pub option: SessionOption,
+    /// split of the dataset, the whole dataset is trained if None
+    #[serde(default)]
+    pub split: Option<Split>,
 }
 
 impl Session {
-    /// train model with current setup and dataset
-    pub fn train_single(&mut self) {
-        match self.dataset.clone() {
+    /// whether the training method can train the model,
+    /// checked before training starts
+    pub fn check_option(&self) -> Result<(), String> {
+        if self.option.train_method == TrainingMethod::AutoDiff {
+            let mut tape = Tape::new();
+            let input = tape.var(Matrix::new(1, self.model.layers[0]));
+            if let Err(name) = self.model.net.trace(&mut tape, input) {
+                return Err(format!(
+                    "Session>>check_option: {} can not be traced by AutoDiff",
+                    name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// train model with current setup and dataset.
+    /// non-finite gradients are not learned.
+    /// panics if check_option fails
+    pub fn train_single(&mut self) -> Result<(), Divergence> {
+        match self.train_set() {
             None => {
-                println!("Session>>train_single: Dataset is None")
+                println!("Session>>train_single: Dataset is None");
+                Ok(())
             }
             Some(ds) => {
+                let ds = match self.option.batch {
+                    Some(batch) if batch > 0 && batch < ds.len() => {
+                        let mut rng = rand::thread_rng();
+                        ds.subset(&rand::seq::index::sample(&mut rng, ds.len(), batch).into_vec())
+                    }
+                    _ => ds,
+                };
                 let inputs = &ds.inputs;
                 let expects = &ds.outputs;
 
-                let delta = match self.option.train_method {
+                self.model.regularization = self.option.regularization.clone();
+                let mut delta = match self.option.train_method {
                     TrainingMethod::FiniteDiff { rate, eps } => {
                         let mut delta = self.model.finite_diff(&inputs, &expects, &eps);
                         delta.mul(&rate);
                         delta
                     }
-                    TrainingMethod::BackProp => self.model.backprop(&inputs, &expects),
+                    TrainingMethod::BackProp => {
+                        self.model
+                            .backprop_parallel(inputs, expects, self.option.threads)
+                    }
+                    TrainingMethod::AutoDiff => {
+                        self.model.autodiff(inputs, expects).unwrap_or_else(|name| {
+                            panic!("Session>>train_single: {} can not be traced by AutoDiff", name)
+                        })
+                    }
                 };
+                if !matches!(self.option.train_method, TrainingMethod::FiniteDiff { .. }) {
+                    delta.mul(&self.option.rate);
+                }
+
+                self.option.clip.apply(&mut delta);
+                if !delta.is_finite() {
+                    return Err(Divergence::Gradient);
+                }
 
-                self.model.learn(&delta)
+                self.model.learn(&delta);
+                if !self.model.is_finite() {
+                    return Err(Divergence::Weights);
+                }
+                Ok(())
             }
         }
     }
-    /// train model n times
-    pub fn train_ntimes(&mut self, n: usize) {
+    /// train model n times, stops at first divergence
+    pub fn train_ntimes(&mut self, n: usize) -> Result<(), Divergence> {
         for _ in 0..n {
-            self.train_single();
+            self.train_single()?;
         }
+        Ok(())
     }
-    /// train model self.cycle times
-    pub fn train(&mut self) {
-        for _ in 0..self.option.cycle {
-            self.train_single();
+    /// train model self.cycle times, stops at first divergence
+    pub fn train(&mut self) -> Result<(), Divergence> {
+        self.train_ntimes(self.option.cycle)
+    }
+
+    /// train in chunks of the cycle option, for `max_cycles` steps at most,
+    /// until a stop condition of the option is met, a callback stops it
+    /// or it diverges. stop conditions watch validation cost if the dataset is split
+    pub fn fit(&mut self, max_cycles: usize, callbacks: &mut [&mut dyn Callback]) -> RunEnd {
+        let mut monitor = Monitor::new(self.option.stop.clone());
+        let chunk = self.option.cycle.max(1);
+        let per_epoch = self.steps_per_epoch();
+        let mut end = RunEnd {
+            cycle: 0,
+            stopped: None,
+            diverged: None,
+        };
+        for callback in callbacks.iter_mut() {
+            callback.on_train_begin(self);
+        }
+
+        'training: while end.cycle < max_cycles {
+            for _ in 0..chunk.min(max_cycles - end.cycle) {
+                if let Err(reason) = self.train_single() {
+                    end.diverged = Some(reason);
+                    break 'training;
+                }
+                end.cycle += 1;
+                if end.cycle.is_multiple_of(per_epoch) {
+                    let epoch = end.cycle / per_epoch;
+                    // every callback is told, even after one asked to stop
+                    let flows: Vec<Flow> = callbacks
+                        .iter_mut()
+                        .map(|callback| callback.on_epoch_end(epoch, self))
+                        .collect();
+                    if flows.contains(&Flow::Stop) {
+                        end.stopped = Some(StopReason::Callback);
+                        break 'training;
+                    }
+                }
+            }
+
+            let cost = match self.cost() {
+                None => break,
+                Some(cost) => cost,
+            };
+            let validation = self.validation_cost();
+            let flows: Vec<Flow> = callbacks
+                .iter_mut()
+                .map(|callback| callback.on_cycle_end(end.cycle, &cost, validation.as_ref(), self))
+                .collect();
+            if flows.contains(&Flow::Stop) {
+                end.stopped = Some(StopReason::Callback);
+                break;
+            }
+
+            let watched = validation.as_ref().map(|cost| cost.avg);
+            if let Some(reason) = monitor.check(end.cycle, cost.avg, watched, &self.model) {
+                if let Some(best) = monitor.take_best() {
+                    self.model = best;
+                }
+                end.stopped = Some(reason);
+                break;
+            }
+        }
+
+        for callback in callbacks.iter_mut() {
+            callback.on_train_end(self, &end);
         }
+        end
     }
 
+    /// training steps of a pass over the training samples
+    fn steps_per_epoch(&self) -> usize {
+        let samples = match (self.split.as_ref(), self.dataset.as_ref()) {
+            (Some(split), _) => split.train.len(),
+            (None, Some(ds)) => ds.len(),
+            (None, None) => 0,
+        };
+        match self.option.batch {
+            Some(batch) if batch > 0 && batch < samples => samples.div_ceil(batch),
+            _ => 1,
+        }
+    }
+
+    /// cost on the training samples
     pub fn cost(&mut self) -> Option<CostInfo> {
-        match self.dataset.clone() {
+        match self.train_set() {
             None => {
                 println!("Session>>train_single: Dataset is None");
                 return None;
@@ -87,10 +341,77 @@ impl Session {
             Some(ds) => {
                 let inputs = &ds.inputs;
                 let expects = &ds.outputs;
+                self.model.regularization = self.option.regularization.clone();
                 return Some(self.model.cost_info(inputs, expects));
             }
         }
     }
+
+    /// cost on the validation samples, None unless split with some
+    pub fn validation_cost(&mut self) -> Option<CostInfo> {
+        let ds = self.validation_set()?;
+        self.model.regularization = self.option.regularization.clone();
+        Some(self.model.cost_info(&ds.inputs, &ds.outputs))
+    }
+
+    /// evaluate model on the validation samples,
+    /// or on the training samples if there are none
+    pub fn metrics(&self) -> Option<Metrics> {
+        match self.validation_set().or_else(|| self.train_set()) {
+            None => {
+                println!("Session>>metrics: Dataset is None");
+                None
+            }
+            Some(ds) => Some(Metrics::evaluate(
+                &self.model.predict_batch(&ds.inputs),
+                &ds.outputs,
+            )),
+        }
+    }
+
+    /// replace the dataset, drawing the split again with the same ratio
+    pub fn set_dataset(&mut self, dataset: DataSet<f64>) {
+        self.dataset = Some(dataset);
+        let ratio = self.split.take().map(|split| split.ratio);
+        self.split_dataset(ratio);
+    }
+
+    /// draw a split of the dataset by `ratio`, or train on all of it if None
+    pub fn split_dataset(&mut self, ratio: Option<SplitRatio>) {
+        self.split = match (self.dataset.as_ref(), ratio) {
+            (Some(ds), Some(ratio)) => Some(ds.split(&ratio)),
+            (None, Some(_)) => {
+                println!("Session>>split_dataset: Dataset is None");
+                None
+            }
+            _ => None,
+        };
+    }
+
+    /// samples trained on, the whole dataset unless split
+    pub fn train_set(&self) -> Option<DataSet<f64>> {
+        let ds = self.dataset.as_ref()?;
+        match self.split.as_ref() {
+            None => Some(ds.clone()),
+            Some(split) => Some(ds.subset(&split.train)),
+        }
+    }
+
+    pub fn validation_set(&self) -> Option<DataSet<f64>> {
+        self.held_out(|split| &split.validation)
+    }
+
+    pub fn test_set(&self) -> Option<DataSet<f64>> {
+        self.held_out(|split| &split.test)
+    }
+
+    fn held_out(&self, indices: fn(&Split) -> &Vec<usize>) -> Option<DataSet<f64>> {
+        let indices = indices(self.split.as_ref()?);
+        if indices.is_empty() {
+            return None;
+        }
+        Some(self.dataset.as_ref()?.subset(indices))
+    }
 }
 
 impl Stringfiable for Session {
@@ -156,3 +477,158 @@ impl Readable for Session {
         ss
     }
 }
+
+#[test]
+fn session_test_divergence_keeps_weights() {
+    let mut model = NN::new(&[1, 1]);
+    model.rand();
+    let saved = model.clone();
+    let mut session = Session {
+        model,
+        dataset: Some(DataSet::new(vec![vec![f64::NAN]], vec![vec![1.0]])),
+        option: SessionOption {
+            clip: GradientClip {
+                value: Some(1.0),
+                norm: Some(1.0),
+            },
+            ..Default::default()
+        },
+        split: None,
+    };
+
+    assert_eq!(session.train(), Err(Divergence::Gradient));
+    assert!(session.model.is_finite());
+    assert_eq!(
+        session.model.net.layers[0].params()[0].el,
+        saved.net.layers[0].params()[0].el
+    );
+}
+
+#[test]
+fn session_test_split_costs() {
+    use crate::core::nn::dataset::SplitMethod;
+
+    let inputs: Vec<Vec<f64>> = (0..8).map(|idx| vec![idx as f64 / 8.0]).collect();
+    let outputs: Vec<Vec<f64>> = (0..8).map(|idx| vec![(idx % 2) as f64]).collect();
+    let mut session = Session {
+        model: NN::new(&[1, 1]),
+        dataset: None,
+        option: SessionOption::default(),
+        split: None,
+    };
+    session.set_dataset(DataSet::new(inputs.clone(), outputs.clone()));
+    assert_eq!(session.cost().unwrap().costs.len(), 8);
+    assert!(session.validation_cost().is_none());
+
+    session.split_dataset(Some(SplitRatio {
+        validation: 0.25,
+        test: 0.0,
+        method: SplitMethod::Stratified,
+    }));
+    assert_eq!(session.cost().unwrap().costs.len(), 6);
+    assert_eq!(session.validation_cost().unwrap().costs.len(), 2);
+    assert!(session.test_set().is_none());
+
+    // a new dataset is split by the same ratio (a sample of each class)
+    session.set_dataset(DataSet::new(inputs[..4].to_vec(), outputs[..4].to_vec()));
+    assert_eq!(session.cost().unwrap().costs.len(), 2);
+    assert_eq!(session.validation_cost().unwrap().costs.len(), 2);
+}
+
+#[test]
+fn session_test_rate_and_batch() {
+    let inputs: Vec<Vec<f64>> = (0..8).map(|idx| vec![(idx % 2) as f64]).collect();
+    let mut model = NN::new(&[1, 1]);
+    model.rand();
+    let mut session = Session {
+        model: model.clone(),
+        dataset: Some(DataSet::new(inputs.clone(), inputs)),
+        option: SessionOption {
+            rate: 0.0,
+            ..Default::default()
+        },
+        split: None,
+    };
+
+    // a zero rate does not move the model
+    session.train_ntimes(3).unwrap();
+    assert_eq!(
+        session.model.net.layers[0].params()[0].el,
+        model.net.layers[0].params()[0].el
+    );
+
+    OptionUpdate {
+        rate: Some(1.0),
+        batch: Some(Some(2)),
+        ..Default::default()
+    }
+    .apply(&mut session.option);
+    assert_eq!(session.option.batch, Some(2));
+    assert_eq!(session.option.cycle, 1000);
+    let before = session.cost().unwrap().avg;
+    session.train_ntimes(200).unwrap();
+    assert!(session.cost().unwrap().avg < before);
+
+    // an empty batch is never sampled
+    session.option.batch = Some(0);
+    session.train_ntimes(1).unwrap();
+    OptionUpdate {
+        batch: Some(Some(0)),
+        ..Default::default()
+    }
+    .apply(&mut session.option);
+    assert_eq!(session.option.batch, Some(1));
+}
+
+#[test]
+fn session_test_update_finite_diff_rate() {
+    let mut option = SessionOption {
+        train_method: TrainingMethod::FiniteDiff {
+            rate: 1e-3,
+            eps: 1e-4,
+        },
+        ..Default::default()
+    };
+    assert_eq!(option.learning_rate(), 1e-3);
+
+    OptionUpdate {
+        rate: Some(0.5),
+        ..Default::default()
+    }
+    .apply(&mut option);
+    assert_eq!(option.learning_rate(), 0.5);
+    assert_eq!(
+        option.train_method,
+        TrainingMethod::FiniteDiff {
+            rate: 0.5,
+            eps: 1e-4
+        }
+    );
+}
+
+#[test]
+fn session_test_check_option() {
+    use crate::core::nn::layer::norm::Normalization;
+
+    let inputs: Vec<Vec<f64>> = (0..4).map(|idx| vec![(idx % 2) as f64]).collect();
+    let mut session = Session {
+        model: NN::new(&[1, 2, 1]).normalized(Normalization::Batch),
+        dataset: Some(DataSet::new(inputs.clone(), inputs)),
+        option: SessionOption {
+            train_method: TrainingMethod::AutoDiff,
+            ..Default::default()
+        },
+        split: None,
+    };
+
+    assert_eq!(
+        session.check_option(),
+        Err(String::from(
+            "Session>>check_option: BatchNorm(2) can not be traced by AutoDiff"
+        ))
+    );
+
+    session.option.train_method = TrainingMethod::BackProp;
+    assert_eq!(session.check_option(), Ok(()));
+    session.train_single().unwrap();
+}
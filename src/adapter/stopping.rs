@@ -0,0 +1,247 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::nn::nn::NN;
+
+use super::session::Divergence;
+
+/// stop when the watched loss has not improved for a while.
+/// validation loss is watched, or training loss if there is no validation split
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EarlyStopping {
+    /// cycles without improvement before stopping
+    pub patience: usize,
+    /// smallest decrease counted as improvement
+    pub min_delta: f64,
+    /// put back the model of the best cycle when stopping
+    pub restore_best: bool,
+}
+
+impl Default for EarlyStopping {
+    fn default() -> Self {
+        EarlyStopping {
+            patience: 10000,
+            min_delta: 0.0,
+            restore_best: true,
+        }
+    }
+}
+
+/// conditions ending training, training goes on until stopped if all are None.
+/// cycles are counted in training steps, as reported by the learner
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct StopCondition {
+    /// stop once the avg training cost is at or below
+    pub target_cost: Option<f64>,
+    pub max_cycles: Option<usize>,
+    /// wall-clock seconds
+    pub time_budget: Option<f64>,
+    pub early_stopping: Option<EarlyStopping>,
+}
+
+/// why training ended by itself
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    TargetCost,
+    MaxCycles,
+    TimeBudget,
+    /// a callback asked to stop
+    Callback,
+    /// no improvement since `best_cycle`
+    EarlyStopping {
+        best_cycle: usize,
+        restored: bool,
+    },
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::TargetCost => write!(f, "target cost reached"),
+            StopReason::MaxCycles => write!(f, "max cycles reached"),
+            StopReason::TimeBudget => write!(f, "time budget spent"),
+            StopReason::Callback => write!(f, "stopped by callback"),
+            StopReason::EarlyStopping {
+                best_cycle,
+                restored: true,
+            } => write!(f, "no improvement, restored model of cycle {}", best_cycle),
+            StopReason::EarlyStopping { best_cycle, .. } => {
+                write!(f, "no improvement since cycle {}", best_cycle)
+            }
+        }
+    }
+}
+
+/// checks stop conditions along a training run
+pub struct Monitor {
+    condition: StopCondition,
+    /// training time until the clock was last paused
+    spent: Duration,
+    /// when the clock was last started, None while paused
+    started: Option<Instant>,
+    /// cycle and watched loss of the best cycle so far
+    best: Option<(usize, f64)>,
+    /// model of the best cycle, kept only to be restored
+    best_model: Option<NN>,
+}
+
+impl Monitor {
+    pub fn new(condition: StopCondition) -> Self {
+        Monitor {
+            condition,
+            spent: Duration::ZERO,
+            started: Some(Instant::now()),
+            best: None,
+            best_model: None,
+        }
+    }
+
+    /// record the costs of the model after `cycle`,
+    /// returns why training should stop, if so
+    pub fn check(
+        &mut self,
+        cycle: usize,
+        cost: f64,
+        validation: Option<f64>,
+        model: &NN,
+    ) -> Option<StopReason> {
+        if let Some(target) = self.condition.target_cost {
+            if cost <= target {
+                return Some(StopReason::TargetCost);
+            }
+        }
+
+        if let Some(early) = self.condition.early_stopping.as_ref() {
+            let watched = validation.unwrap_or(cost);
+            match self.best {
+                Some((_, best)) if watched >= best - early.min_delta => {}
+                _ => {
+                    self.best = Some((cycle, watched));
+                    if early.restore_best {
+                        self.best_model = Some(model.clone());
+                    }
+                }
+            }
+            let (best_cycle, _) = self.best.unwrap();
+            if cycle - best_cycle >= early.patience {
+                return Some(StopReason::EarlyStopping {
+                    best_cycle,
+                    restored: early.restore_best,
+                });
+            }
+        }
+
+        if let Some(max_cycles) = self.condition.max_cycles {
+            if cycle >= max_cycles {
+                return Some(StopReason::MaxCycles);
+            }
+        }
+
+        if let Some(budget) = self.condition.time_budget {
+            if self.elapsed().as_secs_f64() >= budget {
+                return Some(StopReason::TimeBudget);
+            }
+        }
+        None
+    }
+
+    /// stop the clock of the time budget (while training is held)
+    pub fn pause(&mut self) {
+        if let Some(started) = self.started.take() {
+            self.spent += started.elapsed();
+        }
+    }
+
+    /// start the clock again, if paused
+    pub fn resume(&mut self) {
+        self.started.get_or_insert_with(Instant::now);
+    }
+
+    /// training time counted against the time budget
+    fn elapsed(&self) -> Duration {
+        self.spent + self.started.map_or(Duration::ZERO, |started| started.elapsed())
+    }
+
+    /// model of the best cycle, if early stopping restores it
+    pub fn take_best(&mut self) -> Option<NN> {
+        self.best_model.take()
+    }
+}
+
+/// how a training run with a budget ended
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunEnd {
+    /// training steps done
+    pub cycle: usize,
+    /// stop condition which ended the run before its budget
+    pub stopped: Option<StopReason>,
+    pub diverged: Option<Divergence>,
+}
+
+#[test]
+fn stopping_test_early_stopping_restores_best() {
+    let mut monitor = Monitor::new(StopCondition {
+        max_cycles: Some(100),
+        early_stopping: Some(EarlyStopping {
+            patience: 20,
+            min_delta: 0.0,
+            restore_best: true,
+        }),
+        ..Default::default()
+    });
+    let mut model = NN::new(&[1, 1]);
+
+    assert_eq!(monitor.check(10, 0.5, Some(0.4), &model), None);
+    model.rand();
+    let best = model.clone();
+    assert_eq!(monitor.check(20, 0.4, Some(0.3), &model), None);
+    model.rand();
+    // training cost still falls, validation does not
+    assert_eq!(monitor.check(30, 0.3, Some(0.35), &model), None);
+    assert_eq!(
+        monitor.check(40, 0.2, Some(0.3), &model),
+        Some(StopReason::EarlyStopping {
+            best_cycle: 20,
+            restored: true
+        })
+    );
+    assert_eq!(
+        monitor.take_best().unwrap().net.layers[0].params()[0].el,
+        best.net.layers[0].params()[0].el
+    );
+
+    let mut monitor = Monitor::new(StopCondition {
+        target_cost: Some(0.1),
+        max_cycles: Some(100),
+        ..Default::default()
+    });
+    assert_eq!(monitor.check(50, 0.2, None, &model), None);
+    assert_eq!(
+        monitor.check(100, 0.2, None, &model),
+        Some(StopReason::MaxCycles)
+    );
+    assert_eq!(
+        monitor.check(110, 0.1, None, &model),
+        Some(StopReason::TargetCost)
+    );
+}
+
+#[test]
+fn stopping_test_time_budget_skips_pauses() {
+    let model = NN::new(&[1, 1]);
+    let mut monitor = Monitor::new(StopCondition {
+        time_budget: Some(0.2),
+        ..Default::default()
+    });
+    monitor.pause();
+    std::thread::sleep(Duration::from_millis(300));
+    monitor.resume();
+    assert_eq!(monitor.check(1, 1.0, None, &model), None);
+
+    std::thread::sleep(Duration::from_millis(250));
+    assert_eq!(
+        monitor.check(2, 1.0, None, &model),
+        Some(StopReason::TimeBudget)
+    );
+}
@@ -0,0 +1,409 @@
+use crate::core::common::sigmoid;
+use crate::core::matrix::matrix::{Matrix, __Matrix};
+
+/// handle of a value recorded on a tape
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Var {
+    pub idx: usize,
+}
+
+/// operation which produced a recorded value
+#[derive(Debug, Clone)]
+enum Op {
+    Leaf,
+    Add(Var, Var),
+    Sub(Var, Var),
+    Mul(Var, Var),
+    MatMul(Var, Var),
+    /// add a (1 x n) row to every row of a (m x n) matrix
+    AddRow(Var, Var),
+    Scale(Var, f64),
+    /// columns of b appended to columns of a
+    Concat(Var, Var),
+    Sigmoid(Var),
+    Tanh(Var),
+    Relu(Var),
+    Square(Var),
+    Sum(Var),
+    Mean(Var),
+}
+
+#[derive(Debug)]
+struct Record {
+    value: Matrix<f64>,
+    op: Op,
+}
+
+/// Wengert list of a computation graph over matrices.
+///
+/// every operation evaluates eagerly and records how its value was made,
+/// so `backward` can walk the list in reverse and accumulate gradients
+/// for any recorded value (reverse-mode automatic differentiation).
+#[derive(Debug)]
+pub struct Tape {
+    records: Vec<Record>,
+}
+
+/// gradients of a value with respect to every value on the tape
+#[derive(Debug)]
+pub struct Grads {
+    grads: Vec<Option<Matrix<f64>>>,
+}
+
+impl Grads {
+    /// gradient of given var (None if it does not affect the output)
+    pub fn get(&self, var: Var) -> Option<&Matrix<f64>> {
+        self.grads[var.idx].as_ref()
+    }
+}
+
+impl Default for Tape {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tape {
+    pub fn new() -> Self {
+        Tape {
+            records: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    fn push(&mut self, value: Matrix<f64>, op: Op) -> Var {
+        self.records.push(Record { value, op });
+        Var {
+            idx: self.records.len() - 1,
+        }
+    }
+
+    /// record an input (or parameter) value
+    pub fn var(&mut self, value: Matrix<f64>) -> Var {
+        self.push(value, Op::Leaf)
+    }
+
+    pub fn value(&self, var: Var) -> &Matrix<f64> {
+        &self.records[var.idx].value
+    }
+
+    fn map(&self, var: Var, f: impl Fn(f64) -> f64) -> Matrix<f64> {
+        let mut value = self.value(var).clone();
+        for row in value.el.iter_mut() {
+            for col in row.iter_mut() {
+                *col = f(*col);
+            }
+        }
+        value
+    }
+
+    pub fn add(&mut self, a: Var, b: Var) -> Var {
+        let mut value = self.value(a).clone();
+        value.sum(self.value(b));
+        self.push(value, Op::Add(a, b))
+    }
+
+    pub fn sub(&mut self, a: Var, b: Var) -> Var {
+        let mut value = self.value(a).clone();
+        value.sub(self.value(b));
+        self.push(value, Op::Sub(a, b))
+    }
+
+    /// element-wise product
+    pub fn mul(&mut self, a: Var, b: Var) -> Var {
+        let mut value = self.value(a).clone();
+        value.hadamard(self.value(b));
+        self.push(value, Op::Mul(a, b))
+    }
+
+    pub fn matmul(&mut self, a: Var, b: Var) -> Var {
+        let mut value = Matrix::new(self.value(a).len_row(), self.value(b).len_col());
+        value.dot(self.value(a), self.value(b));
+        self.push(value, Op::MatMul(a, b))
+    }
+
+    /// add row vector `row` to every row of `a` (bias broadcast)
+    pub fn add_row(&mut self, a: Var, row: Var) -> Var {
+        assert!(self.value(row).len_row() == 1);
+        assert!(self.value(row).len_col() == self.value(a).len_col());
+        let mut value = self.value(a).clone();
+        let bias = self.value(row).row(0).to_vec();
+        for r in value.el.iter_mut() {
+            for (cidx, col) in r.iter_mut().enumerate() {
+                *col += bias[cidx];
+            }
+        }
+        self.push(value, Op::AddRow(a, row))
+    }
+
+    pub fn scale(&mut self, a: Var, k: f64) -> Var {
+        let mut value = self.value(a).clone();
+        value.mul(&k);
+        self.push(value, Op::Scale(a, k))
+    }
+
+    /// (m x n) and (m x k) into (m x n+k)
+    pub fn concat(&mut self, a: Var, b: Var) -> Var {
+        assert!(self.value(a).len_row() == self.value(b).len_row());
+        let rows: Vec<Vec<f64>> = self
+            .value(a)
+            .el
+            .iter()
+            .zip(self.value(b).el.iter())
+            .map(|(ra, rb)| [ra.as_slice(), rb.as_slice()].concat())
+            .collect();
+        let value = Matrix::from_rows(&rows);
+        self.push(value, Op::Concat(a, b))
+    }
+
+    pub fn sigmoid(&mut self, a: Var) -> Var {
+        let value = self.map(a, sigmoid::sigmoid_f64);
+        self.push(value, Op::Sigmoid(a))
+    }
+
+    pub fn tanh(&mut self, a: Var) -> Var {
+        let value = self.map(a, f64::tanh);
+        self.push(value, Op::Tanh(a))
+    }
+
+    pub fn relu(&mut self, a: Var) -> Var {
+        let value = self.map(a, |x| x.max(0.0));
+        self.push(value, Op::Relu(a))
+    }
+
+    pub fn square(&mut self, a: Var) -> Var {
+        let value = self.map(a, |x| x * x);
+        self.push(value, Op::Square(a))
+    }
+
+    /// sum of every element, as (1 x 1) matrix
+    pub fn sum(&mut self, a: Var) -> Var {
+        let mut value = Matrix::new(1, 1);
+        *value.at_mut(0, 0) = self.value(a).el.iter().flatten().sum();
+        self.push(value, Op::Sum(a))
+    }
+
+    /// mean of every element, as (1 x 1) matrix
+    pub fn mean(&mut self, a: Var) -> Var {
+        let count = (self.value(a).len_row() * self.value(a).len_col()) as f64;
+        let mut value = Matrix::new(1, 1);
+        *value.at_mut(0, 0) = self.value(a).el.iter().flatten().sum::<f64>() / count;
+        self.push(value, Op::Mean(a))
+    }
+
+    fn accumulate(grads: &mut [Option<Matrix<f64>>], var: Var, grad: Matrix<f64>) {
+        match grads[var.idx].as_mut() {
+            None => grads[var.idx] = Some(grad),
+            Some(acc) => acc.sum(&grad),
+        }
+    }
+
+    /// gradients of `output` with respect to every value recorded before it.
+    /// `output` is seeded with ones, so for a (1 x 1) loss this is dloss/dx
+    pub fn backward(&self, output: Var) -> Grads {
+        let mut grads: Vec<Option<Matrix<f64>>> = vec![None; self.records.len()];
+
+        let mut seed = self.value(output).clone();
+        seed.fill(1.0);
+        grads[output.idx] = Some(seed);
+
+        for idx in (0..=output.idx).rev() {
+            let grad = match grads[idx].take() {
+                None => continue,
+                Some(grad) => grad,
+            };
+            let value = &self.records[idx].value;
+
+            match self.records[idx].op {
+                Op::Leaf => {}
+                Op::Add(a, b) => {
+                    Self::accumulate(&mut grads, a, grad.clone());
+                    Self::accumulate(&mut grads, b, grad.clone());
+                }
+                Op::Sub(a, b) => {
+                    Self::accumulate(&mut grads, a, grad.clone());
+                    let mut gb = grad.clone();
+                    gb.mul(&-1.0);
+                    Self::accumulate(&mut grads, b, gb);
+                }
+                Op::Mul(a, b) => {
+                    let mut ga = grad.clone();
+                    ga.hadamard(self.value(b));
+                    Self::accumulate(&mut grads, a, ga);
+                    let mut gb = grad.clone();
+                    gb.hadamard(self.value(a));
+                    Self::accumulate(&mut grads, b, gb);
+                }
+                Op::MatMul(a, b) => {
+                    let mut ga = Matrix::new(grad.len_row(), self.value(b).len_row());
+                    ga.dot(&grad, &self.value(b).transpose());
+                    Self::accumulate(&mut grads, a, ga);
+                    let mut gb = Matrix::new(self.value(a).len_col(), grad.len_col());
+                    gb.dot(&self.value(a).transpose(), &grad);
+                    Self::accumulate(&mut grads, b, gb);
+                }
+                Op::AddRow(a, row) => {
+                    let mut grow = Matrix::new(1, grad.len_col());
+                    for r in grad.el.iter() {
+                        for (cidx, col) in r.iter().enumerate() {
+                            *grow.at_mut(0, cidx) += col;
+                        }
+                    }
+                    Self::accumulate(&mut grads, a, grad.clone());
+                    Self::accumulate(&mut grads, row, grow);
+                }
+                Op::Scale(a, k) => {
+                    let mut ga = grad.clone();
+                    ga.mul(&k);
+                    Self::accumulate(&mut grads, a, ga);
+                }
+                Op::Concat(a, b) => {
+                    let split = self.value(a).len_col();
+                    let ga: Vec<Vec<f64>> = grad.el.iter().map(|r| r[..split].to_vec()).collect();
+                    let gb: Vec<Vec<f64>> = grad.el.iter().map(|r| r[split..].to_vec()).collect();
+                    Self::accumulate(&mut grads, a, Matrix::from_rows(&ga));
+                    Self::accumulate(&mut grads, b, Matrix::from_rows(&gb));
+                }
+                Op::Sigmoid(a) => {
+                    let mut local = value.clone();
+                    for col in local.el.iter_mut().flatten() {
+                        *col = *col * (1.0 - *col);
+                    }
+                    local.hadamard(&grad);
+                    Self::accumulate(&mut grads, a, local);
+                }
+                Op::Tanh(a) => {
+                    let mut local = value.clone();
+                    for col in local.el.iter_mut().flatten() {
+                        *col = 1.0 - *col * *col;
+                    }
+                    local.hadamard(&grad);
+                    Self::accumulate(&mut grads, a, local);
+                }
+                Op::Relu(a) => {
+                    let mut local = self.map(a, |x| if x > 0.0 { 1.0 } else { 0.0 });
+                    local.hadamard(&grad);
+                    Self::accumulate(&mut grads, a, local);
+                }
+                Op::Square(a) => {
+                    let mut local = self.map(a, |x| 2.0 * x);
+                    local.hadamard(&grad);
+                    Self::accumulate(&mut grads, a, local);
+                }
+                Op::Sum(a) => {
+                    let mut ga = self.value(a).clone();
+                    ga.fill(grad.at(0, 0));
+                    Self::accumulate(&mut grads, a, ga);
+                }
+                Op::Mean(a) => {
+                    let source = self.value(a);
+                    let count = (source.len_row() * source.len_col()) as f64;
+                    let mut ga = source.clone();
+                    ga.fill(grad.at(0, 0) / count);
+                    Self::accumulate(&mut grads, a, ga);
+                }
+            }
+
+            grads[idx] = Some(grad);
+        }
+
+        Grads { grads }
+    }
+}
+
+#[cfg(test)]
+fn numeric_grad(f: impl Fn(&Matrix<f64>) -> f64, at: &Matrix<f64>) -> Matrix<f64> {
+    let eps = 1e-6;
+    let mut grad = Matrix::new(at.len_row(), at.len_col());
+    for row in 0..at.len_row() {
+        for col in 0..at.len_col() {
+            let mut plus = at.clone();
+            *plus.at_mut(row, col) += eps;
+            let mut minus = at.clone();
+            *minus.at_mut(row, col) -= eps;
+            *grad.at_mut(row, col) = (f(&plus) - f(&minus)) / (2.0 * eps);
+        }
+    }
+    grad
+}
+
+#[test]
+fn tape_test_elementwise_grads() {
+    let x = Matrix::from_rows(&[vec![0.5, -1.0], vec![2.0, 0.3]]);
+    let y = Matrix::from_rows(&[vec![1.5, 0.2], vec![-0.7, 1.1]]);
+
+    let eval = |x: &Matrix<f64>, record: bool| {
+        let mut tape = Tape::new();
+        let vx = tape.var(x.clone());
+        let vy = tape.var(y.clone());
+        let prod = tape.mul(vx, vy);
+        let t = tape.tanh(prod);
+        let s = tape.sigmoid(vx);
+        let r = tape.relu(vy);
+        let sum = tape.add(t, s);
+        let diff = tape.sub(sum, r);
+        let sq = tape.square(diff);
+        let scaled = tape.scale(sq, 0.5);
+        let loss = tape.mean(scaled);
+        let value = tape.value(loss).at(0, 0);
+        let grad = if record {
+            Some(tape.backward(loss).get(vx).unwrap().clone())
+        } else {
+            None
+        };
+        (value, grad)
+    };
+
+    let analytic = eval(&x, true).1.unwrap();
+    let numeric = numeric_grad(|x| eval(x, false).0, &x);
+
+    for row in 0..2 {
+        for col in 0..2 {
+            assert!((analytic.at(row, col) - numeric.at(row, col)).abs() < 1e-6);
+        }
+    }
+}
+
+#[test]
+fn tape_test_matmul_grads() {
+    let x = Matrix::from_rows(&[vec![0.1, 0.2, 0.3], vec![-0.4, 0.5, 0.6]]);
+    let w = Matrix::from_rows(&[vec![0.7, -0.8], vec![0.9, 1.0], vec![-1.1, 1.2]]);
+    let b = Matrix::from_rows(&[vec![0.05, -0.05]]);
+
+    let eval = |w: &Matrix<f64>, b: &Matrix<f64>| {
+        let mut tape = Tape::new();
+        let vx = tape.var(x.clone());
+        let vw = tape.var(w.clone());
+        let vb = tape.var(b.clone());
+        let z = tape.matmul(vx, vw);
+        let z = tape.add_row(z, vb);
+        let a = tape.sigmoid(z);
+        let loss = tape.sum(a);
+        let grads = tape.backward(loss);
+        (
+            tape.value(loss).at(0, 0),
+            grads.get(vw).unwrap().clone(),
+            grads.get(vb).unwrap().clone(),
+        )
+    };
+
+    let (_, gw, gb) = eval(&w, &b);
+    let nw = numeric_grad(|w| eval(w, &b).0, &w);
+    let nb = numeric_grad(|b| eval(&w, b).0, &b);
+
+    for row in 0..3 {
+        for col in 0..2 {
+            assert!((gw.at(row, col) - nw.at(row, col)).abs() < 1e-6);
+        }
+    }
+    for col in 0..2 {
+        assert!((gb.at(0, col) - nb.at(0, col)).abs() < 1e-6);
+    }
+}
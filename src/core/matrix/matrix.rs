@@ -10,6 +10,7 @@ pub struct Matrix<T> {
 
 pub trait __Matrix<T> {
     fn new(row: usize, col: usize) -> Self;
+    fn from_rows(rows: &[Vec<T>]) -> Self;
     fn at(&self, row: usize, col: usize) -> T;
     fn at_mut(&mut self, row: usize, col: usize) -> &mut T;
     fn len_row(&self) -> usize;
@@ -24,6 +25,8 @@ pub trait __Matrix<T> {
     fn mul(&mut self, with: &f64);
     fn div(&mut self, with: &f64);
     fn dot(&mut self, mat_a: &Self, mat_b: &Self);
+    fn hadamard(&mut self, with: &Self);
+    fn transpose(&self) -> Self;
     fn sigmoid(&mut self);
 }
 
@@ -42,6 +45,13 @@ impl __Matrix<f64> for Matrix<f64> {
 
         return Matrix { el };
     }
+    fn from_rows(rows: &[Vec<f64>]) -> Self {
+        assert!(!rows.is_empty());
+        assert!(rows.iter().all(|row| row.len() == rows[0].len()));
+        Matrix {
+            el: Box::new(rows.to_vec()),
+        }
+    }
     fn at(&self, row: usize, col: usize) -> f64 {
         assert!(self.len_row() > row);
         assert!(self.len_col() > col);
@@ -138,6 +148,25 @@ impl __Matrix<f64> for Matrix<f64> {
             }
         }
     }
+    fn hadamard(&mut self, with: &Self) {
+        assert!(self.len_col() == with.len_col());
+        assert!(self.len_row() == with.len_row());
+
+        for (ridx, row) in self.el.iter_mut().enumerate() {
+            for (cidx, col) in row.iter_mut().enumerate() {
+                *col *= with.at(ridx, cidx);
+            }
+        }
+    }
+    fn transpose(&self) -> Self {
+        let mut transposed = Matrix::new(self.len_col(), self.len_row());
+        for (ridx, row) in self.el.iter().enumerate() {
+            for (cidx, col) in row.iter().enumerate() {
+                *transposed.at_mut(cidx, ridx) = *col;
+            }
+        }
+        transposed
+    }
     fn sigmoid(&mut self) {
         for row in self.el.iter_mut() {
             for col in row.iter_mut() {
@@ -182,6 +211,16 @@ fn matrix_test_dot() {
     println!("{:?}", result);
 }
 
+#[test]
+fn matrix_test_transpose() {
+    let mat = Matrix::from_rows(&[vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]);
+    let transposed = mat.transpose();
+    assert_eq!(transposed.len_row(), 3);
+    assert_eq!(transposed.len_col(), 2);
+    assert_eq!(transposed.at(2, 1), 6.0);
+    assert_eq!(transposed.at(0, 1), 4.0);
+}
+
 #[test]
 fn matrix_test_sigmoid() {
     let mut mat = Matrix::new(3, 5);
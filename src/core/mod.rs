@@ -1,3 +1,4 @@
+pub mod autodiff;
 pub mod common;
 pub mod matrix;
 pub mod nn;
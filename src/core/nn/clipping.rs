@@ -0,0 +1,74 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::nn::nn::NN;
+
+/// limits applied to gradients before they are learned
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct GradientClip {
+    /// clamp every gradient into [-value, value]
+    pub value: Option<f64>,
+    /// rescale all gradients so their global L2 norm is at most this
+    pub norm: Option<f64>,
+}
+
+impl GradientClip {
+    pub fn is_none(&self) -> bool {
+        self.value.is_none() && self.norm.is_none()
+    }
+
+    /// clip gradients held by `delta`, by value first then by norm
+    pub fn apply(&self, delta: &mut NN) {
+        if let Some(value) = self.value {
+            for layer in delta.net.layers.iter_mut() {
+                for param in layer.params_mut() {
+                    for w in param.el.iter_mut().flatten() {
+                        *w = w.clamp(-value, value);
+                    }
+                }
+            }
+        }
+        if let Some(max) = self.norm {
+            let norm = delta.norm();
+            if norm > max {
+                delta.mul(&(max / norm));
+            }
+        }
+    }
+}
+
+#[test]
+fn clipping_test_value_and_norm() {
+    let mut delta = NN::new(&[2, 2]);
+    delta.net.layers[0].params_mut()[0].el[0] = vec![3.0, -4.0];
+
+    let mut by_value = delta.clone();
+    GradientClip {
+        value: Some(1.0),
+        norm: None,
+    }
+    .apply(&mut by_value);
+    assert_eq!(by_value.net.layers[0].params()[0].el[0], vec![1.0, -1.0]);
+
+    let mut by_norm = delta.clone();
+    GradientClip {
+        value: None,
+        norm: Some(1.0),
+    }
+    .apply(&mut by_norm);
+    assert!((by_norm.norm() - 1.0).abs() < 1e-12);
+    let clipped = &by_norm.net.layers[0].params()[0].el[0];
+    assert!((clipped[0] - 0.6).abs() < 1e-12 && (clipped[1] + 0.8).abs() < 1e-12);
+
+    // already small gradients are untouched
+    let mut small = delta.clone();
+    GradientClip {
+        value: None,
+        norm: Some(10.0),
+    }
+    .apply(&mut small);
+    assert_eq!(
+        small.net.layers[0].params()[0].el,
+        delta.net.layers[0].params()[0].el
+    );
+}
@@ -10,6 +10,8 @@ pub struct CostInfo {
     min_idx: usize,
     pub avg: f64,
     pub costs: Vec<Cost>,
+    /// regularization penalty of the model, not included in costs
+    pub penalty: f64,
 }
 
 impl CostInfo {
@@ -20,9 +22,20 @@ impl CostInfo {
             max_idx: 0,
             min_idx: 0,
             costs: Vec::new(),
+            penalty: 0_f64,
         }
     }
 
+    /// cost of every output against its expect
+    pub fn of(outputs: &[Vec<f64>], expects: &[Vec<f64>]) -> Self {
+        assert!(outputs.len() == expects.len());
+        let mut cost_info = CostInfo::new();
+        for (output, expect) in outputs.iter().zip(expects) {
+            cost_info.push(squared_error(output, expect));
+        }
+        cost_info
+    }
+
     pub fn max(&self) -> &Cost {
         &self.costs[self.max_idx]
     }
@@ -32,6 +45,10 @@ impl CostInfo {
     pub fn avg(&self) -> &f64 {
         &self.avg
     }
+    /// avg data cost plus regularization penalty
+    pub fn loss(&self) -> f64 {
+        self.avg + self.penalty
+    }
 
     pub fn push(&mut self, cost: f64) {
         let old_len = self.costs.len();
@@ -55,3 +72,13 @@ impl CostInfo {
         }
     }
 }
+
+/// sum of squared differences between an output and its expect
+pub fn squared_error(output: &[f64], expect: &[f64]) -> f64 {
+    assert!(output.len() == expect.len());
+    let mut diff = 0.0;
+    for (idx, output) in output.iter().enumerate() {
+        diff += (output - expect[idx]).powi(2);
+    }
+    diff
+}
@@ -4,6 +4,7 @@ use std::{
     path::Path,
 };
 
+use rand::seq::SliceRandom;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::adapter::data::{Buildable, Readable, Savable, Stringfiable};
@@ -22,6 +23,162 @@ impl<T> DataSet<T> {
         debug_assert!(inputs.len() == outputs.len());
         Self { inputs, outputs }
     }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+impl<T: Clone> DataSet<T> {
+    /// samples of given indices, in that order
+    pub fn subset(&self, indices: &[usize]) -> DataSet<T> {
+        DataSet::new(
+            indices.iter().map(|idx| self.inputs[*idx].clone()).collect(),
+            indices.iter().map(|idx| self.outputs[*idx].clone()).collect(),
+        )
+    }
+}
+
+impl DataSet<f64> {
+    /// assign every sample to training, validation or test at random.
+    /// stratified split keeps the share of every class in each split,
+    /// a class being the output row rounded at 0.5.
+    /// every group keeps at least one sample for training
+    pub fn split(&self, ratio: &SplitRatio) -> Split {
+        assert!(
+            ratio.validation >= 0.0 && ratio.test >= 0.0 && ratio.validation + ratio.test < 1.0,
+            "DataSet>>split: bad ratio {:?}",
+            ratio
+        );
+        let mut split = Split {
+            ratio: ratio.clone(),
+            train: Vec::new(),
+            validation: Vec::new(),
+            test: Vec::new(),
+        };
+        for members in self.groups(ratio.method) {
+            let n = members.len() as f64;
+            let held = members.len() - 1;
+            let test = ((n * ratio.test).round() as usize).min(held);
+            let validation = ((n * ratio.validation).round() as usize).min(held - test);
+            split.test.extend_from_slice(&members[..test]);
+            split.validation.extend_from_slice(&members[test..test + validation]);
+            split.train.extend_from_slice(&members[test + validation..]);
+        }
+        split.train.sort_unstable();
+        split.validation.sort_unstable();
+        split.test.sort_unstable();
+        split
+    }
+
+    /// sample indices of `k` folds of nearly the same size,
+    /// stratified folds keep the share of every class
+    pub fn folds(&self, k: usize, method: SplitMethod) -> Vec<Vec<usize>> {
+        assert!(k > 0, "DataSet>>folds: no folds");
+        let mut folds = vec![Vec::new(); k];
+        // dealt one by one, carrying on across groups to even fold sizes
+        let mut next = 0;
+        for members in self.groups(method) {
+            for idx in members {
+                folds[next].push(idx);
+                next = (next + 1) % k;
+            }
+        }
+        for fold in folds.iter_mut() {
+            fold.sort_unstable();
+        }
+        folds
+    }
+
+    /// shuffled sample indices of every class (a single group if random)
+    fn groups(&self, method: SplitMethod) -> Vec<Vec<usize>> {
+        let mut groups: Vec<(Vec<bool>, Vec<usize>)> = Vec::new();
+        for (idx, output) in self.outputs.iter().enumerate() {
+            let key = match method {
+                SplitMethod::Random => Vec::new(),
+                SplitMethod::Stratified => output.iter().map(|v| *v >= 0.5).collect(),
+            };
+            match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, members)) => members.push(idx),
+                None => groups.push((key, vec![idx])),
+            }
+        }
+
+        let mut rng = rand::thread_rng();
+        groups
+            .into_iter()
+            .map(|(_, mut members)| {
+                members.shuffle(&mut rng);
+                members
+            })
+            .collect()
+    }
+}
+
+/// how samples are drawn into splits
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum SplitMethod {
+    Random,
+    /// every class is split by the same ratio
+    Stratified,
+}
+
+/// share of samples held out of training
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SplitRatio {
+    pub validation: f64,
+    pub test: f64,
+    pub method: SplitMethod,
+}
+
+/// sample indices of every split of a dataset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Split {
+    /// ratio the split was drawn with, reused when the dataset changes
+    pub ratio: SplitRatio,
+    pub train: Vec<usize>,
+    pub validation: Vec<usize>,
+    pub test: Vec<usize>,
+}
+
+/// dataset of sequences, every sample is a list of timesteps.
+/// all inputs (and all outputs) must have the same number of steps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SequenceSet<T> {
+    /// input timesteps of every sample
+    pub inputs: Vec<Vec<Vec<T>>>,
+    /// expected output timesteps of every sample
+    /// (a single step when only the last output is trained)
+    pub outputs: Vec<Vec<Vec<T>>>,
+}
+
+impl<T: Clone> SequenceSet<T> {
+    pub fn new(inputs: Vec<Vec<Vec<T>>>, outputs: Vec<Vec<Vec<T>>>) -> Self {
+        debug_assert!(inputs.len() == outputs.len());
+        Self { inputs, outputs }
+    }
+
+    /// rows of timesteps laid one after another, the layout recurrent
+    /// layers take. sequences must be of a fixed length (every sample
+    /// of the same number of steps)
+    pub fn flatten(&self) -> DataSet<T> {
+        fn rows<T: Clone>(samples: &[Vec<Vec<T>>]) -> Vec<Vec<T>> {
+            let width = samples.first().map(|s| s.concat().len());
+            samples
+                .iter()
+                .map(|steps| {
+                    let row = steps.concat();
+                    assert!(Some(row.len()) == width, "SequenceSet>>flatten: ragged sample");
+                    row
+                })
+                .collect()
+        }
+        DataSet::new(rows(&self.inputs), rows(&self.outputs))
+    }
 }
 
 impl<T: Serialize> Stringfiable for DataSet<T> {
@@ -87,3 +244,62 @@ impl<T: DeserializeOwned> Readable for DataSet<T> {
         ss
     }
 }
+
+#[test]
+fn dataset_test_flatten_sequences() {
+    let set = SequenceSet::new(
+        vec![vec![vec![1, 2], vec![3, 4]], vec![vec![5, 6], vec![7, 8]]],
+        vec![vec![vec![0], vec![1]], vec![vec![1], vec![0]]],
+    );
+    let flat = set.flatten();
+    assert_eq!(flat.inputs, vec![vec![1, 2, 3, 4], vec![5, 6, 7, 8]]);
+    assert_eq!(flat.outputs, vec![vec![0, 1], vec![1, 0]]);
+}
+
+#[test]
+fn dataset_test_stratified_split() {
+    let outputs: Vec<Vec<f64>> = (0..40).map(|idx| vec![(idx % 4 == 0) as u8 as f64]).collect();
+    let inputs: Vec<Vec<f64>> = (0..40).map(|idx| vec![idx as f64]).collect();
+    let set = DataSet::new(inputs, outputs);
+
+    let split = set.split(&SplitRatio {
+        validation: 0.2,
+        test: 0.1,
+        method: SplitMethod::Stratified,
+    });
+    assert_eq!(split.train.len(), 28);
+    assert_eq!(split.validation.len(), 8);
+    assert_eq!(split.test.len(), 4);
+
+    // 10 positives: 2 validation, 1 test
+    let validation = set.subset(&split.validation);
+    assert_eq!(validation.outputs.iter().filter(|o| o[0] == 1.0).count(), 2);
+    let test = set.subset(&split.test);
+    assert_eq!(test.outputs.iter().filter(|o| o[0] == 1.0).count(), 1);
+
+    let mut all = [split.train, split.validation, split.test].concat();
+    all.sort_unstable();
+    assert_eq!(all, (0..40).collect::<Vec<usize>>());
+}
+
+#[test]
+fn dataset_test_split_keeps_training_samples() {
+    let inputs = vec![vec![0.0], vec![1.0], vec![2.0], vec![3.0]];
+    let set = DataSet::new(inputs.clone(), inputs);
+    let split = set.split(&SplitRatio {
+        validation: 0.9,
+        test: 0.0,
+        method: SplitMethod::Random,
+    });
+    assert_eq!(split.train.len(), 1);
+    assert_eq!(split.validation.len(), 3);
+
+    let split = set.split(&SplitRatio {
+        validation: 0.45,
+        test: 0.45,
+        method: SplitMethod::Random,
+    });
+    assert_eq!(split.train.len(), 1);
+    assert_eq!(split.test.len(), 2);
+    assert_eq!(split.validation.len(), 1);
+}
@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    cost::CostInfo,
+    metrics::{argmax, Metrics},
+    nn::NN,
+};
+
+/// how outputs of the members are combined
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Combine {
+    /// mean of every output value
+    Average,
+    /// share of members voting for each class.
+    /// a single output is a binary class (thresholded at 0.5),
+    /// wider outputs are one-hot classes (argmax)
+    Vote,
+}
+
+/// several models of the same input and output widths,
+/// evaluated as a single one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "EnsembleData")]
+pub struct Ensemble {
+    pub models: Vec<NN>,
+    pub combine: Combine,
+}
+
+/// saved form, checked the same way as `Ensemble::new` when loaded
+#[derive(Deserialize)]
+struct EnsembleData {
+    models: Vec<NN>,
+    combine: Combine,
+}
+
+impl TryFrom<EnsembleData> for Ensemble {
+    type Error = String;
+
+    fn try_from(data: EnsembleData) -> Result<Self, Self::Error> {
+        Ensemble::new(data.models, data.combine).ok_or_else(|| {
+            String::from("Ensemble>>deserialize: no model or models differ in width")
+        })
+    }
+}
+
+impl Ensemble {
+    /// None if there is no model or their widths differ
+    pub fn new(models: Vec<NN>, combine: Combine) -> Option<Self> {
+        let first = match models.first() {
+            None => {
+                println!("Ensemble>>new: no model");
+                return None;
+            }
+            Some(first) => first,
+        };
+        let width = |nn: &NN| (nn.layers[0], *nn.layers.last().unwrap());
+        if models.iter().any(|nn| width(nn) != width(first)) {
+            println!("Ensemble>>new: models differ in input or output width");
+            return None;
+        }
+        Some(Ensemble { models, combine })
+    }
+
+    /// combined output for `input`
+    pub fn predict(&self, input: &[f64]) -> Vec<f64> {
+        self.predict_batch(&[input.to_vec()]).remove(0)
+    }
+
+    /// combined outputs for every row of `inputs`
+    pub fn predict_batch(&self, inputs: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        let outputs: Vec<Vec<Vec<f64>>> = self
+            .models
+            .iter()
+            .map(|nn| nn.predict_batch(inputs))
+            .collect();
+        let n = self.models.len() as f64;
+        (0..inputs.len())
+            .map(|sample| {
+                let width = outputs[0][sample].len();
+                let mut combined = vec![0.0; width];
+                for output in outputs.iter().map(|o| &o[sample]) {
+                    match self.combine {
+                        Combine::Average => {
+                            for (c, o) in combined.iter_mut().zip(output) {
+                                *c += o;
+                            }
+                        }
+                        Combine::Vote if width == 1 => {
+                            combined[0] += (output[0] >= 0.5) as u8 as f64
+                        }
+                        Combine::Vote => combined[argmax(output)] += 1.0,
+                    }
+                }
+                combined.iter().map(|c| c / n).collect()
+            })
+            .collect()
+    }
+
+    /// cost of combined outputs, computed as for a single model
+    pub fn cost_info(&self, inputs: &[Vec<f64>], expects: &[Vec<f64>]) -> CostInfo {
+        CostInfo::of(&self.predict_batch(inputs), expects)
+    }
+
+    /// metrics of combined outputs, computed as for a single model
+    pub fn metrics(&self, inputs: &[Vec<f64>], expects: &[Vec<f64>]) -> Metrics {
+        Metrics::evaluate(&self.predict_batch(inputs), expects)
+    }
+}
+
+#[test]
+fn ensemble_test_average_and_vote() {
+    use crate::core::matrix::matrix::__Matrix;
+
+    // sigmoid(10x - 5) rounds x, the opposite model flips it
+    let model = |w: f64| {
+        let mut nn = NN::new(&[1, 1]);
+        let mut params = nn.net.layers[0].params_mut();
+        *params[0].at_mut(0, 0) = w;
+        *params[1].at_mut(0, 0) = -w / 2.0;
+        nn
+    };
+    let inputs = vec![vec![0.0], vec![1.0], vec![1.0], vec![0.0]];
+
+    let average = Ensemble::new(vec![model(10.0), model(-10.0)], Combine::Average).unwrap();
+    for output in average.predict_batch(&inputs) {
+        assert!((output[0] - 0.5).abs() < 1e-12);
+    }
+
+    let vote = Ensemble::new(vec![model(10.0), model(10.0), model(-10.0)], Combine::Vote).unwrap();
+    assert_eq!(vote.predict(&[1.0]), vec![2.0 / 3.0]);
+    assert_eq!(vote.predict(&[0.0]), vec![1.0 / 3.0]);
+
+    let cost = vote.cost_info(&inputs, &inputs);
+    assert!((cost.avg - 1.0 / 9.0).abs() < 1e-12);
+    let metrics = vote.metrics(&inputs, &inputs);
+    assert_eq!(metrics.classification.unwrap().accuracy, 1.0);
+
+    let json = serde_json::to_string(&vote).unwrap();
+    let loaded: Ensemble = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.combine, Combine::Vote);
+    assert_eq!(loaded.predict_batch(&inputs), vote.predict_batch(&inputs));
+
+    assert!(Ensemble::new(vec![model(1.0), NN::new(&[2, 1])], Combine::Average).is_none());
+    assert!(Ensemble::new(Vec::new(), Combine::Average).is_none());
+
+    // loading goes through the same checks
+    let mut data: serde_json::Value = serde_json::from_str(&json).unwrap();
+    data["models"] = serde_json::json!([]);
+    assert!(serde_json::from_value::<Ensemble>(data).is_err());
+    let mixed = Ensemble {
+        models: vec![model(1.0), NN::new(&[2, 1])],
+        combine: Combine::Average,
+    };
+    assert!(serde_json::from_str::<Ensemble>(&serde_json::to_string(&mixed).unwrap()).is_err());
+}
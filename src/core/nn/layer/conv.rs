@@ -0,0 +1,274 @@
+use crate::core::matrix::matrix::{Matrix, __Matrix};
+
+use super::{Activation, Layer, LayerData, ParamKind, Shape};
+
+/// 2D convolution over (channels x height x width) rows.
+///
+/// kernels are square, `weights` holds one column per filter and
+/// one row per (channel, kernel y, kernel x) of the receptive field.
+#[derive(Debug, Clone)]
+pub struct Conv2D {
+    pub input: Shape,
+    pub filters: usize,
+    pub kernel: usize,
+    pub stride: usize,
+    pub padding: usize,
+    /// (channels * kernel * kernel x filters)
+    pub weights: Matrix<f64>,
+    /// (1 x filters)
+    pub biases: Matrix<f64>,
+    pub activation: Activation,
+    cache_input: Option<Matrix<f64>>,
+    cache_output: Option<Matrix<f64>>,
+    weights_grad: Matrix<f64>,
+    biases_grad: Matrix<f64>,
+}
+
+impl Conv2D {
+    pub fn new(
+        input: Shape,
+        filters: usize,
+        kernel: usize,
+        stride: usize,
+        padding: usize,
+        activation: Activation,
+    ) -> Self {
+        let weights = Matrix::new(input.channels * kernel * kernel, filters);
+        let biases = Matrix::new(1, filters);
+        Self::from(input, kernel, stride, padding, weights, biases, activation)
+    }
+
+    pub fn from(
+        input: Shape,
+        kernel: usize,
+        stride: usize,
+        padding: usize,
+        weights: Matrix<f64>,
+        biases: Matrix<f64>,
+        activation: Activation,
+    ) -> Self {
+        assert!(stride > 0);
+        assert!(input.height + 2 * padding >= kernel);
+        assert!(input.width + 2 * padding >= kernel);
+        assert!(weights.len_row() == input.channels * kernel * kernel);
+        assert!(weights.len_col() == biases.len_col());
+
+        let filters = weights.len_col();
+        let weights_grad = Matrix::new(weights.len_row(), filters);
+        let biases_grad = Matrix::new(1, filters);
+        Conv2D {
+            input,
+            filters,
+            kernel,
+            stride,
+            padding,
+            weights,
+            biases,
+            activation,
+            cache_input: None,
+            cache_output: None,
+            weights_grad,
+            biases_grad,
+        }
+    }
+
+    pub fn output_shape(&self) -> Shape {
+        Shape {
+            channels: self.filters,
+            height: (self.input.height + 2 * self.padding - self.kernel) / self.stride + 1,
+            width: (self.input.width + 2 * self.padding - self.kernel) / self.stride + 1,
+        }
+    }
+
+    /// index of input pixel seen by kernel cell, None if it falls on padding
+    fn source(&self, oy: usize, ox: usize, ky: usize, kx: usize) -> Option<(usize, usize)> {
+        let y = (oy * self.stride + ky).checked_sub(self.padding)?;
+        let x = (ox * self.stride + kx).checked_sub(self.padding)?;
+        if y < self.input.height && x < self.input.width {
+            Some((y, x))
+        } else {
+            None
+        }
+    }
+
+    /// row of weights for given kernel cell
+    fn weight_row(&self, channel: usize, ky: usize, kx: usize) -> usize {
+        (channel * self.kernel + ky) * self.kernel + kx
+    }
+}
+
+impl Layer for Conv2D {
+    fn name(&self) -> String {
+        format!(
+            "Conv2D({}->{}, k{} s{} p{})",
+            self.input,
+            self.output_shape(),
+            self.kernel,
+            self.stride,
+            self.padding
+        )
+    }
+
+    fn input_size(&self) -> usize {
+        self.input.size()
+    }
+
+    fn output_size(&self) -> usize {
+        self.output_shape().size()
+    }
+
+    fn infer(&self, input: &Matrix<f64>) -> Matrix<f64> {
+        let shape = self.output_shape();
+        let mut output = Matrix::new(input.len_row(), shape.size());
+
+        for sample in 0..input.len_row() {
+            let row = input.row(sample);
+            for filter in 0..self.filters {
+                for oy in 0..shape.height {
+                    for ox in 0..shape.width {
+                        let mut z = self.biases.at(0, filter);
+                        for channel in 0..self.input.channels {
+                            for ky in 0..self.kernel {
+                                for kx in 0..self.kernel {
+                                    if let Some((y, x)) = self.source(oy, ox, ky, kx) {
+                                        z += row[self.input.index(channel, y, x)]
+                                            * self
+                                                .weights
+                                                .at(self.weight_row(channel, ky, kx), filter);
+                                    }
+                                }
+                            }
+                        }
+                        *output.at_mut(sample, shape.index(filter, oy, ox)) =
+                            self.activation.apply(z);
+                    }
+                }
+            }
+        }
+        output
+    }
+
+    fn forward(&mut self, input: &Matrix<f64>) -> Matrix<f64> {
+        let output = self.infer(input);
+        self.cache_input = Some(input.clone());
+        self.cache_output = Some(output.clone());
+        output
+    }
+
+    fn backward(&mut self, grad: &Matrix<f64>) -> Matrix<f64> {
+        let input = self
+            .cache_input
+            .take()
+            .expect("Conv2D>>backward: forward first");
+        let output = self.cache_output.take().unwrap();
+        let shape = self.output_shape();
+        let mut input_grad = Matrix::new(input.len_row(), self.input_size());
+
+        for sample in 0..input.len_row() {
+            for filter in 0..self.filters {
+                for oy in 0..shape.height {
+                    for ox in 0..shape.width {
+                        let oidx = shape.index(filter, oy, ox);
+                        let dz = grad.at(sample, oidx)
+                            * self.activation.derive(output.at(sample, oidx));
+                        if dz == 0.0 {
+                            continue;
+                        }
+                        *self.biases_grad.at_mut(0, filter) += dz;
+                        for channel in 0..self.input.channels {
+                            for ky in 0..self.kernel {
+                                for kx in 0..self.kernel {
+                                    if let Some((y, x)) = self.source(oy, ox, ky, kx) {
+                                        let iidx = self.input.index(channel, y, x);
+                                        let widx = self.weight_row(channel, ky, kx);
+                                        *self.weights_grad.at_mut(widx, filter) +=
+                                            dz * input.at(sample, iidx);
+                                        *input_grad.at_mut(sample, iidx) +=
+                                            dz * self.weights.at(widx, filter);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        self.cache_input = Some(input);
+        self.cache_output = Some(output);
+        input_grad
+    }
+
+    fn params(&self) -> Vec<&Matrix<f64>> {
+        vec![&self.weights, &self.biases]
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut Matrix<f64>> {
+        vec![&mut self.weights, &mut self.biases]
+    }
+
+    fn param_kinds(&self) -> Vec<ParamKind> {
+        vec![ParamKind::Weight, ParamKind::Bias]
+    }
+
+    fn grads(&self) -> Vec<&Matrix<f64>> {
+        vec![&self.weights_grad, &self.biases_grad]
+    }
+
+    fn zero_grads(&mut self) {
+        self.weights_grad.fill(0.0);
+        self.biases_grad.fill(0.0);
+    }
+
+    fn serialize(&self) -> LayerData {
+        LayerData::Conv2D {
+            input: self.input,
+            kernel: self.kernel,
+            stride: self.stride,
+            padding: self.padding,
+            weights: self.weights.clone(),
+            biases: self.biases.clone(),
+            activation: self.activation,
+        }
+    }
+}
+
+#[test]
+fn conv_test_output_shape() {
+    let conv = Conv2D::new(Shape::new(1, 28, 28), 4, 3, 1, 0, Activation::Sigmoid);
+    assert_eq!(conv.output_shape(), Shape::new(4, 26, 26));
+
+    let conv = Conv2D::new(Shape::new(2, 5, 5), 3, 3, 2, 1, Activation::Sigmoid);
+    assert_eq!(conv.output_shape(), Shape::new(3, 3, 3));
+}
+
+#[test]
+fn conv_test_backprop_matches_finite_diff() {
+    use super::{assert_same_params, dense::Dense, flatten::Flatten, pool::AvgPool2D};
+    use crate::core::nn::{nn::NN, sequential::Sequential};
+
+    let mut net = Sequential::new();
+    net.push(Box::new(Conv2D::new(
+        Shape::new(2, 4, 4),
+        2,
+        3,
+        1,
+        1,
+        Activation::Sigmoid,
+    )));
+    net.push(Box::new(AvgPool2D::new(Shape::new(2, 4, 4), 2, 2)));
+    net.push(Box::new(Flatten::new(Shape::new(2, 2, 2))));
+    net.push(Box::new(Dense::new(8, 2, Activation::Sigmoid)));
+    let mut nn = NN::from_net(net);
+    nn.rand();
+
+    let inputs: Vec<Vec<f64>> = (0..3)
+        .map(|n| (0..32).map(|i| ((i * 7 + n * 3) % 11) as f64 / 11.0).collect())
+        .collect();
+    let expects = vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![0.5, 0.5]];
+
+    let back = nn.backprop(&inputs, &expects);
+    let numeric = nn.finite_diff(&inputs, &expects, &1e-7);
+
+    assert_same_params(&back.net.layers, &numeric.net.layers, 1e-5);
+}
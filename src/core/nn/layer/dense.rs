@@ -0,0 +1,220 @@
+use crate::core::autodiff::tape::{Tape, Var};
+use crate::core::matrix::matrix::{Matrix, __Matrix};
+
+use super::{Activation, Layer, LayerData, ParamKind};
+
+/// fully connected layer: activation(input . weights + biases)
+#[derive(Debug, Clone)]
+pub struct Dense {
+    /// (input x output)
+    pub weights: Matrix<f64>,
+    /// (1 x output)
+    pub biases: Matrix<f64>,
+    pub activation: Activation,
+    /// probability of dropping each output while training (inverted dropout)
+    pub dropout: f64,
+    training: bool,
+    input: Option<Matrix<f64>>,
+    /// activated output, before dropout
+    output: Option<Matrix<f64>>,
+    /// scale of every output in last training forward (0 if dropped)
+    mask: Option<Matrix<f64>>,
+    weights_grad: Matrix<f64>,
+    biases_grad: Matrix<f64>,
+}
+
+impl Dense {
+    pub fn new(input: usize, output: usize, activation: Activation) -> Self {
+        Self::from(
+            Matrix::new(input, output),
+            Matrix::new(1, output),
+            activation,
+        )
+    }
+
+    pub fn from(weights: Matrix<f64>, biases: Matrix<f64>, activation: Activation) -> Self {
+        assert!(biases.len_row() == 1);
+        assert!(weights.len_col() == biases.len_col());
+        let weights_grad = Matrix::new(weights.len_row(), weights.len_col());
+        let biases_grad = Matrix::new(1, biases.len_col());
+        Dense {
+            weights,
+            biases,
+            activation,
+            dropout: 0.0,
+            training: false,
+            input: None,
+            output: None,
+            mask: None,
+            weights_grad,
+            biases_grad,
+        }
+    }
+
+    pub fn with_dropout(mut self, dropout: f64) -> Self {
+        assert!((0.0..1.0).contains(&dropout));
+        self.dropout = dropout;
+        self
+    }
+
+    /// random mask of inverted dropout, kept outputs are scaled up
+    /// so expected value of output does not change
+    fn sample_mask(&self, rows: usize) -> Matrix<f64> {
+        let keep = 1.0 - self.dropout;
+        let mut mask = Matrix::new(rows, self.output_size());
+        for col in mask.el.iter_mut().flatten() {
+            if rand::random::<f64>() < keep {
+                *col = 1.0 / keep;
+            }
+        }
+        mask
+    }
+
+    /// activated output of the layer, without dropout
+    fn activate(&self, input: &Matrix<f64>) -> Matrix<f64> {
+        let mut output = Matrix::new(input.len_row(), self.output_size());
+        output.dot(input, &self.weights);
+        for row in output.el.iter_mut() {
+            for (cidx, col) in row.iter_mut().enumerate() {
+                *col = self.activation.apply(*col + self.biases.at(0, cidx));
+            }
+        }
+        output
+    }
+}
+
+impl Layer for Dense {
+    fn name(&self) -> String {
+        format!("Dense({}->{})", self.input_size(), self.output_size())
+    }
+
+    fn input_size(&self) -> usize {
+        self.weights.len_row()
+    }
+
+    fn output_size(&self) -> usize {
+        self.weights.len_col()
+    }
+
+    fn forward(&mut self, input: &Matrix<f64>) -> Matrix<f64> {
+        let mut output = self.activate(input);
+        self.input = Some(input.clone());
+        self.output = Some(output.clone());
+
+        if self.training && self.dropout > 0.0 {
+            let mask = self.sample_mask(output.len_row());
+            output.hadamard(&mask);
+            self.mask = Some(mask);
+        } else {
+            self.mask = None;
+        }
+        output
+    }
+
+    fn infer(&self, input: &Matrix<f64>) -> Matrix<f64> {
+        self.activate(input)
+    }
+
+    fn backward(&mut self, grad: &Matrix<f64>) -> Matrix<f64> {
+        let input = self.input.as_ref().expect("Dense>>backward: forward first");
+        let output = self.output.as_ref().unwrap();
+
+        // dcost/dz
+        let mut dz = grad.clone();
+        if let Some(mask) = self.mask.as_ref() {
+            dz.hadamard(mask);
+        }
+        for (ridx, row) in dz.el.iter_mut().enumerate() {
+            for (cidx, col) in row.iter_mut().enumerate() {
+                *col *= self.activation.derive(output.at(ridx, cidx));
+            }
+        }
+
+        let mut weights_grad = Matrix::new(self.input_size(), self.output_size());
+        weights_grad.dot(&input.transpose(), &dz);
+        self.weights_grad.sum(&weights_grad);
+
+        for row in dz.el.iter() {
+            for (cidx, col) in row.iter().enumerate() {
+                *self.biases_grad.at_mut(0, cidx) += col;
+            }
+        }
+
+        let mut input_grad = Matrix::new(dz.len_row(), self.input_size());
+        input_grad.dot(&dz, &self.weights.transpose());
+        input_grad
+    }
+
+    fn params(&self) -> Vec<&Matrix<f64>> {
+        vec![&self.weights, &self.biases]
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut Matrix<f64>> {
+        vec![&mut self.weights, &mut self.biases]
+    }
+
+    fn param_kinds(&self) -> Vec<ParamKind> {
+        vec![ParamKind::Weight, ParamKind::Bias]
+    }
+
+    fn grads(&self) -> Vec<&Matrix<f64>> {
+        vec![&self.weights_grad, &self.biases_grad]
+    }
+
+    fn zero_grads(&mut self) {
+        self.weights_grad.fill(0.0);
+        self.biases_grad.fill(0.0);
+    }
+
+    fn serialize(&self) -> LayerData {
+        LayerData::Dense {
+            weights: self.weights.clone(),
+            biases: self.biases.clone(),
+            activation: self.activation,
+            dropout: self.dropout,
+        }
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+
+    fn trace(&self, tape: &mut Tape, input: Var) -> Option<(Var, Vec<Var>)> {
+        let weights = tape.var(self.weights.clone());
+        let biases = tape.var(self.biases.clone());
+        let z = tape.matmul(input, weights);
+        let z = tape.add_row(z, biases);
+        let mut output = self.activation.trace(tape, z);
+        if self.training && self.dropout > 0.0 {
+            let rows = tape.value(input).len_row();
+            let mask = tape.var(self.sample_mask(rows));
+            output = tape.mul(output, mask);
+        }
+        Some((output, vec![weights, biases]))
+    }
+}
+
+#[test]
+fn dense_test_dropout_mask() {
+    use crate::core::nn::nn::NN;
+
+    let mut nn = NN::with_dropout(&[3, 6, 2], &[0.5]);
+    nn.rand();
+
+    // inference is deterministic
+    nn.set(&[0.2, 0.4, 0.6]);
+    nn.process();
+    let first = nn.output().to_vec();
+    nn.process();
+    assert_eq!(first, nn.output().to_vec());
+
+    // dropped hidden units pass no gradient to either side
+    let delta = nn.backprop(&[vec![0.2, 0.4, 0.6]], &[vec![1.0, 0.0]]);
+    let hidden = delta.net.layers[0].params()[0];
+    let output = delta.net.layers[1].params()[0];
+    for unit in 0..6 {
+        let dropped_in = hidden.col(unit).iter().all(|w| **w == 0.0);
+        let dropped_out = output.row(unit).iter().all(|w| *w == 0.0);
+        assert_eq!(dropped_in, dropped_out);
+    }
+}
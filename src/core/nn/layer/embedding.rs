@@ -0,0 +1,249 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::core::matrix::matrix::{Matrix, __Matrix};
+
+use super::{Layer, LayerData};
+
+/// lookup table mapping integer token ids to learned vectors.
+///
+/// an input row holds `length` token ids (as f64), the output row holds
+/// their vectors one after another. gradients and updates only touch rows
+/// of tokens which were looked up: a delta of the layer (built by
+/// `gradients`) holds those rows only
+#[derive(Debug, Clone)]
+pub struct Embedding {
+    /// (vocab x dim), row `id` is the vector of token `id`
+    pub table: Matrix<f64>,
+    /// tokens per input row
+    pub length: usize,
+    /// tokens of the rows of table if it holds some of them (sparse delta)
+    rows: Option<Vec<usize>>,
+    /// token ids of last forward
+    tokens: Vec<Vec<usize>>,
+    table_grad: Matrix<f64>,
+    /// rows of table_grad holding accumulated gradients
+    touched: BTreeSet<usize>,
+}
+
+impl Embedding {
+    pub fn new(vocab: usize, dim: usize, length: usize) -> Self {
+        Self::from(Matrix::new(vocab, dim), length)
+    }
+
+    pub fn from(table: Matrix<f64>, length: usize) -> Self {
+        assert!(length > 0);
+        let table_grad = Matrix::new(table.len_row(), table.len_col());
+        Embedding {
+            table,
+            length,
+            rows: None,
+            tokens: Vec::new(),
+            table_grad,
+            touched: BTreeSet::new(),
+        }
+    }
+
+    /// table holding rows of given tokens only, in the same order
+    pub fn with_rows(mut self, rows: Option<Vec<usize>>) -> Self {
+        if let Some(rows) = rows.as_ref() {
+            assert!(rows.len() == self.table.len_row());
+        }
+        self.rows = rows;
+        self
+    }
+
+    pub fn vocab(&self) -> usize {
+        self.table.len_row()
+    }
+
+    pub fn dim(&self) -> usize {
+        self.table.len_col()
+    }
+
+    fn token(&self, id: f64) -> usize {
+        let token = id.round();
+        assert!(
+            token >= 0.0 && (token as usize) < self.vocab(),
+            "Embedding>>forward: token {} out of vocab {}",
+            id,
+            self.vocab()
+        );
+        token as usize
+    }
+
+    /// vectors of every input row and their token ids
+    fn lookup(&self, input: &Matrix<f64>) -> (Matrix<f64>, Vec<Vec<usize>>) {
+        let dim = self.dim();
+        let mut output = Matrix::new(input.len_row(), self.output_size());
+        let tokens: Vec<Vec<usize>> = input
+            .el
+            .iter()
+            .map(|row| row.iter().map(|id| self.token(*id)).collect())
+            .collect();
+
+        for (sample, tokens) in tokens.iter().enumerate() {
+            let row = output.row_mut(sample);
+            for (pos, token) in tokens.iter().enumerate() {
+                row[pos * dim..(pos + 1) * dim].copy_from_slice(self.table.row(*token));
+            }
+        }
+        (output, tokens)
+    }
+}
+
+impl Layer for Embedding {
+    fn name(&self) -> String {
+        format!(
+            "Embedding({}x{} table, {} tokens)",
+            self.vocab(),
+            self.dim(),
+            self.length
+        )
+    }
+
+    fn input_size(&self) -> usize {
+        self.length
+    }
+
+    fn output_size(&self) -> usize {
+        self.length * self.dim()
+    }
+
+    fn forward(&mut self, input: &Matrix<f64>) -> Matrix<f64> {
+        let (output, tokens) = self.lookup(input);
+        self.tokens = tokens;
+        output
+    }
+
+    fn infer(&self, input: &Matrix<f64>) -> Matrix<f64> {
+        self.lookup(input).0
+    }
+
+    /// token ids are not differentiable, so dcost/dinput is zero
+    fn backward(&mut self, grad: &Matrix<f64>) -> Matrix<f64> {
+        let dim = self.dim();
+        for (sample, tokens) in self.tokens.iter().enumerate() {
+            let grad = grad.row(sample);
+            for (pos, token) in tokens.iter().enumerate() {
+                for (g, d) in self
+                    .table_grad
+                    .row_mut(*token)
+                    .iter_mut()
+                    .zip(&grad[pos * dim..(pos + 1) * dim])
+                {
+                    *g += d;
+                }
+                self.touched.insert(*token);
+            }
+        }
+        Matrix::new(grad.len_row(), self.input_size())
+    }
+
+    fn params(&self) -> Vec<&Matrix<f64>> {
+        vec![&self.table]
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut Matrix<f64>> {
+        vec![&mut self.table]
+    }
+
+    fn grads(&self) -> Vec<&Matrix<f64>> {
+        vec![&self.table_grad]
+    }
+
+    fn zero_grads(&mut self) {
+        for token in std::mem::take(&mut self.touched) {
+            self.table_grad.row_mut(token).fill(0.0);
+        }
+    }
+
+    /// sparse delta of the rows touched since zero_grads
+    fn gradients(&self) -> Box<dyn Layer> {
+        let rows: Vec<usize> = self.touched.iter().copied().collect();
+        let mut table = Matrix::new(rows.len(), self.dim());
+        for (row, token) in table.el.iter_mut().zip(rows.iter()) {
+            row.copy_from_slice(self.table_grad.row(*token));
+        }
+        Box::new(Embedding::from(table, self.length).with_rows(Some(rows)))
+    }
+
+    fn sparse_rows(&self) -> Option<&[usize]> {
+        self.rows.as_deref()
+    }
+
+    /// rows held by either delta are kept
+    fn accumulate(&mut self, delta: &dyn Layer) {
+        let mut merged: BTreeMap<usize, Vec<f64>> = BTreeMap::new();
+        for layer in [self as &dyn Layer, delta] {
+            let steps = layer.params()[0];
+            for (idx, step) in steps.el.iter().enumerate() {
+                let token = layer.sparse_rows().map_or(idx, |rows| rows[idx]);
+                match merged.get_mut(&token) {
+                    None => {
+                        merged.insert(token, step.clone());
+                    }
+                    Some(row) => {
+                        for (w, d) in row.iter_mut().zip(step) {
+                            *w += d;
+                        }
+                    }
+                }
+            }
+        }
+        let dense = self.rows.is_none() && delta.sparse_rows().is_none();
+        self.rows = (!dense).then(|| merged.keys().copied().collect());
+        *self.table.el = merged.into_values().collect();
+    }
+
+    /// a sparse delta only steps the rows it holds
+    fn learn(&mut self, delta: &dyn Layer) {
+        let steps = delta.params()[0];
+        for (idx, step) in steps.el.iter().enumerate() {
+            let token = delta.sparse_rows().map_or(idx, |rows| rows[idx]);
+            for (w, d) in self.table.row_mut(token).iter_mut().zip(step) {
+                *w -= d;
+            }
+        }
+    }
+
+    fn serialize(&self) -> LayerData {
+        LayerData::Embedding {
+            table: self.table.clone(),
+            length: self.length,
+            rows: self.rows.clone(),
+        }
+    }
+}
+
+#[test]
+fn embedding_test_sparse_grads() {
+    let mut embedding = Embedding::new(5, 2, 3);
+    embedding.table.rand();
+    let table = embedding.table.clone();
+
+    let input = Matrix::from_rows(&[vec![1.0, 3.0, 1.0], vec![4.0, 1.0, 3.0]]);
+    let output = embedding.forward(&input);
+    assert_eq!(output.row(0)[2..4], *table.row(3));
+    assert_eq!(output.row(1)[0..2], *table.row(4));
+
+    let mut grad = Matrix::new(2, 6);
+    grad.fill(1.0);
+    embedding.backward(&grad);
+    let table_grad = embedding.grads()[0].clone();
+    assert_eq!(table_grad.row(1), &[3.0, 3.0]);
+    assert_eq!(table_grad.row(3), &[2.0, 2.0]);
+    assert_eq!(table_grad.row(4), &[1.0, 1.0]);
+    assert_eq!(table_grad.row(0), &[0.0, 0.0]);
+
+    // the delta holds touched rows only, others are left as they are
+    let delta = embedding.gradients();
+    assert_eq!(delta.sparse_rows(), Some(&[1, 3, 4][..]));
+    assert_eq!(delta.params()[0].row(0), &[3.0, 3.0]);
+    embedding.learn(delta.as_ref());
+    assert_eq!(embedding.table.row(0), table.row(0));
+    assert_eq!(embedding.table.row(2), table.row(2));
+    assert_eq!(embedding.table.at(1, 0), table.at(1, 0) - 3.0);
+
+    embedding.zero_grads();
+    assert!(embedding.grads()[0].el.iter().flatten().all(|g| *g == 0.0));
+}
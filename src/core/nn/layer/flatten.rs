@@ -0,0 +1,65 @@
+use crate::core::autodiff::tape::{Tape, Var};
+use crate::core::matrix::matrix::Matrix;
+
+use super::{Layer, LayerData, Shape};
+
+/// marks the end of spatial layers.
+/// rows are already stored flat, so values pass through unchanged
+#[derive(Debug, Clone)]
+pub struct Flatten {
+    pub input: Shape,
+}
+
+impl Flatten {
+    pub fn new(input: Shape) -> Self {
+        Flatten { input }
+    }
+}
+
+impl Layer for Flatten {
+    fn name(&self) -> String {
+        format!("Flatten({}->{})", self.input, self.input.size())
+    }
+
+    fn input_size(&self) -> usize {
+        self.input.size()
+    }
+
+    fn output_size(&self) -> usize {
+        self.input.size()
+    }
+
+    fn forward(&mut self, input: &Matrix<f64>) -> Matrix<f64> {
+        input.clone()
+    }
+
+    fn infer(&self, input: &Matrix<f64>) -> Matrix<f64> {
+        input.clone()
+    }
+
+    fn backward(&mut self, grad: &Matrix<f64>) -> Matrix<f64> {
+        grad.clone()
+    }
+
+    fn params(&self) -> Vec<&Matrix<f64>> {
+        Vec::new()
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut Matrix<f64>> {
+        Vec::new()
+    }
+
+    fn grads(&self) -> Vec<&Matrix<f64>> {
+        Vec::new()
+    }
+
+    fn zero_grads(&mut self) {}
+
+    fn serialize(&self) -> LayerData {
+        LayerData::Flatten { input: self.input }
+    }
+
+    fn trace(&self, _tape: &mut Tape, input: Var) -> Option<(Var, Vec<Var>)> {
+        Some((input, Vec::new()))
+    }
+}
@@ -0,0 +1,594 @@
+pub mod conv;
+pub mod dense;
+pub mod embedding;
+pub mod flatten;
+pub mod norm;
+pub mod pool;
+pub mod recurrent;
+pub mod time_distributed;
+
+use std::fmt;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::autodiff::tape::{Tape, Var};
+use crate::core::common::sigmoid;
+use crate::core::matrix::matrix::{Matrix, __Matrix};
+
+use self::conv::Conv2D;
+use self::dense::Dense;
+use self::embedding::Embedding;
+use self::flatten::Flatten;
+use self::norm::{BatchNorm, LayerNorm};
+use self::pool::{AvgPool2D, MaxPool2D};
+use self::recurrent::{Gru, Lstm, Rnn};
+use self::time_distributed::TimeDistributed;
+
+/// layout of a spatial row, stored flat in (channel, y, x) order
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Shape {
+    pub channels: usize,
+    pub height: usize,
+    pub width: usize,
+}
+
+impl Shape {
+    pub fn new(channels: usize, height: usize, width: usize) -> Self {
+        Shape {
+            channels,
+            height,
+            width,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.channels * self.height * self.width
+    }
+
+    /// flat index of given position
+    pub fn index(&self, channel: usize, y: usize, x: usize) -> usize {
+        (channel * self.height + y) * self.width + x
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}x{}", self.channels, self.height, self.width)
+    }
+}
+
+/// activation applied on the output of a layer
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum Activation {
+    Sigmoid,
+    /// identity, output is left as it is
+    Linear,
+}
+
+impl Activation {
+    pub fn apply(&self, x: f64) -> f64 {
+        match self {
+            Activation::Sigmoid => sigmoid::sigmoid_f64(x),
+            Activation::Linear => x,
+        }
+    }
+
+    /// derivative, expressed with the activated value `y`
+    pub fn derive(&self, y: f64) -> f64 {
+        match self {
+            Activation::Sigmoid => y * (1.0 - y),
+            Activation::Linear => 1.0,
+        }
+    }
+
+    pub fn trace(&self, tape: &mut Tape, x: Var) -> Var {
+        match self {
+            Activation::Sigmoid => tape.sigmoid(x),
+            Activation::Linear => x,
+        }
+    }
+}
+
+/// role of a parameter, so penalties can tell weights from biases
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamKind {
+    Weight,
+    Bias,
+}
+
+/// building block of a model.
+///
+/// a layer takes a batch (one sample per row) and returns a batch.
+/// `forward` keeps whatever `backward` needs, and `backward` accumulates
+/// gradients of its parameters until `zero_grads` is called.
+pub trait Layer: Send + Sync {
+    /// short description shown in views
+    fn name(&self) -> String;
+    /// width of a single input row
+    fn input_size(&self) -> usize;
+    /// width of a single output row
+    fn output_size(&self) -> usize;
+    fn forward(&mut self, input: &Matrix<f64>) -> Matrix<f64>;
+    /// output in inference mode, without keeping anything for backward
+    fn infer(&self, input: &Matrix<f64>) -> Matrix<f64>;
+    /// take dcost/doutput of the last forward and return dcost/dinput
+    fn backward(&mut self, grad: &Matrix<f64>) -> Matrix<f64>;
+    /// trainable parameters
+    fn params(&self) -> Vec<&Matrix<f64>>;
+    fn params_mut(&mut self) -> Vec<&mut Matrix<f64>>;
+    /// role of every param, in the same order as params
+    fn param_kinds(&self) -> Vec<ParamKind> {
+        self.params().iter().map(|_| ParamKind::Weight).collect()
+    }
+    /// accumulated gradients, in the same order as params
+    fn grads(&self) -> Vec<&Matrix<f64>>;
+    fn zero_grads(&mut self);
+    /// layer of the same kind holding accumulated gradients as params
+    fn gradients(&self) -> Box<dyn Layer> {
+        let mut delta = self.serialize().build();
+        for (param, grad) in delta.params_mut().into_iter().zip(self.grads()) {
+            *param = grad.clone();
+        }
+        delta
+    }
+    /// rows of the params held by a sparse delta, in order.
+    /// None if every row is held
+    fn sparse_rows(&self) -> Option<&[usize]> {
+        None
+    }
+    /// add steps of `delta` into this delta (both built by `gradients`)
+    fn accumulate(&mut self, delta: &dyn Layer) {
+        for (param, delta) in self.params_mut().into_iter().zip(delta.params()) {
+            param.sum(delta);
+        }
+    }
+    /// subtract `delta` (same kind of layer holding steps as params)
+    fn learn(&mut self, delta: &dyn Layer) {
+        for (param, delta) in self.params_mut().into_iter().zip(delta.params()) {
+            param.sub(delta);
+        }
+    }
+    /// draw random parameters before training
+    /// (layers starting from fixed values reset to them instead)
+    fn rand(&mut self) {
+        for param in self.params_mut() {
+            param.rand();
+        }
+    }
+    /// switch between training and inference behaviour
+    /// (layers which behave the same way can ignore it)
+    fn set_training(&mut self, _training: bool) {}
+    /// whether the output of a sample depends on the rest of its batch
+    /// (batch statistics while training), such a batch can not be split
+    fn batch_dependent(&self) -> bool {
+        false
+    }
+    /// serializable form of the layer
+    fn serialize(&self) -> LayerData;
+    /// record forward on a tape.
+    /// returns output var and vars of params (same order as params),
+    /// or None if the layer cannot be traced
+    fn trace(&self, _tape: &mut Tape, _input: Var) -> Option<(Var, Vec<Var>)> {
+        None
+    }
+}
+
+/// serializable form of every layer kind
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LayerData {
+    Dense {
+        weights: Matrix<f64>,
+        biases: Matrix<f64>,
+        activation: Activation,
+        #[serde(default)]
+        dropout: f64,
+    },
+    Conv2D {
+        input: Shape,
+        kernel: usize,
+        stride: usize,
+        padding: usize,
+        weights: Matrix<f64>,
+        biases: Matrix<f64>,
+        activation: Activation,
+    },
+    MaxPool2D {
+        input: Shape,
+        size: usize,
+        stride: usize,
+    },
+    AvgPool2D {
+        input: Shape,
+        size: usize,
+        stride: usize,
+    },
+    Flatten {
+        input: Shape,
+    },
+    BatchNorm {
+        gamma: Matrix<f64>,
+        beta: Matrix<f64>,
+        running_mean: Matrix<f64>,
+        running_var: Matrix<f64>,
+        momentum: f64,
+        eps: f64,
+    },
+    LayerNorm {
+        gamma: Matrix<f64>,
+        beta: Matrix<f64>,
+        eps: f64,
+    },
+    Embedding {
+        table: Matrix<f64>,
+        length: usize,
+        /// tokens of the rows of a sparse delta
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rows: Option<Vec<usize>>,
+    },
+    Rnn {
+        steps: usize,
+        weights: Matrix<f64>,
+        recurrent: Matrix<f64>,
+        biases: Matrix<f64>,
+        return_sequences: bool,
+    },
+    Gru {
+        steps: usize,
+        weights: Matrix<f64>,
+        recurrent: Matrix<f64>,
+        biases: Matrix<f64>,
+        return_sequences: bool,
+    },
+    Lstm {
+        steps: usize,
+        weights: Matrix<f64>,
+        recurrent: Matrix<f64>,
+        biases: Matrix<f64>,
+        return_sequences: bool,
+    },
+    TimeDistributed {
+        steps: usize,
+        layer: Box<LayerData>,
+    },
+}
+
+/// (rows, cols) of a matrix, if it has rows of the same width
+fn shape_of(name: &str, matrix: &Matrix<f64>) -> Result<(usize, usize), String> {
+    match matrix.el.first() {
+        Some(row) if !row.is_empty() && matrix.el.iter().all(|r| r.len() == row.len()) => {
+            Ok((matrix.len_row(), row.len()))
+        }
+        _ => Err(format!("LayerData>>build: {} is not a matrix", name)),
+    }
+}
+
+/// recurrent weights of `gates` hidden-sized blocks
+fn check_recurrent(
+    steps: usize,
+    gates: usize,
+    weights: &Matrix<f64>,
+    recurrent: &Matrix<f64>,
+    biases: &Matrix<f64>,
+) -> Result<(), String> {
+    let (_, weights_cols) = shape_of("weights", weights)?;
+    let (hidden, recurrent_cols) = shape_of("recurrent", recurrent)?;
+    let biases = shape_of("biases", biases)?;
+    if steps == 0 {
+        return Err(String::from("LayerData>>build: recurrent layer of 0 steps"));
+    }
+    let cols = gates * hidden;
+    if weights_cols != cols || recurrent_cols != cols || biases != (1, cols) {
+        return Err(format!(
+            "LayerData>>build: recurrent weights do not fit {} hidden units",
+            hidden
+        ));
+    }
+    Ok(())
+}
+
+impl LayerData {
+    /// same as build, returns why the data does not make a layer instead of panicking
+    pub fn try_build(self) -> Result<Box<dyn Layer>, String> {
+        self.check()?;
+        Ok(self.build())
+    }
+
+    /// whether constructors accept the data
+    fn check(&self) -> Result<(), String> {
+        match self {
+            LayerData::Dense {
+                weights,
+                biases,
+                dropout,
+                ..
+            } => {
+                let (_, cols) = shape_of("weights", weights)?;
+                if shape_of("biases", biases)? != (1, cols) {
+                    return Err(format!(
+                        "LayerData>>build: dense biases do not fit {} outputs",
+                        cols
+                    ));
+                }
+                if !(0.0..1.0).contains(dropout) {
+                    return Err(format!(
+                        "LayerData>>build: dropout rate {} is not in [0, 1)",
+                        dropout
+                    ));
+                }
+            }
+            LayerData::Conv2D {
+                input,
+                kernel,
+                stride,
+                padding,
+                weights,
+                biases,
+                ..
+            } => {
+                let (rows, cols) = shape_of("weights", weights)?;
+                if *stride == 0
+                    || input.height + 2 * padding < *kernel
+                    || input.width + 2 * padding < *kernel
+                    || rows != input.channels * kernel * kernel
+                    || shape_of("biases", biases)? != (1, cols)
+                {
+                    return Err(format!(
+                        "LayerData>>build: conv of kernel {} does not fit {} input",
+                        kernel, input
+                    ));
+                }
+            }
+            LayerData::MaxPool2D {
+                input,
+                size,
+                stride,
+            }
+            | LayerData::AvgPool2D {
+                input,
+                size,
+                stride,
+            } => {
+                if *size == 0 || *stride == 0 || input.height < *size || input.width < *size {
+                    return Err(format!(
+                        "LayerData>>build: pool of size {} does not fit {} input",
+                        size, input
+                    ));
+                }
+            }
+            LayerData::Flatten { .. } => {}
+            LayerData::BatchNorm {
+                gamma,
+                beta,
+                running_mean,
+                running_var,
+                ..
+            } => {
+                let size = shape_of("gamma", gamma)?;
+                if shape_of("beta", beta)? != size
+                    || shape_of("running_mean", running_mean)? != size
+                    || shape_of("running_var", running_var)? != size
+                {
+                    return Err(String::from("LayerData>>build: batch norm sizes differ"));
+                }
+            }
+            LayerData::LayerNorm { gamma, beta, .. } => {
+                if shape_of("beta", beta)? != shape_of("gamma", gamma)? {
+                    return Err(String::from("LayerData>>build: layer norm sizes differ"));
+                }
+            }
+            LayerData::Embedding {
+                table,
+                length,
+                rows,
+            } => {
+                let (vocab, _) = shape_of("table", table)?;
+                if *length == 0 {
+                    return Err(String::from("LayerData>>build: embedding of 0 tokens"));
+                }
+                if rows.as_ref().is_some_and(|rows| rows.len() != vocab) {
+                    return Err(String::from(
+                        "LayerData>>build: embedding rows do not fit its table",
+                    ));
+                }
+            }
+            LayerData::Rnn {
+                steps,
+                weights,
+                recurrent,
+                biases,
+                ..
+            } => check_recurrent(*steps, 1, weights, recurrent, biases)?,
+            LayerData::Gru {
+                steps,
+                weights,
+                recurrent,
+                biases,
+                ..
+            } => check_recurrent(*steps, 3, weights, recurrent, biases)?,
+            LayerData::Lstm {
+                steps,
+                weights,
+                recurrent,
+                biases,
+                ..
+            } => check_recurrent(*steps, 4, weights, recurrent, biases)?,
+            LayerData::TimeDistributed { steps, layer } => {
+                if *steps == 0 {
+                    return Err(String::from("LayerData>>build: time distributed of 0 steps"));
+                }
+                layer.check()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn build(self) -> Box<dyn Layer> {
+        match self {
+            LayerData::Dense {
+                weights,
+                biases,
+                activation,
+                dropout,
+            } => Box::new(Dense::from(weights, biases, activation).with_dropout(dropout)),
+            LayerData::Conv2D {
+                input,
+                kernel,
+                stride,
+                padding,
+                weights,
+                biases,
+                activation,
+            } => Box::new(Conv2D::from(
+                input, kernel, stride, padding, weights, biases, activation,
+            )),
+            LayerData::MaxPool2D {
+                input,
+                size,
+                stride,
+            } => Box::new(MaxPool2D::new(input, size, stride)),
+            LayerData::AvgPool2D {
+                input,
+                size,
+                stride,
+            } => Box::new(AvgPool2D::new(input, size, stride)),
+            LayerData::Flatten { input } => Box::new(Flatten::new(input)),
+            LayerData::BatchNorm {
+                gamma,
+                beta,
+                running_mean,
+                running_var,
+                momentum,
+                eps,
+            } => Box::new(BatchNorm::from(
+                gamma,
+                beta,
+                running_mean,
+                running_var,
+                momentum,
+                eps,
+            )),
+            LayerData::LayerNorm { gamma, beta, eps } => {
+                Box::new(LayerNorm::from(gamma, beta, eps))
+            }
+            LayerData::Embedding {
+                table,
+                length,
+                rows,
+            } => Box::new(Embedding::from(table, length).with_rows(rows)),
+            LayerData::Rnn {
+                steps,
+                weights,
+                recurrent,
+                biases,
+                return_sequences,
+            } => Box::new(Rnn::from(
+                steps,
+                weights,
+                recurrent,
+                biases,
+                return_sequences,
+            )),
+            LayerData::Gru {
+                steps,
+                weights,
+                recurrent,
+                biases,
+                return_sequences,
+            } => Box::new(Gru::from(
+                steps,
+                weights,
+                recurrent,
+                biases,
+                return_sequences,
+            )),
+            LayerData::Lstm {
+                steps,
+                weights,
+                recurrent,
+                biases,
+                return_sequences,
+            } => Box::new(Lstm::from(
+                steps,
+                weights,
+                recurrent,
+                biases,
+                return_sequences,
+            )),
+            LayerData::TimeDistributed { steps, layer } => {
+                Box::new(TimeDistributed::new(steps, layer.build()))
+            }
+        }
+    }
+}
+
+/// asserts every param of two chains of the same layout is within `tolerance`
+#[cfg(test)]
+pub(crate) fn assert_same_params(a: &[Box<dyn Layer>], b: &[Box<dyn Layer>], tolerance: f64) {
+    for (la, lb) in a.iter().zip(b.iter()) {
+        for (pa, pb) in la.params().into_iter().zip(lb.params()) {
+            for (va, vb) in pa.el.iter().flatten().zip(pb.el.iter().flatten()) {
+                assert!((va - vb).abs() < tolerance, "{} != {}", va, vb);
+            }
+        }
+    }
+}
+
+/// checks input and parameter gradients of a layer in training mode
+/// against central differences of sum(output * weight), with random
+/// params and a fixed weight for every output
+#[cfg(test)]
+fn check_grads(layer: &mut dyn Layer, input: &Matrix<f64>) {
+    let mut weight = Matrix::new(input.len_row(), layer.output_size());
+    for (ridx, row) in weight.el.iter_mut().enumerate() {
+        for (cidx, col) in row.iter_mut().enumerate() {
+            *col = ((ridx * 7 + cidx * 3) % 11) as f64 / 5.0 - 1.0;
+        }
+    }
+    let eval = |layer: &mut dyn Layer, input: &Matrix<f64>| {
+        let mut output = layer.forward(input);
+        output.hadamard(&weight);
+        output.el.iter().flatten().sum::<f64>()
+    };
+
+    layer.set_training(true);
+    for param in layer.params_mut() {
+        param.rand();
+    }
+    layer.zero_grads();
+    eval(layer, input);
+    let input_grad = layer.backward(&weight);
+    let param_grads: Vec<Matrix<f64>> = layer.grads().into_iter().cloned().collect();
+
+    let eps = 1e-6;
+    for row in 0..input.len_row() {
+        for col in 0..input.len_col() {
+            let mut plus = input.clone();
+            *plus.at_mut(row, col) += eps;
+            let mut minus = input.clone();
+            *minus.at_mut(row, col) -= eps;
+            let numeric = (eval(layer, &plus) - eval(layer, &minus)) / (2.0 * eps);
+            assert!((numeric - input_grad.at(row, col)).abs() < 1e-6);
+        }
+    }
+    for (pidx, grad) in param_grads.iter().enumerate() {
+        for row in 0..grad.len_row() {
+            for col in 0..grad.len_col() {
+                let saved = layer.params()[pidx].at(row, col);
+                *layer.params_mut()[pidx].at_mut(row, col) = saved + eps;
+                let plus = eval(layer, input);
+                *layer.params_mut()[pidx].at_mut(row, col) = saved - eps;
+                let minus = eval(layer, input);
+                *layer.params_mut()[pidx].at_mut(row, col) = saved;
+                let numeric = (plus - minus) / (2.0 * eps);
+                assert!(
+                    (numeric - grad.at(row, col)).abs() < 1e-6,
+                    "{}: {} != {}",
+                    layer.name(),
+                    numeric,
+                    grad.at(row, col)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,459 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::matrix::matrix::{Matrix, __Matrix};
+
+use super::{Layer, LayerData, ParamKind};
+
+/// kind of normalization inserted after hidden layers
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum Normalization {
+    Batch,
+    Layer,
+}
+
+impl Normalization {
+    pub fn build(&self, size: usize) -> Box<dyn Layer> {
+        match self {
+            Normalization::Batch => Box::new(BatchNorm::new(size)),
+            Normalization::Layer => Box::new(LayerNorm::new(size)),
+        }
+    }
+}
+
+/// values kept by a normalizing forward for its backward
+#[derive(Debug, Clone)]
+struct NormCache {
+    /// normalized input
+    xhat: Matrix<f64>,
+    /// 1 / sqrt(var + eps) of every normalized group
+    std_inv: Vec<f64>,
+}
+
+impl NormCache {
+    /// scaled and shifted normalized input
+    fn output(&self, gamma: &Matrix<f64>, beta: &Matrix<f64>) -> Matrix<f64> {
+        let mut output = self.xhat.clone();
+        for row in output.el.iter_mut() {
+            for (cidx, col) in row.iter_mut().enumerate() {
+                *col = *col * gamma.at(0, cidx) + beta.at(0, cidx);
+            }
+        }
+        output
+    }
+}
+
+/// dcost/dx of x normalized over a group of n values, given
+/// dcost/dxhat (`dxhat`) and normalized values (`xhat`) of the group
+fn normalize_backward(dxhat: &[f64], xhat: &[f64], std_inv: f64) -> Vec<f64> {
+    let n = dxhat.len() as f64;
+    let sum_dxhat: f64 = dxhat.iter().sum();
+    let sum_dxhat_xhat: f64 = dxhat.iter().zip(xhat).map(|(d, x)| d * x).sum();
+    dxhat
+        .iter()
+        .zip(xhat)
+        .map(|(d, x)| std_inv / n * (n * d - sum_dxhat - x * sum_dxhat_xhat))
+        .collect()
+}
+
+/// normalizes every feature over the batch.
+///
+/// training uses statistics of the current batch and updates running
+/// mean/variance, which are used instead on inference.
+#[derive(Debug, Clone)]
+pub struct BatchNorm {
+    /// scale (1 x size)
+    pub gamma: Matrix<f64>,
+    /// shift (1 x size)
+    pub beta: Matrix<f64>,
+    pub running_mean: Matrix<f64>,
+    pub running_var: Matrix<f64>,
+    /// weight of old running statistics on update
+    pub momentum: f64,
+    pub eps: f64,
+    training: bool,
+    cache: Option<NormCache>,
+    gamma_grad: Matrix<f64>,
+    beta_grad: Matrix<f64>,
+}
+
+impl BatchNorm {
+    pub fn new(size: usize) -> Self {
+        let mut gamma = Matrix::new(1, size);
+        gamma.fill(1.0);
+        let mut running_var = Matrix::new(1, size);
+        running_var.fill(1.0);
+        Self::from(
+            gamma,
+            Matrix::new(1, size),
+            Matrix::new(1, size),
+            running_var,
+            0.9,
+            1e-5,
+        )
+    }
+
+    pub fn from(
+        gamma: Matrix<f64>,
+        beta: Matrix<f64>,
+        running_mean: Matrix<f64>,
+        running_var: Matrix<f64>,
+        momentum: f64,
+        eps: f64,
+    ) -> Self {
+        let size = gamma.len_col();
+        assert!(beta.len_col() == size);
+        assert!(running_mean.len_col() == size);
+        assert!(running_var.len_col() == size);
+        BatchNorm {
+            gamma,
+            beta,
+            running_mean,
+            running_var,
+            momentum,
+            eps,
+            training: false,
+            cache: None,
+            gamma_grad: Matrix::new(1, size),
+            beta_grad: Matrix::new(1, size),
+        }
+    }
+
+    /// normalize every feature with given (mean, var)
+    fn normalize(&self, input: &Matrix<f64>, stats: &[(f64, f64)]) -> NormCache {
+        let mut xhat = input.clone();
+        let mut std_inv = Vec::with_capacity(stats.len());
+        for (col, (mean, var)) in stats.iter().enumerate() {
+            let inv = 1.0 / (var + self.eps).sqrt();
+            for row in 0..input.len_row() {
+                *xhat.at_mut(row, col) = (input.at(row, col) - mean) * inv;
+            }
+            std_inv.push(inv);
+        }
+        NormCache { xhat, std_inv }
+    }
+
+    fn running_stats(&self) -> Vec<(f64, f64)> {
+        (0..self.input_size())
+            .map(|col| (self.running_mean.at(0, col), self.running_var.at(0, col)))
+            .collect()
+    }
+}
+
+impl Layer for BatchNorm {
+    fn name(&self) -> String {
+        format!("BatchNorm({})", self.gamma.len_col())
+    }
+
+    fn input_size(&self) -> usize {
+        self.gamma.len_col()
+    }
+
+    fn output_size(&self) -> usize {
+        self.gamma.len_col()
+    }
+
+    fn forward(&mut self, input: &Matrix<f64>) -> Matrix<f64> {
+        let stats = if self.training {
+            let rows = input.len_row();
+            let mut stats = Vec::with_capacity(self.input_size());
+            for col in 0..self.input_size() {
+                let mean = input.col(col).iter().copied().sum::<f64>() / rows as f64;
+                let var = input
+                    .col(col)
+                    .iter()
+                    .map(|x| (*x - mean).powi(2))
+                    .sum::<f64>()
+                    / rows as f64;
+
+                let running_mean = self.running_mean.at_mut(0, col);
+                *running_mean = self.momentum * *running_mean + (1.0 - self.momentum) * mean;
+                let running_var = self.running_var.at_mut(0, col);
+                *running_var = self.momentum * *running_var + (1.0 - self.momentum) * var;
+                stats.push((mean, var));
+            }
+            stats
+        } else {
+            self.running_stats()
+        };
+
+        let cache = self.normalize(input, &stats);
+        let output = cache.output(&self.gamma, &self.beta);
+        self.cache = Some(cache);
+        output
+    }
+
+    /// uses running statistics
+    fn infer(&self, input: &Matrix<f64>) -> Matrix<f64> {
+        self.normalize(input, &self.running_stats())
+            .output(&self.gamma, &self.beta)
+    }
+
+    fn backward(&mut self, grad: &Matrix<f64>) -> Matrix<f64> {
+        let cache = self
+            .cache
+            .as_ref()
+            .expect("BatchNorm>>backward: forward first");
+        let mut input_grad = Matrix::new(grad.len_row(), self.input_size());
+
+        for col in 0..self.input_size() {
+            let gamma = self.gamma.at(0, col);
+            let dy: Vec<f64> = grad.col(col).into_iter().copied().collect();
+            let xhat: Vec<f64> = cache.xhat.col(col).into_iter().copied().collect();
+
+            *self.beta_grad.at_mut(0, col) += dy.iter().sum::<f64>();
+            *self.gamma_grad.at_mut(0, col) +=
+                dy.iter().zip(xhat.iter()).map(|(d, x)| d * x).sum::<f64>();
+
+            let dxhat: Vec<f64> = dy.iter().map(|d| d * gamma).collect();
+            let dx = if self.training {
+                normalize_backward(&dxhat, &xhat, cache.std_inv[col])
+            } else {
+                // running statistics are constants on inference
+                dxhat.iter().map(|d| d * cache.std_inv[col]).collect()
+            };
+            for (row, d) in dx.into_iter().enumerate() {
+                *input_grad.at_mut(row, col) = d;
+            }
+        }
+        input_grad
+    }
+
+    fn params(&self) -> Vec<&Matrix<f64>> {
+        vec![&self.gamma, &self.beta]
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut Matrix<f64>> {
+        vec![&mut self.gamma, &mut self.beta]
+    }
+
+    /// scale and shift are not penalized like weights
+    fn param_kinds(&self) -> Vec<ParamKind> {
+        vec![ParamKind::Bias, ParamKind::Bias]
+    }
+
+    fn grads(&self) -> Vec<&Matrix<f64>> {
+        vec![&self.gamma_grad, &self.beta_grad]
+    }
+
+    fn zero_grads(&mut self) {
+        self.gamma_grad.fill(0.0);
+        self.beta_grad.fill(0.0);
+    }
+
+    /// identity scale and shift, with fresh running statistics
+    fn rand(&mut self) {
+        self.gamma.fill(1.0);
+        self.beta.fill(0.0);
+        self.running_mean.fill(0.0);
+        self.running_var.fill(1.0);
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+
+    fn batch_dependent(&self) -> bool {
+        true
+    }
+
+    fn serialize(&self) -> LayerData {
+        LayerData::BatchNorm {
+            gamma: self.gamma.clone(),
+            beta: self.beta.clone(),
+            running_mean: self.running_mean.clone(),
+            running_var: self.running_var.clone(),
+            momentum: self.momentum,
+            eps: self.eps,
+        }
+    }
+}
+
+/// normalizes every sample over its features,
+/// behaves the same on training and inference
+#[derive(Debug, Clone)]
+pub struct LayerNorm {
+    /// scale (1 x size)
+    pub gamma: Matrix<f64>,
+    /// shift (1 x size)
+    pub beta: Matrix<f64>,
+    pub eps: f64,
+    cache: Option<NormCache>,
+    gamma_grad: Matrix<f64>,
+    beta_grad: Matrix<f64>,
+}
+
+impl LayerNorm {
+    pub fn new(size: usize) -> Self {
+        let mut gamma = Matrix::new(1, size);
+        gamma.fill(1.0);
+        Self::from(gamma, Matrix::new(1, size), 1e-5)
+    }
+
+    pub fn from(gamma: Matrix<f64>, beta: Matrix<f64>, eps: f64) -> Self {
+        let size = gamma.len_col();
+        assert!(beta.len_col() == size);
+        LayerNorm {
+            gamma,
+            beta,
+            eps,
+            cache: None,
+            gamma_grad: Matrix::new(1, size),
+            beta_grad: Matrix::new(1, size),
+        }
+    }
+
+    /// normalize every row over its own values
+    fn normalize(&self, input: &Matrix<f64>) -> NormCache {
+        let n = self.input_size() as f64;
+        let mut xhat = input.clone();
+        let mut std_inv = Vec::with_capacity(input.len_row());
+
+        for row in xhat.el.iter_mut() {
+            let mean = row.iter().sum::<f64>() / n;
+            let var = row.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+            let inv = 1.0 / (var + self.eps).sqrt();
+            for col in row.iter_mut() {
+                *col = (*col - mean) * inv;
+            }
+            std_inv.push(inv);
+        }
+        NormCache { xhat, std_inv }
+    }
+}
+
+impl Layer for LayerNorm {
+    fn name(&self) -> String {
+        format!("LayerNorm({})", self.gamma.len_col())
+    }
+
+    fn input_size(&self) -> usize {
+        self.gamma.len_col()
+    }
+
+    fn output_size(&self) -> usize {
+        self.gamma.len_col()
+    }
+
+    fn forward(&mut self, input: &Matrix<f64>) -> Matrix<f64> {
+        let cache = self.normalize(input);
+        let output = cache.output(&self.gamma, &self.beta);
+        self.cache = Some(cache);
+        output
+    }
+
+    fn infer(&self, input: &Matrix<f64>) -> Matrix<f64> {
+        self.normalize(input).output(&self.gamma, &self.beta)
+    }
+
+    fn backward(&mut self, grad: &Matrix<f64>) -> Matrix<f64> {
+        let cache = self
+            .cache
+            .as_ref()
+            .expect("LayerNorm>>backward: forward first");
+        let mut input_grad = Matrix::new(grad.len_row(), self.input_size());
+
+        for row in 0..grad.len_row() {
+            let dy = grad.row(row);
+            let xhat = cache.xhat.row(row);
+            let mut dxhat = Vec::with_capacity(dy.len());
+            for col in 0..self.input_size() {
+                *self.beta_grad.at_mut(0, col) += dy[col];
+                *self.gamma_grad.at_mut(0, col) += dy[col] * xhat[col];
+                dxhat.push(dy[col] * self.gamma.at(0, col));
+            }
+            let dx = normalize_backward(&dxhat, xhat, cache.std_inv[row]);
+            input_grad.row_mut(row).copy_from_slice(&dx);
+        }
+        input_grad
+    }
+
+    fn params(&self) -> Vec<&Matrix<f64>> {
+        vec![&self.gamma, &self.beta]
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut Matrix<f64>> {
+        vec![&mut self.gamma, &mut self.beta]
+    }
+
+    /// scale and shift are not penalized like weights
+    fn param_kinds(&self) -> Vec<ParamKind> {
+        vec![ParamKind::Bias, ParamKind::Bias]
+    }
+
+    fn grads(&self) -> Vec<&Matrix<f64>> {
+        vec![&self.gamma_grad, &self.beta_grad]
+    }
+
+    fn zero_grads(&mut self) {
+        self.gamma_grad.fill(0.0);
+        self.beta_grad.fill(0.0);
+    }
+
+    /// identity scale and shift
+    fn rand(&mut self) {
+        self.gamma.fill(1.0);
+        self.beta.fill(0.0);
+    }
+
+    fn serialize(&self) -> LayerData {
+        LayerData::LayerNorm {
+            gamma: self.gamma.clone(),
+            beta: self.beta.clone(),
+            eps: self.eps,
+        }
+    }
+}
+
+#[cfg(test)]
+fn norm_input() -> Matrix<f64> {
+    Matrix::from_rows(&[
+        vec![0.3, -1.2, 0.8],
+        vec![1.5, 0.4, -0.2],
+        vec![-0.7, 0.9, 0.1],
+        vec![0.2, 0.0, 1.1],
+    ])
+}
+
+#[test]
+fn norm_test_batch_norm_grads() {
+    super::check_grads(&mut BatchNorm::new(3), &norm_input());
+}
+
+#[test]
+fn norm_test_layer_norm_grads() {
+    super::check_grads(&mut LayerNorm::new(3), &norm_input());
+}
+
+#[test]
+fn norm_test_batch_norm_running_stats() {
+    let mut norm = BatchNorm::new(1);
+    norm.momentum = 0.0;
+    norm.set_training(true);
+    norm.forward(&Matrix::from_rows(&[vec![1.0], vec![3.0]]));
+    assert_eq!(norm.running_mean.at(0, 0), 2.0);
+    assert_eq!(norm.running_var.at(0, 0), 1.0);
+
+    // inference uses running statistics, so a single sample is fine
+    norm.set_training(false);
+    let output = norm.forward(&Matrix::from_rows(&[vec![3.0]]));
+    assert!((output.at(0, 0) - 1.0).abs() < 1e-4);
+}
+
+#[test]
+fn norm_test_rand_keeps_identity() {
+    let mut batch = BatchNorm::new(3);
+    batch.set_training(true);
+    batch.forward(&norm_input());
+    batch.rand();
+    assert_eq!(batch.gamma.row(0), &[1.0, 1.0, 1.0]);
+    assert_eq!(batch.beta.row(0), &[0.0, 0.0, 0.0]);
+    assert_eq!(batch.running_mean.row(0), &[0.0, 0.0, 0.0]);
+    assert_eq!(batch.running_var.row(0), &[1.0, 1.0, 1.0]);
+
+    let mut layer = LayerNorm::new(3);
+    layer.gamma.rand();
+    layer.rand();
+    assert_eq!(layer.gamma.row(0), &[1.0, 1.0, 1.0]);
+    assert_eq!(layer.beta.row(0), &[0.0, 0.0, 0.0]);
+}
@@ -0,0 +1,301 @@
+use crate::core::matrix::matrix::{Matrix, __Matrix};
+
+use super::{Layer, LayerData, Shape};
+
+/// how a pooling window is reduced
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Reduce {
+    Max,
+    Avg,
+}
+
+/// shared implementation of 2D pooling layers.
+/// windows never cross channels and never overflow the input
+#[derive(Debug, Clone)]
+struct Pool2D {
+    input: Shape,
+    size: usize,
+    stride: usize,
+    reduce: Reduce,
+    /// for every sample and output, index of input it was taken from
+    /// (only filled on max pooling)
+    argmax: Vec<Vec<usize>>,
+}
+
+impl Pool2D {
+    fn new(input: Shape, size: usize, stride: usize, reduce: Reduce) -> Self {
+        assert!(size > 0 && stride > 0);
+        assert!(input.height >= size && input.width >= size);
+        Pool2D {
+            input,
+            size,
+            stride,
+            reduce,
+            argmax: Vec::new(),
+        }
+    }
+
+    fn output_shape(&self) -> Shape {
+        Shape {
+            channels: self.input.channels,
+            height: (self.input.height - self.size) / self.stride + 1,
+            width: (self.input.width - self.size) / self.stride + 1,
+        }
+    }
+
+    /// input indices covered by window of given output
+    fn window(&self, channel: usize, oy: usize, ox: usize) -> Vec<usize> {
+        let mut window = Vec::with_capacity(self.size * self.size);
+        for ky in 0..self.size {
+            for kx in 0..self.size {
+                window.push(self.input.index(
+                    channel,
+                    oy * self.stride + ky,
+                    ox * self.stride + kx,
+                ));
+            }
+        }
+        window
+    }
+
+    /// pooled output and index of the max of every window
+    fn pool(&self, input: &Matrix<f64>) -> (Matrix<f64>, Vec<Vec<usize>>) {
+        let shape = self.output_shape();
+        let mut output = Matrix::new(input.len_row(), shape.size());
+        let mut argmax = vec![vec![0; shape.size()]; input.len_row()];
+
+        for (sample, sample_argmax) in argmax.iter_mut().enumerate() {
+            let row = input.row(sample);
+            for channel in 0..shape.channels {
+                for oy in 0..shape.height {
+                    for ox in 0..shape.width {
+                        let oidx = shape.index(channel, oy, ox);
+                        let window = self.window(channel, oy, ox);
+                        *output.at_mut(sample, oidx) = match self.reduce {
+                            Reduce::Max => {
+                                let mut best = window[0];
+                                for idx in window {
+                                    if row[idx] > row[best] {
+                                        best = idx;
+                                    }
+                                }
+                                sample_argmax[oidx] = best;
+                                row[best]
+                            }
+                            Reduce::Avg => {
+                                window.iter().map(|idx| row[*idx]).sum::<f64>()
+                                    / (self.size * self.size) as f64
+                            }
+                        };
+                    }
+                }
+            }
+        }
+        (output, argmax)
+    }
+
+    fn forward(&mut self, input: &Matrix<f64>) -> Matrix<f64> {
+        let (output, argmax) = self.pool(input);
+        self.argmax = argmax;
+        output
+    }
+
+    fn backward(&mut self, grad: &Matrix<f64>) -> Matrix<f64> {
+        let shape = self.output_shape();
+        let mut input_grad = Matrix::new(grad.len_row(), self.input.size());
+
+        for sample in 0..grad.len_row() {
+            for channel in 0..shape.channels {
+                for oy in 0..shape.height {
+                    for ox in 0..shape.width {
+                        let oidx = shape.index(channel, oy, ox);
+                        let g = grad.at(sample, oidx);
+                        match self.reduce {
+                            Reduce::Max => {
+                                *input_grad.at_mut(sample, self.argmax[sample][oidx]) += g;
+                            }
+                            Reduce::Avg => {
+                                let share = g / (self.size * self.size) as f64;
+                                for idx in self.window(channel, oy, ox) {
+                                    *input_grad.at_mut(sample, idx) += share;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        input_grad
+    }
+}
+
+/// 2D max pooling
+#[derive(Debug, Clone)]
+pub struct MaxPool2D {
+    pool: Pool2D,
+}
+
+impl MaxPool2D {
+    pub fn new(input: Shape, size: usize, stride: usize) -> Self {
+        MaxPool2D {
+            pool: Pool2D::new(input, size, stride, Reduce::Max),
+        }
+    }
+
+    pub fn output_shape(&self) -> Shape {
+        self.pool.output_shape()
+    }
+}
+
+/// 2D average pooling
+#[derive(Debug, Clone)]
+pub struct AvgPool2D {
+    pool: Pool2D,
+}
+
+impl AvgPool2D {
+    pub fn new(input: Shape, size: usize, stride: usize) -> Self {
+        AvgPool2D {
+            pool: Pool2D::new(input, size, stride, Reduce::Avg),
+        }
+    }
+
+    pub fn output_shape(&self) -> Shape {
+        self.pool.output_shape()
+    }
+}
+
+impl Layer for MaxPool2D {
+    fn name(&self) -> String {
+        format!(
+            "MaxPool2D({}->{}, k{} s{})",
+            self.pool.input,
+            self.output_shape(),
+            self.pool.size,
+            self.pool.stride
+        )
+    }
+
+    fn input_size(&self) -> usize {
+        self.pool.input.size()
+    }
+
+    fn output_size(&self) -> usize {
+        self.output_shape().size()
+    }
+
+    fn forward(&mut self, input: &Matrix<f64>) -> Matrix<f64> {
+        self.pool.forward(input)
+    }
+
+    fn infer(&self, input: &Matrix<f64>) -> Matrix<f64> {
+        self.pool.pool(input).0
+    }
+
+    fn backward(&mut self, grad: &Matrix<f64>) -> Matrix<f64> {
+        self.pool.backward(grad)
+    }
+
+    fn params(&self) -> Vec<&Matrix<f64>> {
+        Vec::new()
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut Matrix<f64>> {
+        Vec::new()
+    }
+
+    fn grads(&self) -> Vec<&Matrix<f64>> {
+        Vec::new()
+    }
+
+    fn zero_grads(&mut self) {}
+
+    fn serialize(&self) -> LayerData {
+        LayerData::MaxPool2D {
+            input: self.pool.input,
+            size: self.pool.size,
+            stride: self.pool.stride,
+        }
+    }
+}
+
+impl Layer for AvgPool2D {
+    fn name(&self) -> String {
+        format!(
+            "AvgPool2D({}->{}, k{} s{})",
+            self.pool.input,
+            self.output_shape(),
+            self.pool.size,
+            self.pool.stride
+        )
+    }
+
+    fn input_size(&self) -> usize {
+        self.pool.input.size()
+    }
+
+    fn output_size(&self) -> usize {
+        self.output_shape().size()
+    }
+
+    fn forward(&mut self, input: &Matrix<f64>) -> Matrix<f64> {
+        self.pool.forward(input)
+    }
+
+    fn infer(&self, input: &Matrix<f64>) -> Matrix<f64> {
+        self.pool.pool(input).0
+    }
+
+    fn backward(&mut self, grad: &Matrix<f64>) -> Matrix<f64> {
+        self.pool.backward(grad)
+    }
+
+    fn params(&self) -> Vec<&Matrix<f64>> {
+        Vec::new()
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut Matrix<f64>> {
+        Vec::new()
+    }
+
+    fn grads(&self) -> Vec<&Matrix<f64>> {
+        Vec::new()
+    }
+
+    fn zero_grads(&mut self) {}
+
+    fn serialize(&self) -> LayerData {
+        LayerData::AvgPool2D {
+            input: self.pool.input,
+            size: self.pool.size,
+            stride: self.pool.stride,
+        }
+    }
+}
+
+#[test]
+fn pool_test_forward_backward() {
+    let input = Matrix::from_rows(&[vec![
+        1.0, 2.0, 3.0, 4.0, //
+        5.0, 6.0, 7.0, 8.0, //
+        9.0, 1.0, 2.0, 3.0, //
+        4.0, 5.0, 6.0, 0.0,
+    ]]);
+    let mut max = MaxPool2D::new(Shape::new(1, 4, 4), 2, 2);
+    let mut avg = AvgPool2D::new(Shape::new(1, 4, 4), 2, 2);
+
+    assert_eq!(max.forward(&input).row(0), &[6.0, 8.0, 9.0, 6.0]);
+    assert_eq!(avg.forward(&input).row(0), &[3.5, 5.5, 4.75, 2.75]);
+
+    let grad = Matrix::from_rows(&[vec![1.0, 2.0, 3.0, 4.0]]);
+    let max_grad = max.backward(&grad);
+    assert_eq!(max_grad.at(0, 5), 1.0);
+    assert_eq!(max_grad.at(0, 7), 2.0);
+    assert_eq!(max_grad.at(0, 8), 3.0);
+    assert_eq!(max_grad.at(0, 14), 4.0);
+    assert_eq!(max_grad.row(0).iter().sum::<f64>(), 10.0);
+
+    let avg_grad = avg.backward(&grad);
+    assert_eq!(avg_grad.at(0, 0), 0.25);
+    assert_eq!(avg_grad.at(0, 15), 1.0);
+}
@@ -0,0 +1,686 @@
+use crate::core::common::sigmoid::sigmoid_f64;
+use crate::core::matrix::matrix::{Matrix, __Matrix};
+
+use super::{Layer, LayerData, ParamKind};
+
+/// how a recurrent step turns input and hidden state into a new state
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cell {
+    /// h' = tanh(x.W + h.U + b)
+    Rnn,
+    /// gates (z, r, n), h' = (1 - z) * n + z * h
+    Gru,
+    /// gates (i, f, g, o), c' = f * c + i * g, h' = o * tanh(c')
+    Lstm,
+}
+
+impl Cell {
+    /// number of hidden-sized blocks in weights
+    fn gates(&self) -> usize {
+        match self {
+            Cell::Rnn => 1,
+            Cell::Gru => 3,
+            Cell::Lstm => 4,
+        }
+    }
+}
+
+/// values of a single step kept for backpropagation through time
+#[derive(Debug, Clone)]
+struct Step {
+    x: Vec<f64>,
+    h_prev: Vec<f64>,
+    c_prev: Vec<f64>,
+    /// activated gates
+    gates: Vec<f64>,
+    /// new cell state (LSTM) or h.U of candidate (GRU)
+    extra: Vec<f64>,
+}
+
+/// add x.w into out
+fn affine(x: &[f64], w: &Matrix<f64>, out: &mut [f64]) {
+    for (xi, row) in x.iter().zip(w.el.iter()) {
+        for (o, wij) in out.iter_mut().zip(row.iter()) {
+            *o += xi * wij;
+        }
+    }
+}
+
+/// backward of `affine`: accumulate dcost/dw and add w.dout into dx
+fn affine_back(x: &[f64], w: &Matrix<f64>, dout: &[f64], w_grad: &mut Matrix<f64>, dx: &mut [f64]) {
+    for (idx, xi) in x.iter().enumerate() {
+        let row = w.row(idx);
+        let grad_row = w_grad.row_mut(idx);
+        for (jdx, d) in dout.iter().enumerate() {
+            grad_row[jdx] += xi * d;
+            dx[idx] += row[jdx] * d;
+        }
+    }
+}
+
+/// shared implementation of recurrent layers.
+/// a row holds `steps` timesteps of `features` values each, in time order.
+/// output is the last hidden state, or every hidden state in time order
+/// when `return_sequences` is set
+#[derive(Debug, Clone)]
+struct Recurrent {
+    cell: Cell,
+    steps: usize,
+    /// (features x gates*hidden)
+    weights: Matrix<f64>,
+    /// (hidden x gates*hidden)
+    recurrent: Matrix<f64>,
+    /// (1 x gates*hidden)
+    biases: Matrix<f64>,
+    return_sequences: bool,
+    /// steps of every sample of last forward
+    cache: Vec<Vec<Step>>,
+    weights_grad: Matrix<f64>,
+    recurrent_grad: Matrix<f64>,
+    biases_grad: Matrix<f64>,
+}
+
+impl Recurrent {
+    fn new(
+        cell: Cell,
+        steps: usize,
+        weights: Matrix<f64>,
+        recurrent: Matrix<f64>,
+        biases: Matrix<f64>,
+        return_sequences: bool,
+    ) -> Self {
+        let hidden = recurrent.len_row();
+        assert!(steps > 0);
+        assert!(recurrent.len_col() == cell.gates() * hidden);
+        assert!(weights.len_col() == cell.gates() * hidden);
+        assert!(biases.len_row() == 1 && biases.len_col() == cell.gates() * hidden);
+        Recurrent {
+            cell,
+            steps,
+            weights_grad: Matrix::new(weights.len_row(), weights.len_col()),
+            recurrent_grad: Matrix::new(hidden, recurrent.len_col()),
+            biases_grad: Matrix::new(1, biases.len_col()),
+            weights,
+            recurrent,
+            biases,
+            return_sequences,
+            cache: Vec::new(),
+        }
+    }
+
+    fn empty(
+        cell: Cell,
+        steps: usize,
+        features: usize,
+        hidden: usize,
+        return_sequences: bool,
+    ) -> Self {
+        let width = cell.gates() * hidden;
+        Self::new(
+            cell,
+            steps,
+            Matrix::new(features, width),
+            Matrix::new(hidden, width),
+            Matrix::new(1, width),
+            return_sequences,
+        )
+    }
+
+    fn features(&self) -> usize {
+        self.weights.len_row()
+    }
+
+    fn hidden(&self) -> usize {
+        self.recurrent.len_row()
+    }
+
+    fn name(&self, kind: &str) -> String {
+        format!(
+            "{}({}x{}->{}{})",
+            kind,
+            self.steps,
+            self.features(),
+            self.hidden(),
+            if self.return_sequences { ", seq" } else { "" }
+        )
+    }
+
+    fn input_size(&self) -> usize {
+        self.steps * self.features()
+    }
+
+    fn output_size(&self) -> usize {
+        if self.return_sequences {
+            self.steps * self.hidden()
+        } else {
+            self.hidden()
+        }
+    }
+
+    /// single step, returns new hidden state, new cell state and kept values
+    fn step(&self, x: &[f64], h: &[f64], c: &[f64]) -> (Vec<f64>, Vec<f64>, Step) {
+        let hsize = self.hidden();
+        let mut zx = self.biases.row(0).to_vec();
+        affine(x, &self.weights, &mut zx);
+        let mut zh = vec![0.0; zx.len()];
+        affine(h, &self.recurrent, &mut zh);
+
+        let (h_new, c_new, gates, extra) = match self.cell {
+            Cell::Rnn => {
+                let a: Vec<f64> = zx
+                    .iter()
+                    .zip(zh.iter())
+                    .map(|(x, h)| (x + h).tanh())
+                    .collect();
+                (a.clone(), c.to_vec(), a, Vec::new())
+            }
+            Cell::Gru => {
+                let mut gates = vec![0.0; 3 * hsize];
+                let mut h_new = vec![0.0; hsize];
+                for k in 0..hsize {
+                    let z = sigmoid_f64(zx[k] + zh[k]);
+                    let r = sigmoid_f64(zx[hsize + k] + zh[hsize + k]);
+                    let n = (zx[2 * hsize + k] + r * zh[2 * hsize + k]).tanh();
+                    h_new[k] = (1.0 - z) * n + z * h[k];
+                    gates[k] = z;
+                    gates[hsize + k] = r;
+                    gates[2 * hsize + k] = n;
+                }
+                (h_new, c.to_vec(), gates, zh[2 * hsize..].to_vec())
+            }
+            Cell::Lstm => {
+                let mut gates = vec![0.0; 4 * hsize];
+                let mut h_new = vec![0.0; hsize];
+                let mut c_new = vec![0.0; hsize];
+                for k in 0..hsize {
+                    let i = sigmoid_f64(zx[k] + zh[k]);
+                    let f = sigmoid_f64(zx[hsize + k] + zh[hsize + k]);
+                    let g = (zx[2 * hsize + k] + zh[2 * hsize + k]).tanh();
+                    let o = sigmoid_f64(zx[3 * hsize + k] + zh[3 * hsize + k]);
+                    c_new[k] = f * c[k] + i * g;
+                    h_new[k] = o * c_new[k].tanh();
+                    gates[k] = i;
+                    gates[hsize + k] = f;
+                    gates[2 * hsize + k] = g;
+                    gates[3 * hsize + k] = o;
+                }
+                (h_new, c_new.clone(), gates, c_new)
+            }
+        };
+
+        let step = Step {
+            x: x.to_vec(),
+            h_prev: h.to_vec(),
+            c_prev: c.to_vec(),
+            gates,
+            extra,
+        };
+        (h_new, c_new, step)
+    }
+
+    /// backward of a single step given dcost/dh' and dcost/dc'.
+    /// returns dcost/dz of input side and of hidden side (both gates*hidden),
+    /// plus the part of dcost/dh which does not go through h.U, and dcost/dc
+    fn step_back(
+        &self,
+        step: &Step,
+        dh: &[f64],
+        dc: &[f64],
+    ) -> (Vec<f64>, Vec<f64>, Vec<f64>, Vec<f64>) {
+        let hsize = self.hidden();
+        match self.cell {
+            Cell::Rnn => {
+                let dz: Vec<f64> = dh
+                    .iter()
+                    .zip(step.gates.iter())
+                    .map(|(d, a)| d * (1.0 - a * a))
+                    .collect();
+                (dz.clone(), dz, vec![0.0; hsize], vec![0.0; hsize])
+            }
+            Cell::Gru => {
+                let mut dzx = vec![0.0; 3 * hsize];
+                let mut dzh = vec![0.0; 3 * hsize];
+                let mut dh_prev = vec![0.0; hsize];
+                for k in 0..hsize {
+                    let z = step.gates[k];
+                    let r = step.gates[hsize + k];
+                    let n = step.gates[2 * hsize + k];
+                    let dn = dh[k] * (1.0 - z) * (1.0 - n * n);
+                    let dz = dh[k] * (step.h_prev[k] - n) * z * (1.0 - z);
+                    let dr = dn * step.extra[k] * r * (1.0 - r);
+                    dh_prev[k] = dh[k] * z;
+
+                    dzx[k] = dz;
+                    dzx[hsize + k] = dr;
+                    dzx[2 * hsize + k] = dn;
+                    dzh[k] = dz;
+                    dzh[hsize + k] = dr;
+                    dzh[2 * hsize + k] = dn * r;
+                }
+                (dzx, dzh, dh_prev, vec![0.0; hsize])
+            }
+            Cell::Lstm => {
+                let mut dzx = vec![0.0; 4 * hsize];
+                let mut dc_prev = vec![0.0; hsize];
+                for k in 0..hsize {
+                    let i = step.gates[k];
+                    let f = step.gates[hsize + k];
+                    let g = step.gates[2 * hsize + k];
+                    let o = step.gates[3 * hsize + k];
+                    let tc = step.extra[k].tanh();
+                    let dc = dc[k] + dh[k] * o * (1.0 - tc * tc);
+
+                    dzx[k] = dc * g * i * (1.0 - i);
+                    dzx[hsize + k] = dc * step.c_prev[k] * f * (1.0 - f);
+                    dzx[2 * hsize + k] = dc * i * (1.0 - g * g);
+                    dzx[3 * hsize + k] = dh[k] * tc * o * (1.0 - o);
+                    dc_prev[k] = dc * f;
+                }
+                (dzx.clone(), dzx, vec![0.0; hsize], dc_prev)
+            }
+        }
+    }
+
+    /// output and steps of every sample
+    fn run(&self, input: &Matrix<f64>) -> (Matrix<f64>, Vec<Vec<Step>>) {
+        let (features, hsize) = (self.features(), self.hidden());
+        let mut output = Matrix::new(input.len_row(), self.output_size());
+        let mut cache = Vec::with_capacity(input.len_row());
+
+        for sample in 0..input.len_row() {
+            let row = input.row(sample);
+            let mut h = vec![0.0; hsize];
+            let mut c = vec![0.0; hsize];
+            let mut steps = Vec::with_capacity(self.steps);
+
+            for t in 0..self.steps {
+                let (h_new, c_new, step) =
+                    self.step(&row[t * features..(t + 1) * features], &h, &c);
+                steps.push(step);
+                h = h_new;
+                c = c_new;
+                if self.return_sequences {
+                    output.row_mut(sample)[t * hsize..(t + 1) * hsize].copy_from_slice(&h);
+                }
+            }
+            if !self.return_sequences {
+                output.row_mut(sample).copy_from_slice(&h);
+            }
+            cache.push(steps);
+        }
+        (output, cache)
+    }
+
+    fn forward(&mut self, input: &Matrix<f64>) -> Matrix<f64> {
+        let (output, cache) = self.run(input);
+        self.cache = cache;
+        output
+    }
+
+    fn backward(&mut self, grad: &Matrix<f64>) -> Matrix<f64> {
+        assert!(!self.cache.is_empty(), "Recurrent>>backward: forward first");
+        let (features, hsize) = (self.features(), self.hidden());
+        let mut input_grad = Matrix::new(grad.len_row(), self.input_size());
+        let cache = std::mem::take(&mut self.cache);
+
+        for (sample, steps) in cache.iter().enumerate() {
+            let grad = grad.row(sample);
+            let mut dh_next = vec![0.0; hsize];
+            let mut dc_next = vec![0.0; hsize];
+
+            for t in (0..self.steps).rev() {
+                let mut dh = dh_next.clone();
+                if self.return_sequences {
+                    for (d, g) in dh.iter_mut().zip(&grad[t * hsize..(t + 1) * hsize]) {
+                        *d += g;
+                    }
+                } else if t == self.steps - 1 {
+                    for (d, g) in dh.iter_mut().zip(grad.iter()) {
+                        *d += g;
+                    }
+                }
+
+                let step = &steps[t];
+                let (dzx, dzh, mut dh_prev, dc_prev) = self.step_back(step, &dh, &dc_next);
+
+                for (b, d) in self.biases_grad.row_mut(0).iter_mut().zip(dzx.iter()) {
+                    *b += d;
+                }
+                let dx = &mut input_grad.row_mut(sample)[t * features..(t + 1) * features];
+                affine_back(&step.x, &self.weights, &dzx, &mut self.weights_grad, dx);
+                affine_back(
+                    &step.h_prev,
+                    &self.recurrent,
+                    &dzh,
+                    &mut self.recurrent_grad,
+                    &mut dh_prev,
+                );
+
+                dh_next = dh_prev;
+                dc_next = dc_prev;
+            }
+        }
+        self.cache = cache;
+        input_grad
+    }
+
+    fn params(&self) -> Vec<&Matrix<f64>> {
+        vec![&self.weights, &self.recurrent, &self.biases]
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut Matrix<f64>> {
+        vec![&mut self.weights, &mut self.recurrent, &mut self.biases]
+    }
+
+    fn grads(&self) -> Vec<&Matrix<f64>> {
+        vec![&self.weights_grad, &self.recurrent_grad, &self.biases_grad]
+    }
+
+    fn zero_grads(&mut self) {
+        self.weights_grad.fill(0.0);
+        self.recurrent_grad.fill(0.0);
+        self.biases_grad.fill(0.0);
+    }
+}
+
+/// Elman recurrent layer: h' = tanh(x.W + h.U + b)
+#[derive(Debug, Clone)]
+pub struct Rnn {
+    rnn: Recurrent,
+}
+
+impl Rnn {
+    /// `steps` timesteps of `features` values per row
+    pub fn new(steps: usize, features: usize, hidden: usize, return_sequences: bool) -> Self {
+        Rnn {
+            rnn: Recurrent::empty(Cell::Rnn, steps, features, hidden, return_sequences),
+        }
+    }
+
+    pub fn from(
+        steps: usize,
+        weights: Matrix<f64>,
+        recurrent: Matrix<f64>,
+        biases: Matrix<f64>,
+        return_sequences: bool,
+    ) -> Self {
+        Rnn {
+            rnn: Recurrent::new(
+                Cell::Rnn,
+                steps,
+                weights,
+                recurrent,
+                biases,
+                return_sequences,
+            ),
+        }
+    }
+}
+
+/// gated recurrent unit, gates ordered (update, reset, candidate)
+#[derive(Debug, Clone)]
+pub struct Gru {
+    rnn: Recurrent,
+}
+
+impl Gru {
+    /// `steps` timesteps of `features` values per row
+    pub fn new(steps: usize, features: usize, hidden: usize, return_sequences: bool) -> Self {
+        Gru {
+            rnn: Recurrent::empty(Cell::Gru, steps, features, hidden, return_sequences),
+        }
+    }
+
+    pub fn from(
+        steps: usize,
+        weights: Matrix<f64>,
+        recurrent: Matrix<f64>,
+        biases: Matrix<f64>,
+        return_sequences: bool,
+    ) -> Self {
+        Gru {
+            rnn: Recurrent::new(
+                Cell::Gru,
+                steps,
+                weights,
+                recurrent,
+                biases,
+                return_sequences,
+            ),
+        }
+    }
+}
+
+/// long short-term memory, gates ordered (input, forget, cell, output)
+#[derive(Debug, Clone)]
+pub struct Lstm {
+    rnn: Recurrent,
+}
+
+impl Lstm {
+    /// `steps` timesteps of `features` values per row
+    pub fn new(steps: usize, features: usize, hidden: usize, return_sequences: bool) -> Self {
+        Lstm {
+            rnn: Recurrent::empty(Cell::Lstm, steps, features, hidden, return_sequences),
+        }
+    }
+
+    pub fn from(
+        steps: usize,
+        weights: Matrix<f64>,
+        recurrent: Matrix<f64>,
+        biases: Matrix<f64>,
+        return_sequences: bool,
+    ) -> Self {
+        Lstm {
+            rnn: Recurrent::new(
+                Cell::Lstm,
+                steps,
+                weights,
+                recurrent,
+                biases,
+                return_sequences,
+            ),
+        }
+    }
+}
+
+impl Layer for Rnn {
+    fn name(&self) -> String {
+        self.rnn.name("Rnn")
+    }
+
+    fn input_size(&self) -> usize {
+        self.rnn.input_size()
+    }
+
+    fn output_size(&self) -> usize {
+        self.rnn.output_size()
+    }
+
+    fn forward(&mut self, input: &Matrix<f64>) -> Matrix<f64> {
+        self.rnn.forward(input)
+    }
+
+    fn infer(&self, input: &Matrix<f64>) -> Matrix<f64> {
+        self.rnn.run(input).0
+    }
+
+    fn backward(&mut self, grad: &Matrix<f64>) -> Matrix<f64> {
+        self.rnn.backward(grad)
+    }
+
+    fn params(&self) -> Vec<&Matrix<f64>> {
+        self.rnn.params()
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut Matrix<f64>> {
+        self.rnn.params_mut()
+    }
+
+    fn param_kinds(&self) -> Vec<ParamKind> {
+        vec![ParamKind::Weight, ParamKind::Weight, ParamKind::Bias]
+    }
+
+    fn grads(&self) -> Vec<&Matrix<f64>> {
+        self.rnn.grads()
+    }
+
+    fn zero_grads(&mut self) {
+        self.rnn.zero_grads()
+    }
+
+    fn serialize(&self) -> LayerData {
+        LayerData::Rnn {
+            steps: self.rnn.steps,
+            weights: self.rnn.weights.clone(),
+            recurrent: self.rnn.recurrent.clone(),
+            biases: self.rnn.biases.clone(),
+            return_sequences: self.rnn.return_sequences,
+        }
+    }
+}
+
+impl Layer for Gru {
+    fn name(&self) -> String {
+        self.rnn.name("Gru")
+    }
+
+    fn input_size(&self) -> usize {
+        self.rnn.input_size()
+    }
+
+    fn output_size(&self) -> usize {
+        self.rnn.output_size()
+    }
+
+    fn forward(&mut self, input: &Matrix<f64>) -> Matrix<f64> {
+        self.rnn.forward(input)
+    }
+
+    fn infer(&self, input: &Matrix<f64>) -> Matrix<f64> {
+        self.rnn.run(input).0
+    }
+
+    fn backward(&mut self, grad: &Matrix<f64>) -> Matrix<f64> {
+        self.rnn.backward(grad)
+    }
+
+    fn params(&self) -> Vec<&Matrix<f64>> {
+        self.rnn.params()
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut Matrix<f64>> {
+        self.rnn.params_mut()
+    }
+
+    fn param_kinds(&self) -> Vec<ParamKind> {
+        vec![ParamKind::Weight, ParamKind::Weight, ParamKind::Bias]
+    }
+
+    fn grads(&self) -> Vec<&Matrix<f64>> {
+        self.rnn.grads()
+    }
+
+    fn zero_grads(&mut self) {
+        self.rnn.zero_grads()
+    }
+
+    fn serialize(&self) -> LayerData {
+        LayerData::Gru {
+            steps: self.rnn.steps,
+            weights: self.rnn.weights.clone(),
+            recurrent: self.rnn.recurrent.clone(),
+            biases: self.rnn.biases.clone(),
+            return_sequences: self.rnn.return_sequences,
+        }
+    }
+}
+
+impl Layer for Lstm {
+    fn name(&self) -> String {
+        self.rnn.name("Lstm")
+    }
+
+    fn input_size(&self) -> usize {
+        self.rnn.input_size()
+    }
+
+    fn output_size(&self) -> usize {
+        self.rnn.output_size()
+    }
+
+    fn forward(&mut self, input: &Matrix<f64>) -> Matrix<f64> {
+        self.rnn.forward(input)
+    }
+
+    fn infer(&self, input: &Matrix<f64>) -> Matrix<f64> {
+        self.rnn.run(input).0
+    }
+
+    fn backward(&mut self, grad: &Matrix<f64>) -> Matrix<f64> {
+        self.rnn.backward(grad)
+    }
+
+    fn params(&self) -> Vec<&Matrix<f64>> {
+        self.rnn.params()
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut Matrix<f64>> {
+        self.rnn.params_mut()
+    }
+
+    fn param_kinds(&self) -> Vec<ParamKind> {
+        vec![ParamKind::Weight, ParamKind::Weight, ParamKind::Bias]
+    }
+
+    fn grads(&self) -> Vec<&Matrix<f64>> {
+        self.rnn.grads()
+    }
+
+    fn zero_grads(&mut self) {
+        self.rnn.zero_grads()
+    }
+
+    fn serialize(&self) -> LayerData {
+        LayerData::Lstm {
+            steps: self.rnn.steps,
+            weights: self.rnn.weights.clone(),
+            recurrent: self.rnn.recurrent.clone(),
+            biases: self.rnn.biases.clone(),
+            return_sequences: self.rnn.return_sequences,
+        }
+    }
+}
+
+/// 2 samples of 3 steps with 2 features
+#[cfg(test)]
+fn sequence_input() -> Matrix<f64> {
+    Matrix::from_rows(&[
+        vec![0.3, -0.8, 0.5, 0.1, -0.4, 0.9],
+        vec![-0.6, 0.2, 0.7, -0.3, 0.8, 0.4],
+    ])
+}
+
+#[test]
+fn recurrent_test_bptt_grads() {
+    use super::check_grads;
+
+    for return_sequences in [false, true] {
+        check_grads(&mut Rnn::new(3, 2, 3, return_sequences), &sequence_input());
+        check_grads(&mut Gru::new(3, 2, 3, return_sequences), &sequence_input());
+        check_grads(&mut Lstm::new(3, 2, 3, return_sequences), &sequence_input());
+    }
+}
+
+#[test]
+fn recurrent_test_output_size() {
+    let mut lstm = Lstm::new(3, 2, 4, true);
+    assert_eq!(lstm.input_size(), 6);
+    assert_eq!(lstm.output_size(), 12);
+    assert_eq!(lstm.forward(&sequence_input()).len_col(), 12);
+
+    let gru = Gru::new(3, 2, 4, false);
+    assert_eq!(gru.output_size(), 4);
+    assert_eq!(gru.params()[0].len_col(), 12);
+}
@@ -0,0 +1,149 @@
+use std::fmt;
+
+use crate::core::matrix::matrix::{Matrix, __Matrix};
+
+use super::{Layer, LayerData, ParamKind};
+
+/// applies the same layer to every timestep of a row.
+///
+/// an input row holds `steps` inputs of the inner layer one after another
+/// (the layout of recurrent layers returning sequences), the output row
+/// holds the output of every step in the same order
+pub struct TimeDistributed {
+    pub steps: usize,
+    pub layer: Box<dyn Layer>,
+}
+
+impl TimeDistributed {
+    pub fn new(steps: usize, layer: Box<dyn Layer>) -> Self {
+        assert!(steps > 0);
+        TimeDistributed { steps, layer }
+    }
+
+    /// a row of every step, `width` wide
+    fn unfold(matrix: &Matrix<f64>, width: usize) -> Matrix<f64> {
+        let rows: Vec<Vec<f64>> = matrix
+            .el
+            .iter()
+            .flat_map(|row| row.chunks(width).map(|step| step.to_vec()))
+            .collect();
+        Matrix::from_rows(&rows)
+    }
+
+    /// rows of the steps of every sample laid one after another
+    fn fold(matrix: &Matrix<f64>, steps: usize) -> Matrix<f64> {
+        let rows: Vec<Vec<f64>> = matrix.el.chunks(steps).map(|row| row.concat()).collect();
+        Matrix::from_rows(&rows)
+    }
+}
+
+impl Clone for TimeDistributed {
+    fn clone(&self) -> Self {
+        TimeDistributed {
+            steps: self.steps,
+            layer: self.layer.serialize().build(),
+        }
+    }
+}
+
+impl fmt::Debug for TimeDistributed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeDistributed")
+            .field("steps", &self.steps)
+            .field("layer", &self.layer.serialize())
+            .finish()
+    }
+}
+
+impl Layer for TimeDistributed {
+    fn name(&self) -> String {
+        format!("TimeDistributed({} steps of {})", self.steps, self.layer.name())
+    }
+
+    fn input_size(&self) -> usize {
+        self.steps * self.layer.input_size()
+    }
+
+    fn output_size(&self) -> usize {
+        self.steps * self.layer.output_size()
+    }
+
+    fn forward(&mut self, input: &Matrix<f64>) -> Matrix<f64> {
+        let steps = Self::unfold(input, self.layer.input_size());
+        Self::fold(&self.layer.forward(&steps), self.steps)
+    }
+
+    fn infer(&self, input: &Matrix<f64>) -> Matrix<f64> {
+        let steps = Self::unfold(input, self.layer.input_size());
+        Self::fold(&self.layer.infer(&steps), self.steps)
+    }
+
+    fn backward(&mut self, grad: &Matrix<f64>) -> Matrix<f64> {
+        let steps = Self::unfold(grad, self.layer.output_size());
+        Self::fold(&self.layer.backward(&steps), self.steps)
+    }
+
+    fn params(&self) -> Vec<&Matrix<f64>> {
+        self.layer.params()
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut Matrix<f64>> {
+        self.layer.params_mut()
+    }
+
+    fn param_kinds(&self) -> Vec<ParamKind> {
+        self.layer.param_kinds()
+    }
+
+    fn grads(&self) -> Vec<&Matrix<f64>> {
+        self.layer.grads()
+    }
+
+    fn zero_grads(&mut self) {
+        self.layer.zero_grads();
+    }
+
+    fn rand(&mut self) {
+        self.layer.rand();
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.layer.set_training(training);
+    }
+
+    fn batch_dependent(&self) -> bool {
+        self.layer.batch_dependent()
+    }
+
+    fn serialize(&self) -> LayerData {
+        LayerData::TimeDistributed {
+            steps: self.steps,
+            layer: Box::new(self.layer.serialize()),
+        }
+    }
+}
+
+#[test]
+fn time_distributed_test_steps() {
+    use super::{check_grads, dense::Dense, Activation};
+
+    let mut layer = TimeDistributed::new(3, Box::new(Dense::new(2, 1, Activation::Sigmoid)));
+    let input = Matrix::from_rows(&[
+        vec![0.1, -0.2, 0.3, 0.4, -0.5, 0.6],
+        vec![0.7, 0.8, -0.9, 1.0, 0.0, -0.1],
+    ]);
+    check_grads(&mut layer, &input);
+
+    // every step goes through the same layer on its own
+    let output = layer.infer(&input);
+    assert_eq!(layer.output_size(), 3);
+    for (sample, row) in input.el.iter().enumerate() {
+        for (step, slice) in row.chunks(2).enumerate() {
+            let single = layer.layer.infer(&Matrix::from_rows(&[slice.to_vec()]));
+            assert_eq!(output.at(sample, step), single.at(0, 0));
+        }
+    }
+
+    let saved = layer.serialize().try_build().unwrap();
+    assert_eq!(saved.infer(&input).el, output.el);
+}
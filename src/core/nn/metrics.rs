@@ -0,0 +1,305 @@
+/// precision, recall and f1 of a class (or averaged over classes)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Score {
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+}
+
+impl Score {
+    fn new(precision: f64, recall: f64) -> Self {
+        let f1 = if precision + recall > 0.0 {
+            2.0 * precision * recall / (precision + recall)
+        } else {
+            0.0
+        };
+        Score {
+            precision,
+            recall,
+            f1,
+        }
+    }
+}
+
+/// metrics of outputs treated as classes.
+/// a single output is a binary class (thresholded at 0.5),
+/// wider outputs are one-hot classes (argmax)
+#[derive(Debug, Clone)]
+pub struct Classification {
+    pub accuracy: f64,
+    /// confusion[actual][predicted] = count
+    pub confusion: Vec<Vec<usize>>,
+    pub per_class: Vec<Score>,
+    /// unweighted average of per class scores
+    pub macro_avg: Score,
+    /// scores of counts summed over classes
+    pub micro_avg: Score,
+    /// area under roc curve, binary outputs only
+    pub roc_auc: Option<f64>,
+}
+
+/// metrics of outputs treated as real values (over every output value)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Regression {
+    pub mae: f64,
+    pub rmse: f64,
+    pub r2: f64,
+}
+
+/// evaluation of model outputs against expected outputs
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    pub regression: Regression,
+    /// None unless expects are classes (binary or one-hot)
+    pub classification: Option<Classification>,
+    /// share of output values on the right side of 0.5,
+    /// None unless every expect is 0 or 1
+    pub bit_accuracy: Option<f64>,
+}
+
+impl Metrics {
+    /// compute every metric which fits given expects
+    pub fn evaluate(outputs: &[Vec<f64>], expects: &[Vec<f64>]) -> Metrics {
+        assert!(!expects.is_empty() && outputs.len() == expects.len());
+        let binary = expects.iter().flatten().all(|e| *e == 0.0 || *e == 1.0);
+        let one_hot = binary
+            && expects
+                .iter()
+                .all(|e| e.iter().filter(|v| **v == 1.0).count() == 1);
+        let width = expects[0].len();
+
+        let classification = if binary && width == 1 {
+            let predicted: Vec<usize> = outputs.iter().map(|o| (o[0] >= 0.5) as usize).collect();
+            let actual: Vec<usize> = expects.iter().map(|e| e[0] as usize).collect();
+            let scores: Vec<f64> = outputs.iter().map(|o| o[0]).collect();
+            let labels: Vec<bool> = expects.iter().map(|e| e[0] == 1.0).collect();
+            Some(classify(&predicted, &actual, 2, roc_auc(&scores, &labels)))
+        } else if one_hot && width > 1 {
+            let predicted: Vec<usize> = outputs.iter().map(|o| argmax(o)).collect();
+            let actual: Vec<usize> = expects.iter().map(|e| argmax(e)).collect();
+            Some(classify(&predicted, &actual, width, None))
+        } else {
+            None
+        };
+
+        Metrics {
+            regression: regression(outputs, expects),
+            classification,
+            bit_accuracy: if binary {
+                Some(bit_accuracy(outputs, expects))
+            } else {
+                None
+            },
+        }
+    }
+}
+
+fn classify(
+    predicted: &[usize],
+    actual: &[usize],
+    classes: usize,
+    roc_auc: Option<f64>,
+) -> Classification {
+    let confusion = confusion(predicted, actual, classes);
+    let per_class = per_class(&confusion);
+    Classification {
+        accuracy: accuracy(predicted, actual),
+        macro_avg: macro_avg(&per_class),
+        micro_avg: micro_avg(&confusion),
+        per_class,
+        confusion,
+        roc_auc,
+    }
+}
+
+/// index of the largest value
+pub fn argmax(row: &[f64]) -> usize {
+    let mut best = 0;
+    for (idx, val) in row.iter().enumerate() {
+        if *val > row[best] {
+            best = idx;
+        }
+    }
+    best
+}
+
+/// share of samples whose predicted class is the actual one
+pub fn accuracy(predicted: &[usize], actual: &[usize]) -> f64 {
+    let hits = predicted.iter().zip(actual).filter(|(p, a)| p == a).count();
+    hits as f64 / actual.len() as f64
+}
+
+/// confusion[actual][predicted] = count
+pub fn confusion(predicted: &[usize], actual: &[usize], classes: usize) -> Vec<Vec<usize>> {
+    let mut confusion = vec![vec![0; classes]; classes];
+    for (p, a) in predicted.iter().zip(actual) {
+        confusion[*a][*p] += 1;
+    }
+    confusion
+}
+
+/// scores of every class from a confusion matrix.
+/// a class never predicted (or never present) scores 0
+pub fn per_class(confusion: &[Vec<usize>]) -> Vec<Score> {
+    (0..confusion.len())
+        .map(|class| {
+            let tp = confusion[class][class] as f64;
+            let predicted: usize = confusion.iter().map(|row| row[class]).sum();
+            let actual: usize = confusion[class].iter().sum();
+            Score::new(ratio(tp, predicted as f64), ratio(tp, actual as f64))
+        })
+        .collect()
+}
+
+pub fn macro_avg(scores: &[Score]) -> Score {
+    let n = scores.len() as f64;
+    Score {
+        precision: scores.iter().map(|s| s.precision).sum::<f64>() / n,
+        recall: scores.iter().map(|s| s.recall).sum::<f64>() / n,
+        f1: scores.iter().map(|s| s.f1).sum::<f64>() / n,
+    }
+}
+
+/// for single label classes, micro precision and recall equal accuracy
+pub fn micro_avg(confusion: &[Vec<usize>]) -> Score {
+    let tp: usize = (0..confusion.len())
+        .map(|class| confusion[class][class])
+        .sum();
+    let total: usize = confusion.iter().flatten().sum();
+    let share = ratio(tp as f64, total as f64);
+    Score::new(share, share)
+}
+
+/// area under roc curve of `scores` for binary `labels`
+/// (probability a positive scores above a negative, ties count half).
+/// None if only one kind of label is present
+pub fn roc_auc(scores: &[f64], labels: &[bool]) -> Option<f64> {
+    let positives: Vec<f64> = scores
+        .iter()
+        .zip(labels)
+        .filter(|(_, l)| **l)
+        .map(|(s, _)| *s)
+        .collect();
+    let negatives: Vec<f64> = scores
+        .iter()
+        .zip(labels)
+        .filter(|(_, l)| !**l)
+        .map(|(s, _)| *s)
+        .collect();
+    if positives.is_empty() || negatives.is_empty() {
+        return None;
+    }
+
+    let mut wins = 0.0;
+    for p in positives.iter() {
+        for n in negatives.iter() {
+            if p > n {
+                wins += 1.0;
+            } else if p == n {
+                wins += 0.5;
+            }
+        }
+    }
+    Some(wins / (positives.len() * negatives.len()) as f64)
+}
+
+/// mean absolute error, root mean squared error and
+/// coefficient of determination over every output value
+pub fn regression(outputs: &[Vec<f64>], expects: &[Vec<f64>]) -> Regression {
+    let pairs: Vec<(f64, f64)> = outputs
+        .iter()
+        .flatten()
+        .zip(expects.iter().flatten())
+        .map(|(o, e)| (*o, *e))
+        .collect();
+    let n = pairs.len() as f64;
+    let mean = pairs.iter().map(|(_, e)| e).sum::<f64>() / n;
+    let squared: f64 = pairs.iter().map(|(o, e)| (o - e).powi(2)).sum();
+    let total: f64 = pairs.iter().map(|(_, e)| (e - mean).powi(2)).sum();
+
+    Regression {
+        mae: pairs.iter().map(|(o, e)| (o - e).abs()).sum::<f64>() / n,
+        rmse: (squared / n).sqrt(),
+        // constant expects are explained only by a perfect fit
+        r2: if total > 0.0 {
+            1.0 - squared / total
+        } else if squared == 0.0 {
+            1.0
+        } else {
+            0.0
+        },
+    }
+}
+
+/// share of output values rounding (at 0.5) to their 0/1 expect
+pub fn bit_accuracy(outputs: &[Vec<f64>], expects: &[Vec<f64>]) -> f64 {
+    let mut hits = 0;
+    let mut total = 0;
+    for (o, e) in outputs.iter().flatten().zip(expects.iter().flatten()) {
+        if (*o >= 0.5) == (*e == 1.0) {
+            hits += 1;
+        }
+        total += 1;
+    }
+    hits as f64 / total as f64
+}
+
+fn ratio(a: f64, b: f64) -> f64 {
+    if b > 0.0 {
+        a / b
+    } else {
+        0.0
+    }
+}
+
+#[test]
+fn metrics_test_one_hot_classes() {
+    let outputs = vec![
+        vec![0.9, 0.1, 0.0],
+        vec![0.2, 0.7, 0.1],
+        vec![0.1, 0.8, 0.1],
+        vec![0.3, 0.3, 0.4],
+    ];
+    let expects = vec![
+        vec![1.0, 0.0, 0.0],
+        vec![0.0, 1.0, 0.0],
+        vec![0.0, 0.0, 1.0],
+        vec![0.0, 0.0, 1.0],
+    ];
+    let metrics = Metrics::evaluate(&outputs, &expects);
+    let classes = metrics.classification.unwrap();
+
+    assert_eq!(classes.accuracy, 0.75);
+    assert_eq!(
+        classes.confusion,
+        vec![vec![1, 0, 0], vec![0, 1, 0], vec![0, 1, 1]]
+    );
+    assert_eq!(classes.per_class[1], Score::new(0.5, 1.0));
+    assert_eq!(classes.per_class[2], Score::new(1.0, 0.5));
+    assert!((classes.macro_avg.recall - 2.5 / 3.0).abs() < 1e-12);
+    assert_eq!(classes.micro_avg.f1, 0.75);
+    assert!(classes.roc_auc.is_none());
+    assert_eq!(metrics.bit_accuracy, Some(9.0 / 12.0));
+}
+
+#[test]
+fn metrics_test_binary_and_regression() {
+    let outputs = vec![vec![0.8], vec![0.4], vec![0.6], vec![0.1]];
+    let expects = vec![vec![1.0], vec![1.0], vec![0.0], vec![0.0]];
+    let metrics = Metrics::evaluate(&outputs, &expects);
+    let classes = metrics.classification.unwrap();
+
+    assert_eq!(classes.accuracy, 0.5);
+    // positives 0.8, 0.4 against negatives 0.6, 0.1 win 3 of 4 pairs
+    assert_eq!(classes.roc_auc, Some(0.75));
+
+    let exact = regression(&[vec![1.0, 2.0]], &[vec![1.0, 2.0]]);
+    assert_eq!(exact.rmse, 0.0);
+    assert_eq!(exact.r2, 1.0);
+
+    let metrics = Metrics::evaluate(&[vec![0.5], vec![2.5]], &[vec![1.0], vec![3.0]]);
+    assert!(metrics.classification.is_none() && metrics.bit_accuracy.is_none());
+    assert_eq!(metrics.regression.mae, 0.5);
+    assert_eq!(metrics.regression.rmse, 0.5);
+    assert_eq!(metrics.regression.r2, 0.75);
+}
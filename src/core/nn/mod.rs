@@ -1,3 +1,10 @@
+pub mod clipping;
 pub mod cost;
 pub mod dataset;
+pub mod ensemble;
+pub mod layer;
+pub mod metrics;
 pub mod nn;
+pub mod regularization;
+pub mod sequential;
+pub mod surgery;
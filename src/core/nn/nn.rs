@@ -1,62 +1,218 @@
+use std::thread;
+
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::core::autodiff::tape::Tape;
 use crate::core::matrix::matrix::Matrix;
 use crate::core::matrix::matrix::__Matrix;
-use crate::core::nn::cost::CostInfo;
+use crate::core::nn::cost::{squared_error, CostInfo};
+use crate::core::nn::layer::dense::Dense;
+use crate::core::nn::layer::norm::Normalization;
+use crate::core::nn::layer::{Activation, LayerData};
+use crate::core::nn::regularization::Regularization;
+use crate::core::nn::sequential::{Sequential, Skip};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "NNData")]
 pub struct NN {
+    /// width of every activation, input first
     pub layers: Vec<usize>,
-    pub weights: Vec<Matrix<f64>>,
-    pub biases: Vec<Matrix<f64>>,
+    /// chained layers
+    pub net: Sequential,
+    /// whether `learn` updates each layer (frozen layers are kept)
+    pub trainable: Vec<bool>,
+    /// activations of last process, input first
+    #[serde(skip)]
     pub apps: Vec<Matrix<f64>>,
+    /// penalty included in gradients and cost info
+    #[serde(skip)]
+    pub regularization: Regularization,
+}
+
+/// every format a model was saved in
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NNData {
+    Layered {
+        net: Sequential,
+        /// missing in sessions saved before layers could be frozen
+        #[serde(default)]
+        trainable: Vec<bool>,
+    },
+    /// stack of dense sigmoid layers (saved before layers existed)
+    Legacy {
+        weights: Vec<Matrix<f64>>,
+        biases: Vec<Matrix<f64>>,
+    },
+}
+
+impl TryFrom<NNData> for NN {
+    type Error = String;
+
+    fn try_from(data: NNData) -> Result<Self, Self::Error> {
+        match data {
+            NNData::Layered { net, trainable } => {
+                if net.is_empty() {
+                    return Err(String::from("NN>>deserialize: model has no layers"));
+                }
+                let mut nn = NN::from_net(net);
+                if !trainable.is_empty() {
+                    if trainable.len() != nn.len() {
+                        return Err(format!(
+                            "NN>>deserialize: {} trainable flags for {} layers",
+                            trainable.len(),
+                            nn.len()
+                        ));
+                    }
+                    nn.trainable = trainable;
+                }
+                Ok(nn)
+            }
+            NNData::Legacy { weights, biases } => {
+                if weights.is_empty() {
+                    return Err(String::from("NN>>deserialize: model has no layers"));
+                }
+                if weights.len() != biases.len() {
+                    return Err(format!(
+                        "NN>>deserialize: {} weights for {} biases",
+                        weights.len(),
+                        biases.len()
+                    ));
+                }
+                let mut net = Sequential::new();
+                for (weights, biases) in weights.into_iter().zip(biases) {
+                    let dense = LayerData::Dense {
+                        weights,
+                        biases,
+                        activation: Activation::Sigmoid,
+                        dropout: 0.0,
+                    };
+                    net.try_push(dense.try_build()?)?;
+                }
+                Ok(NN::from_net(net))
+            }
+        }
+    }
 }
 
 impl NN {
+    /// stack of dense sigmoid layers
     pub fn new(layers: &[usize]) -> Self {
-        let depth = layers.len();
-
-        let mut weights = Vec::with_capacity(depth - 1);
-        let mut biases = Vec::with_capacity(depth - 1);
-        let mut apps = Vec::with_capacity(depth);
-
-        for (level, size) in layers.iter().enumerate() {
-            if level == 0 {
-                apps.push(Matrix::new(1, *size));
-            } else {
-                weights.push(Matrix::new(apps[level - 1].len_col(), *size));
-                biases.push(Matrix::new(1, *size));
-                apps.push(Matrix::new(1, *size));
+        Self::stack(layers, &vec![0.0; layers.len().saturating_sub(2)], &[])
+    }
+
+    /// stack of dense sigmoid layers, with dropout rate of every hidden layer
+    pub fn with_dropout(layers: &[usize], dropout: &[f64]) -> Self {
+        Self::stack(layers, dropout, &[])
+    }
+
+    /// stack of dense sigmoid layers with dropout rate of every hidden layer
+    /// and skips between levels. `layers` are output widths of layers, every
+    /// layer takes whatever its previous level is wide after skips
+    pub fn stack(layers: &[usize], dropout: &[f64], skips: &[Skip]) -> Self {
+        Self::try_stack(layers, dropout, skips).unwrap_or_else(|msg| panic!("{}", msg))
+    }
+
+    /// same as stack, returns why the layout can not be built instead of panicking
+    pub fn try_stack(layers: &[usize], dropout: &[f64], skips: &[Skip]) -> Result<Self, String> {
+        if layers.len() < 2 || dropout.len() + 2 != layers.len() {
+            return Err(format!(
+                "NN>>stack: {} dropout rates for {} levels",
+                dropout.len(),
+                layers.len()
+            ));
+        }
+        if let Some(rate) = dropout.iter().find(|rate| !(0.0..1.0).contains(*rate)) {
+            return Err(format!("NN>>stack: dropout rate {} is not in [0, 1)", rate));
+        }
+        if let Some(skip) = skips.iter().find(|skip| skip.to >= layers.len()) {
+            return Err(format!(
+                "NN>>stack: skip {} -> {} goes past the last level {}",
+                skip.from,
+                skip.to,
+                layers.len() - 1
+            ));
+        }
+        let mut net = Sequential::new();
+        for level in 1..layers.len() {
+            let input = if level == 1 { layers[0] } else { net.width(level - 1) };
+            let mut dense = Dense::new(input, layers[level], Activation::Sigmoid);
+            if level < layers.len() - 1 && dropout[level - 1] > 0.0 {
+                dense = dense.with_dropout(dropout[level - 1]);
+            }
+            net.push(Box::new(dense));
+            for skip in skips.iter().filter(|skip| skip.to == level) {
+                net.try_connect(skip.from, skip.merge)?;
             }
         }
+        Ok(Self::from_net(net))
+    }
 
-        return NN {
-            layers: layers.to_vec(),
-            weights,
-            biases,
-            apps,
+    /// insert normalization after every hidden layer.
+    /// skips keep merging into the same (now normalized) activations
+    pub fn normalized(self, normalization: Normalization) -> Self {
+        let last = self.len();
+        // level of an activation once norms are inserted
+        let moved = |level: usize| match level {
+            0 => 0,
+            level if level < last => 2 * level,
+            _ => 2 * last - 1,
         };
+        let skips = self.net.skips;
+        let mut net = Sequential::new();
+        let mut trainable = Vec::new();
+        for (idx, layer) in self.net.layers.into_iter().enumerate() {
+            let level = idx + 1;
+            let size = layer.output_size();
+            net.push(layer);
+            trainable.push(self.trainable[idx]);
+            if level < last {
+                net.push(normalization.build(size));
+                trainable.push(true);
+            }
+            for skip in skips.iter().filter(|skip| skip.to == level) {
+                net.connect(moved(skip.from), skip.merge);
+            }
+        }
+        let mut nn = Self::from_net(net);
+        nn.regularization = self.regularization;
+        nn.trainable = trainable;
+        nn
+    }
+
+    /// wrap chained layers as a model
+    pub fn from_net(net: Sequential) -> Self {
+        assert!(!net.is_empty());
+        let layers: Vec<usize> = (0..=net.len()).map(|level| net.width(level)).collect();
+        let apps = layers.iter().map(|size| Matrix::new(1, *size)).collect();
+
+        let trainable = vec![true; net.len()];
+        NN {
+            layers,
+            net,
+            trainable,
+            apps,
+            regularization: Regularization::default(),
+        }
     }
 
     pub fn len(&self) -> usize {
-        self.weights.len()
+        self.net.len()
     }
 
+    /// forward current input.
+    /// layers are kept in inference mode unless gradients are being
+    /// computed, so output for prediction is deterministic
     pub fn process(&mut self) {
-        for idx in 0..self.len() {
-            let apps = self.apps[idx].clone();
-            self.apps[idx + 1].dot(&apps, &self.weights[idx]);
-            self.apps[idx + 1].sum(&self.biases[idx]);
-            self.apps[idx + 1].sigmoid()
-        }
+        let outputs = self.net.forward(&self.apps[0]);
+        self.apps.truncate(1);
+        self.apps.extend(outputs);
     }
 
     pub fn set(&mut self, input: &[f64]) {
-        assert!(input.len() == self.apps[0].len_col());
-        for (idx, r) in self.apps[0].row_mut(0).iter_mut().enumerate() {
-            *r = input[idx];
-        }
+        assert!(input.len() == self.layers[0]);
+        self.apps[0] = Matrix::from_rows(&[input.to_vec()]);
     }
 
     pub fn output(&self) -> &[f64] {
@@ -68,13 +224,20 @@ impl NN {
         self.apps[len].row_mut(0)
     }
 
+    /// output for `input`, without touching the model.
+    /// dropout and batch statistics are off as on inference
+    pub fn predict(&self, input: &[f64]) -> Vec<f64> {
+        self.predict_batch(&[input.to_vec()]).remove(0)
+    }
+
+    /// outputs for every row of `inputs`, computed as a single batch
+    pub fn predict_batch(&self, inputs: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        assert!(inputs.iter().all(|input| input.len() == self.layers[0]));
+        *self.net.infer(&Matrix::from_rows(inputs)).el
+    }
+
     fn __cost(&self, expect: &[f64]) -> f64 {
-        assert!(self.output().len() == expect.len());
-        let mut diff = 0.0;
-        for (idx, output) in self.output().iter().enumerate() {
-            diff += (output - expect[idx]).powi(2);
-        }
-        diff
+        squared_error(self.output(), expect)
     }
 
     pub fn cost(&mut self, inputs: &Vec<Vec<f64>>, expects: &Vec<Vec<f64>>) -> f64 {
@@ -97,130 +260,615 @@ impl NN {
             self.process();
             cost_info.push(self.__cost(expects[round].as_slice()))
         }
+        cost_info.penalty = self.regularization.penalty(self);
         cost_info
     }
 
-    pub fn backprop(&mut self, inputs: &Vec<Vec<f64>>, expects: &Vec<Vec<f64>>) -> Self {
+    /// cost including regularization penalty (what training minimizes)
+    pub fn loss(&mut self, inputs: &Vec<Vec<f64>>, expects: &Vec<Vec<f64>>) -> f64 {
+        self.cost(inputs, expects) + self.regularization.penalty(self)
+    }
+
+    /// model of same layout, holding accumulated gradients as parameters
+    /// (sparse layers hold the rows which got gradients only).
+    /// a delta is never forwarded, so it keeps no activations
+    fn gradients(&self) -> Self {
+        NN {
+            layers: self.layers.clone(),
+            net: Sequential {
+                layers: self.net.layers.iter().map(|layer| layer.gradients()).collect(),
+                skips: self.net.skips.clone(),
+            },
+            trainable: self.trainable.clone(),
+            apps: Vec::new(),
+            regularization: self.regularization.clone(),
+        }
+    }
+
+    /// gradients of the cost, the whole dataset is forwarded as one batch
+    pub fn backprop(&mut self, inputs: &[Vec<f64>], expects: &[Vec<f64>]) -> Self {
+        assert!(inputs.len() == expects.len());
+        let mut delta = self.cost_gradients(inputs, expects, inputs.len());
+        self.regularization.apply(self, &mut delta);
+        self.zero_frozen(&mut delta);
+        delta
+    }
+
+    /// gradients of the cost as `backprop`, the dataset split over `threads`
+    /// clones of the model whose gradients are summed.
+    /// models with batch statistics are not split
+    pub fn backprop_parallel(
+        &mut self,
+        inputs: &[Vec<f64>],
+        expects: &[Vec<f64>],
+        threads: usize,
+    ) -> Self {
+        assert!(inputs.len() == expects.len());
+        let threads = threads.min(inputs.len());
+        if threads <= 1 || self.net.batch_dependent() {
+            return self.backprop(inputs, expects);
+        }
+
         let n = inputs.len();
-        let mut delta = Self::new(
-            self.apps
-                .iter()
-                .fold(Vec::new(), |mut layers, apps| {
-                    layers.push(apps.len_col());
-                    layers
+        let chunk = n.div_ceil(threads);
+        let mut deltas = thread::scope(|scope| {
+            let handles: Vec<_> = inputs
+                .chunks(chunk)
+                .zip(expects.chunks(chunk))
+                .map(|(inputs, expects)| {
+                    let mut model = self.clone();
+                    scope.spawn(move || model.cost_gradients(inputs, expects, n))
                 })
-                .as_slice(),
-        );
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<Self>>()
+        })
+        .into_iter();
 
-        for (round, input) in inputs.iter().enumerate() {
-            self.set(input.as_slice());
-            self.process();
-
-            for level in 0..delta.len() {
-                delta.apps[level].fill(0.0);
+        let mut delta = deltas.next().unwrap();
+        for part in deltas {
+            for (layer, part) in delta.net.layers.iter_mut().zip(part.net.layers.iter()) {
+                layer.accumulate(part.as_ref());
             }
+        }
+        self.regularization.apply(self, &mut delta);
+        self.zero_frozen(&mut delta);
+        delta
+    }
 
-            for oidx in 0..self.output().len() {
-                delta.output_mut()[oidx] = self.output()[oidx] - expects[round][oidx];
-            }
+    /// gradients of the data cost over `inputs`, a share of `total` samples
+    fn cost_gradients(&mut self, inputs: &[Vec<f64>], expects: &[Vec<f64>], total: usize) -> Self {
+        self.apps[0] = Matrix::from_rows(inputs);
+        self.net.set_training(true);
+        self.process();
+        self.net.set_training(false);
 
-            for level in (1..=self.len()).rev() {
-                for aidx in 0..self.apps[level].len_col() {
-                    let a = self.apps[level].at(0, aidx);
-                    let da = delta.apps[level].at(0, aidx);
-                    *delta.biases[level - 1].at_mut(0, aidx) += 2.0 * da * a * (1.0 - a);
-
-                    for paidx in 0..self.apps[level - 1].len_col() {
-                        let pa = self.apps[level - 1].at(0, paidx);
-                        let w = self.weights[level - 1].at(paidx, aidx);
-                        *delta.weights[level - 1].at_mut(paidx, aidx) +=
-                            2.0 * da * a * (1.0 - a) * pa;
-                        *delta.apps[level - 1].at_mut(0, paidx) += 2.0 * da * a * (1.0 - a) * w;
-                    }
+        // dcost/doutput of cost = sum((output - expect)^2) / n
+        let mut grad = self.apps[self.len()].clone();
+        grad.sub(&Matrix::from_rows(expects));
+        grad.mul(&(2.0 / total as f64));
+
+        self.net.zero_grads();
+        self.net.backward(&grad);
+        self.gradients()
+    }
+
+    /// frozen layers take no step (and add nothing to norms of the delta)
+    fn zero_frozen(&self, delta: &mut Self) {
+        for (layer, trainable) in delta.net.layers.iter_mut().zip(self.trainable.iter()) {
+            if !*trainable {
+                for param in layer.params_mut() {
+                    param.fill(0.0);
                 }
             }
         }
+    }
+
+    /// gradients of the cost by reverse-mode automatic differentiation.
+    /// the whole dataset is recorded as one batch on a tape.
+    /// returns name of the first layer which cannot be traced on failure
+    pub fn autodiff(&mut self, inputs: &[Vec<f64>], expects: &[Vec<f64>]) -> Result<Self, String> {
+        assert!(inputs.len() == expects.len());
+        let n = inputs.len() as f64;
+
+        let mut tape = Tape::new();
+        let input = tape.var(Matrix::from_rows(inputs));
+        self.net.set_training(true);
+        let traced = self.net.trace(&mut tape, input);
+        self.net.set_training(false);
+        let (apps, params) = traced?;
+
+        let expects = tape.var(Matrix::from_rows(expects));
+        let diff = tape.sub(apps, expects);
+        let squared = tape.square(diff);
+        let total = tape.sum(squared);
+        let cost = tape.scale(total, 1.0 / n);
+
+        let grads = tape.backward(cost);
 
-        for level in 0..delta.len() {
-            let n = n as f64;
-            delta.weights[level].div(&n);
-            delta.biases[level].div(&n);
+        let mut delta = self.clone();
+        for (layer, vars) in delta.net.layers.iter_mut().zip(params) {
+            for (param, var) in layer.params_mut().into_iter().zip(vars) {
+                match grads.get(var) {
+                    Some(grad) => *param = grad.clone(),
+                    None => param.fill(0.0),
+                }
+            }
         }
+        self.regularization.apply(self, &mut delta);
+        self.zero_frozen(&mut delta);
 
-        delta
+        Ok(delta)
     }
 
+    /// gradients of the cost by finite difference (in inference mode)
     pub fn finite_diff(
         &mut self,
         inputs: &Vec<Vec<f64>>,
         expects: &Vec<Vec<f64>>,
         epsilon: &f64,
     ) -> Self {
-        let cost_original = self.cost(inputs, expects);
+        let cost_original = self.loss(inputs, expects);
 
-        let mut delta = Self::new(
-            self.apps
-                .iter()
-                .fold(Vec::new(), |mut layers, apps| {
-                    layers.push(apps.len_col());
-                    layers
-                })
-                .as_slice(),
-        );
+        let mut delta = self.clone();
 
         for level in 0..self.len() {
-            for row in 0..self.weights[level].len_row() {
-                for col in 0..self.weights[level].len_col() {
-                    let saved = self.weights[level].at(row, col);
-                    *self.weights[level].at_mut(row, col) += *epsilon;
-                    let cost_renewed = self.cost(inputs, expects);
-                    *delta.weights[level].at_mut(row, col) =
-                        (cost_renewed - cost_original) / epsilon;
-                    *self.weights[level].at_mut(row, col) = saved;
-                }
+            if !self.trainable[level] {
+                continue;
             }
-
-            for col in 0..self.biases[level].len_col() {
-                let saved = self.biases[level].at(0, col);
-                *self.biases[level].at_mut(0, col) += *epsilon;
-                let cost_renewed = self.cost(inputs, expects);
-                *delta.biases[level].at_mut(0, col) = (cost_renewed - cost_original) / epsilon;
-                *self.biases[level].at_mut(0, col) = saved;
+            for pidx in 0..self.net.layers[level].params().len() {
+                let (rows, cols) = {
+                    let param = self.net.layers[level].params()[pidx];
+                    (param.len_row(), param.len_col())
+                };
+                for row in 0..rows {
+                    for col in 0..cols {
+                        let saved = self.net.layers[level].params()[pidx].at(row, col);
+                        *self.net.layers[level].params_mut()[pidx].at_mut(row, col) += *epsilon;
+                        let cost_renewed = self.loss(inputs, expects);
+                        *delta.net.layers[level].params_mut()[pidx].at_mut(row, col) =
+                            (cost_renewed - cost_original) / epsilon;
+                        *self.net.layers[level].params_mut()[pidx].at_mut(row, col) = saved;
+                    }
+                }
             }
         }
+        self.zero_frozen(&mut delta);
 
         delta
     }
 
     pub fn mul(&mut self, rate: &f64) {
-        for level in 0..self.len() {
-            self.weights[level].mul(rate);
-            self.biases[level].mul(rate);
+        for layer in self.net.layers.iter_mut() {
+            for param in layer.params_mut() {
+                param.mul(rate);
+            }
+        }
+    }
+
+    /// global L2 norm of all parameters
+    pub fn norm(&self) -> f64 {
+        let mut sum = 0.0;
+        for layer in self.net.layers.iter() {
+            for param in layer.params() {
+                sum += param.el.iter().flatten().map(|w| w * w).sum::<f64>();
+            }
         }
+        sum.sqrt()
+    }
+
+    /// false if any parameter is NaN or infinite
+    pub fn is_finite(&self) -> bool {
+        self.net.layers.iter().all(|layer| {
+            layer
+                .params()
+                .into_iter()
+                .all(|param| param.el.iter().flatten().all(|w| w.is_finite()))
+        })
     }
 
     pub fn rand(&mut self) {
-        for level in 0..self.len() {
-            self.weights[level].rand();
-            self.biases[level].rand();
+        for layer in self.net.layers.iter_mut() {
+            layer.rand();
         }
     }
 
+    /// apply steps of `delta` to every trainable layer
     pub fn learn(&mut self, delta: &Self) {
-        for level in 0..self.len() {
-            self.weights[level].sub(&delta.weights[level]);
-            self.biases[level].sub(&delta.biases[level]);
+        for ((layer, delta), trainable) in self
+            .net
+            .layers
+            .iter_mut()
+            .zip(delta.net.layers.iter())
+            .zip(self.trainable.iter())
+        {
+            if *trainable {
+                layer.learn(delta.as_ref());
+            }
+        }
+    }
+
+    /// keep every layer but the last `n` as it is while learning
+    pub fn freeze_all_but(&mut self, n: usize) {
+        let len = self.len();
+        for (idx, trainable) in self.trainable.iter_mut().enumerate() {
+            *trainable = idx + n >= len;
         }
     }
+
+    /// one line per layer with its parameter count, then the total
+    pub fn summary(&self) -> String {
+        let mut total = 0;
+        let mut summary = String::new();
+        for (level, layer) in self.net.layers.iter().enumerate() {
+            let count: usize = layer
+                .params()
+                .iter()
+                .map(|param| param.len_row() * param.len_col())
+                .sum();
+            total += count;
+            summary += &format!("{:>3} {:<40} {:>10}\n", level, layer.name(), count);
+        }
+        summary += &format!("total params: {}", total);
+        summary
+    }
 }
 
-impl Clone for NN {
-    fn clone(&self) -> Self {
-        Self {
-            layers: self.layers.clone(),
-            weights: self.weights.clone(),
-            biases: self.biases.clone(),
-            apps: self.apps.clone(),
+#[cfg(test)]
+use crate::core::nn::layer::assert_same_params;
+
+#[cfg(test)]
+fn xor() -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+    let inputs = vec![
+        vec![0.0, 0.0],
+        vec![0.0, 1.0],
+        vec![1.0, 0.0],
+        vec![1.0, 1.0],
+    ];
+    let expects = vec![vec![0.0], vec![1.0], vec![1.0], vec![0.0]];
+    (inputs, expects)
+}
+
+#[test]
+fn nn_test_autodiff_matches_finite_diff() {
+    let (inputs, expects) = xor();
+
+    let mut nn = NN::new(&[2, 3, 2, 1]);
+    nn.rand();
+
+    let auto = nn.autodiff(&inputs, &expects).unwrap();
+    let numeric = nn.finite_diff(&inputs, &expects, &1e-7);
+
+    assert_same_params(&auto.net.layers, &numeric.net.layers, 1e-5);
+}
+
+#[test]
+fn nn_test_autodiff_rejects_untraceable_layers() {
+    use crate::core::nn::layer::conv::Conv2D;
+    use crate::core::nn::layer::embedding::Embedding;
+    use crate::core::nn::layer::flatten::Flatten;
+    use crate::core::nn::layer::pool::MaxPool2D;
+    use crate::core::nn::layer::recurrent::Lstm;
+    use crate::core::nn::layer::{Layer, Shape};
+
+    let image = Shape::new(1, 4, 4);
+    let pooled = Shape::new(1, 2, 2);
+    let models: Vec<(Vec<Box<dyn Layer>>, Vec<f64>)> = vec![
+        (
+            vec![
+                Box::new(Conv2D::new(image, 1, 3, 1, 1, Activation::Linear)),
+                Box::new(Flatten::new(image)),
+            ],
+            vec![0.5; 16],
+        ),
+        (
+            vec![
+                Box::new(MaxPool2D::new(image, 2, 2)),
+                Box::new(Flatten::new(pooled)),
+            ],
+            vec![0.5; 16],
+        ),
+        (vec![Box::new(Lstm::new(2, 1, 2, false))], vec![0.5, 0.5]),
+        (vec![Box::new(Embedding::new(3, 2, 2))], vec![0.0, 2.0]),
+    ];
+    for (layers, input) in models {
+        let name = layers[0].name();
+        let mut net = Sequential::new();
+        for layer in layers {
+            net.push(layer);
         }
+        net.push(Box::new(Dense::new(net.output_size(), 1, Activation::Sigmoid)));
+        let mut nn = NN::from_net(net);
+        assert_eq!(nn.autodiff(&[input], &[vec![1.0]]).err(), Some(name));
+    }
+}
+
+#[test]
+fn nn_test_backprop_matches_autodiff() {
+    let (inputs, expects) = xor();
+
+    let mut nn = NN::new(&[2, 4, 3, 1]);
+    nn.rand();
+
+    let back = nn.backprop(&inputs, &expects);
+    let auto = nn.autodiff(&inputs, &expects).unwrap();
+
+    assert_same_params(&back.net.layers, &auto.net.layers, 1e-9);
+}
+
+#[test]
+fn nn_test_parallel_backprop_matches_single_thread() {
+    let inputs: Vec<Vec<f64>> = (0..11)
+        .map(|i| vec![(i % 3) as f64 / 2.0, (i % 5) as f64 / 4.0])
+        .collect();
+    let expects: Vec<Vec<f64>> = (0..11).map(|i| vec![(i % 2) as f64]).collect();
+
+    let mut nn = NN::new(&[2, 4, 3, 1]).normalized(Normalization::Layer);
+    nn.rand();
+    nn.regularization = Regularization {
+        l2: 1e-2,
+        ..Default::default()
+    };
+    nn.trainable[0] = false;
+
+    let single = nn.backprop(&inputs, &expects);
+    // more threads than samples included
+    for threads in [2, 4, 16] {
+        let parallel = nn.backprop_parallel(&inputs, &expects, threads);
+        assert_same_params(&single.net.layers, &parallel.net.layers, 1e-12);
     }
+
+    // batch statistics need the whole batch, it is not split
+    let mut nn = NN::new(&[2, 3, 1]).normalized(Normalization::Batch);
+    nn.rand();
+    let single = nn.clone().backprop(&inputs, &expects);
+    let parallel = nn.backprop_parallel(&inputs, &expects, 4);
+    assert_same_params(&single.net.layers, &parallel.net.layers, 1e-15);
+}
+
+#[test]
+fn nn_test_normalized_backprop_matches_finite_diff() {
+    let (inputs, expects) = xor();
+
+    // batch norm is checked on its own, as finite difference
+    // forwards one sample at a time
+    let mut nn = NN::new(&[2, 3, 3, 1]).normalized(Normalization::Layer);
+    nn.rand();
+    assert_eq!(nn.layers, vec![2, 3, 3, 3, 3, 1]);
+
+    let back = nn.backprop(&inputs, &expects);
+    let numeric = nn.finite_diff(&inputs, &expects, &1e-7);
+
+    assert_same_params(&back.net.layers, &numeric.net.layers, 1e-5);
+}
+
+#[test]
+fn nn_test_embedding_summary_and_grads() {
+    use crate::core::nn::layer::embedding::Embedding;
+
+    let mut net = Sequential::new();
+    net.push(Box::new(Embedding::new(6, 2, 2)));
+    net.push(Box::new(Dense::new(4, 1, Activation::Sigmoid)));
+    let mut nn = NN::from_net(net);
+    nn.rand();
+
+    let summary = nn.summary();
+    assert!(summary.contains("Embedding(6x2 table, 2 tokens)"));
+    assert!(summary.ends_with("total params: 17"));
+
+    let inputs = vec![vec![0.0, 5.0], vec![2.0, 0.0]];
+    let expects = vec![vec![1.0], vec![0.0]];
+    let back = nn.backprop(&inputs, &expects);
+    let numeric = nn.finite_diff(&inputs, &expects, &1e-7);
+    // the delta only holds rows of tokens looked up
+    assert_eq!(back.net.layers[0].sparse_rows(), Some(&[0, 2, 5][..]));
+    assert_eq!(back.net.layers[0].params()[0].len_row(), 3);
+    let model = nn.clone();
+    let step = |delta: &NN| {
+        let mut stepped = model.clone();
+        stepped.learn(delta);
+        stepped
+    };
+    assert_same_params(&step(&back).net.layers, &step(&numeric).net.layers, 1e-5);
+
+    // samples split over threads touch different rows
+    let parallel = nn.backprop_parallel(&inputs, &expects, 2);
+    assert_eq!(parallel.net.layers[0].sparse_rows(), Some(&[0, 2, 5][..]));
+    assert_same_params(&step(&back).net.layers, &step(&parallel).net.layers, 1e-12);
+
+    // held rows take their penalty
+    nn.regularization.l2 = 0.1;
+    let back = nn.backprop(&inputs, &expects);
+    let numeric = nn.finite_diff(&inputs, &expects, &1e-7);
+    let table = numeric.net.layers[0].params()[0];
+    for (row, token) in back.net.layers[0].params()[0].el.iter().zip([0, 2, 5]) {
+        for (b, n) in row.iter().zip(table.row(token)) {
+            assert!((b - n).abs() < 1e-5, "{} != {}", b, n);
+        }
+    }
+
+    let saved: NN = serde_json::from_str(&serde_json::to_string(&nn).unwrap()).unwrap();
+    assert_same_params(&nn.net.layers, &saved.net.layers, 1e-12);
+}
+
+#[test]
+fn nn_test_skips_backprop_matches_autodiff() {
+    use crate::core::nn::sequential::Merge;
+
+    let (inputs, expects) = xor();
+
+    let skips = [
+        Skip {
+            from: 1,
+            to: 2,
+            merge: Merge::Add,
+        },
+        Skip {
+            from: 0,
+            to: 2,
+            merge: Merge::Concat,
+        },
+    ];
+    let mut nn = NN::stack(&[2, 3, 3, 1], &[0.0, 0.0], &skips);
+    nn.rand();
+    assert_eq!(nn.layers, vec![2, 3, 5, 1]);
+
+    let back = nn.backprop(&inputs, &expects);
+    let auto = nn.autodiff(&inputs, &expects).unwrap();
+    let numeric = nn.finite_diff(&inputs, &expects, &1e-7);
+
+    assert_same_params(&back.net.layers, &auto.net.layers, 1e-9);
+    assert_same_params(&back.net.layers, &numeric.net.layers, 1e-5);
+
+    // skips follow their activations when norms are inserted
+    let normalized = nn.normalized(Normalization::Layer);
+    assert_eq!(normalized.layers, vec![2, 3, 3, 3, 5, 1]);
+    assert_eq!(normalized.net.skips[0].from, 2);
+    assert_eq!(normalized.net.skips[0].to, 4);
+}
+
+#[test]
+fn nn_test_stack_rejects_bad_skips() {
+    use crate::core::nn::sequential::Merge;
+
+    let skip = |from, to, merge| Skip { from, to, merge };
+    let bad = [
+        // backward and self skips
+        skip(2, 1, Merge::Concat),
+        skip(1, 1, Merge::Concat),
+        // past the last level
+        skip(0, 3, Merge::Concat),
+        // 2 wide input added to 3 wide activation
+        skip(0, 1, Merge::Add),
+    ];
+    for skip in bad {
+        assert!(NN::try_stack(&[2, 3, 1], &[0.0], &[skip]).is_err());
+    }
+    assert!(NN::try_stack(&[2, 3, 1], &[], &[]).is_err());
+    assert!(NN::try_stack(&[2, 3, 1], &[1.0], &[]).is_err());
+    assert!(NN::try_stack(&[2, 3, 1], &[-0.5], &[]).is_err());
+    assert!(NN::try_stack(&[2, 3, 1], &[0.0], &[skip(0, 2, Merge::Concat)]).is_ok());
+}
+
+#[test]
+fn nn_test_predict_shared_across_threads() {
+    use crate::core::nn::sequential::Merge;
+    use std::sync::Arc;
+
+    let (inputs, expects) = xor();
+    let skips = [Skip {
+        from: 0,
+        to: 2,
+        merge: Merge::Concat,
+    }];
+    let mut nn = NN::stack(&[2, 3, 3, 1], &[0.5, 0.0], &skips).normalized(Normalization::Batch);
+    nn.rand();
+    // moves running statistics away from their initial values
+    nn.backprop(&inputs, &expects);
+
+    let expected: Vec<Vec<f64>> = inputs
+        .iter()
+        .map(|input| {
+            nn.set(input);
+            nn.process();
+            nn.output().to_vec()
+        })
+        .collect();
+    assert_eq!(nn.predict(&inputs[1]), expected[1]);
+
+    let shared = Arc::new(nn);
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let model = Arc::clone(&shared);
+            let inputs = inputs.clone();
+            std::thread::spawn(move || model.predict_batch(&inputs))
+        })
+        .collect();
+    for handle in handles {
+        let outputs = handle.join().unwrap();
+        for (output, expected) in outputs.iter().zip(&expected) {
+            assert!((output[0] - expected[0]).abs() < 1e-12);
+        }
+    }
+}
+
+#[test]
+fn nn_test_frozen_layers() {
+    let (inputs, expects) = xor();
+
+    let mut nn = NN::new(&[2, 3, 1]);
+    nn.rand();
+    nn.freeze_all_but(1);
+    assert_eq!(nn.trainable, vec![false, true]);
+
+    let before = nn.clone();
+    let delta = nn.backprop(&inputs, &expects);
+    nn.learn(&delta);
+    assert_eq!(
+        nn.net.layers[0].params()[0].el,
+        before.net.layers[0].params()[0].el
+    );
+    assert!(nn.net.layers[1].params()[0].el != before.net.layers[1].params()[0].el);
+
+    let saved: NN = serde_json::from_str(&serde_json::to_string(&nn).unwrap()).unwrap();
+    assert_eq!(saved.trainable, vec![false, true]);
+
+    // flags not matching the layers are rejected instead of panicking
+    let mut data: serde_json::Value = serde_json::to_value(&nn).unwrap();
+    data["trainable"] = serde_json::json!([true]);
+    assert!(serde_json::from_value::<NN>(data).is_err());
+}
+
+#[test]
+fn nn_test_read_legacy_format() {
+    let legacy = r#"{
+        "layers": [2, 2, 1],
+        "weights": [
+            { "el": [[0.1, 0.2], [0.3, 0.4]] },
+            { "el": [[0.5], [0.6]] }
+        ],
+        "biases": [
+            { "el": [[0.7, 0.8]] },
+            { "el": [[0.9]] }
+        ],
+        "apps": [
+            { "el": [[0.0, 0.0]] },
+            { "el": [[0.0, 0.0]] },
+            { "el": [[0.0]] }
+        ]
+    }"#;
+    let mut nn: NN = serde_json::from_str(legacy).unwrap();
+
+    assert_eq!(nn.layers, vec![2, 2, 1]);
+    assert_eq!(nn.len(), 2);
+    assert_eq!(nn.trainable, vec![true, true]);
+    assert_eq!(nn.net.layers[1].params()[0].at(1, 0), 0.6);
+    assert_eq!(nn.net.layers[0].params()[1].at(0, 1), 0.8);
+
+    nn.set(&[1.0, 0.0]);
+    nn.process();
+    let saved: NN = serde_json::from_str(&serde_json::to_string(&nn).unwrap()).unwrap();
+    assert_same_params(&nn.net.layers, &saved.net.layers, 1e-12);
+
+    // unequal counts would drop layers
+    let mut data: serde_json::Value = serde_json::from_str(legacy).unwrap();
+    data["biases"].as_array_mut().unwrap().pop();
+    assert!(serde_json::from_value::<NN>(data).is_err());
+}
+
+#[test]
+fn nn_test_read_rejects_bad_layers() {
+    let nn = NN::with_dropout(&[2, 3, 1], &[0.5]);
+    let saved = serde_json::to_value(&nn).unwrap();
+    assert!(serde_json::from_value::<NN>(saved.clone()).is_ok());
+
+    let mut data = saved.clone();
+    data["net"][0]["Dense"]["biases"] = serde_json::json!({ "el": [[0.0, 0.0]] });
+    assert!(serde_json::from_value::<NN>(data).is_err());
+
+    let mut data = saved;
+    data["net"][0]["Dense"]["dropout"] = serde_json::json!(1.0);
+    assert!(serde_json::from_value::<NN>(data).is_err());
 }
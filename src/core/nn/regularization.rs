@@ -0,0 +1,122 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::matrix::matrix::{Matrix, __Matrix};
+use crate::core::nn::layer::ParamKind;
+use crate::core::nn::nn::NN;
+
+/// L1/L2 penalty on parameters (weight decay)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Regularization {
+    /// factor of sum(|w|)
+    pub l1: f64,
+    /// factor of sum(w^2)
+    pub l2: f64,
+    /// penalize biases as well as weights
+    pub biases: bool,
+}
+
+impl Default for Regularization {
+    fn default() -> Self {
+        Regularization {
+            l1: 0.0,
+            l2: 0.0,
+            biases: false,
+        }
+    }
+}
+
+impl Regularization {
+    pub fn is_none(&self) -> bool {
+        self.l1 == 0.0 && self.l2 == 0.0
+    }
+
+    fn covers(&self, kind: &ParamKind) -> bool {
+        match kind {
+            ParamKind::Weight => true,
+            ParamKind::Bias => self.biases,
+        }
+    }
+
+    /// penalty added to the data cost of given model
+    pub fn penalty(&self, nn: &NN) -> f64 {
+        if self.is_none() {
+            return 0.0;
+        }
+        let mut penalty = 0.0;
+        for layer in nn.net.layers.iter() {
+            for (param, kind) in layer.params().into_iter().zip(layer.param_kinds()) {
+                if !self.covers(&kind) {
+                    continue;
+                }
+                for w in param.el.iter().flatten() {
+                    penalty += self.l1 * w.abs() + self.l2 * w * w;
+                }
+            }
+        }
+        penalty
+    }
+
+    /// add gradient of the penalty (of `nn`) into `delta`.
+    /// sparse deltas only take the penalty of the rows they hold
+    pub fn apply(&self, nn: &NN, delta: &mut NN) {
+        if self.is_none() {
+            return;
+        }
+        for (layer, delta) in nn.net.layers.iter().zip(delta.net.layers.iter_mut()) {
+            let kinds = layer.param_kinds();
+            let rows = delta.sparse_rows().map(|rows| rows.to_vec());
+            for ((param, delta), kind) in layer
+                .params()
+                .into_iter()
+                .zip(delta.params_mut())
+                .zip(kinds)
+            {
+                if self.covers(&kind) {
+                    self.derive(param, delta, rows.as_deref());
+                }
+            }
+        }
+    }
+
+    fn derive(&self, param: &Matrix<f64>, delta: &mut Matrix<f64>, rows: Option<&[usize]>) {
+        for (idx, delta) in delta.el.iter_mut().enumerate() {
+            let param = param.row(rows.map_or(idx, |rows| rows[idx]));
+            for (w, d) in param.iter().zip(delta.iter_mut()) {
+                let l1 = if *w == 0.0 { 0.0 } else { self.l1 * w.signum() };
+                *d += l1 + 2.0 * self.l2 * w;
+            }
+        }
+    }
+}
+
+#[test]
+fn regularization_test_gradients() {
+    use super::layer::assert_same_params;
+
+    let inputs = vec![vec![0.1, 0.9], vec![0.7, 0.3]];
+    let expects = vec![vec![1.0], vec![0.0]];
+
+    let mut nn = NN::new(&[2, 3, 1]);
+    nn.rand();
+    nn.regularization = Regularization {
+        l1: 0.01,
+        l2: 0.1,
+        biases: false,
+    };
+
+    let back = nn.backprop(&inputs, &expects);
+    let numeric = nn.finite_diff(&inputs, &expects, &1e-7);
+
+    assert_same_params(&back.net.layers, &numeric.net.layers, 1e-5);
+
+    // biases are excluded by default
+    let mut plain = nn.clone();
+    plain.regularization = Regularization::default();
+    let plain = plain.backprop(&inputs, &expects);
+    assert_eq!(
+        plain.net.layers[0].params()[1].el,
+        back.net.layers[0].params()[1].el
+    );
+    assert!(plain.net.layers[0].params()[0].el != back.net.layers[0].params()[0].el);
+}
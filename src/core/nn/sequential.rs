@@ -0,0 +1,358 @@
+use std::fmt;
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::core::autodiff::tape::{Tape, Var};
+use crate::core::matrix::matrix::{Matrix, __Matrix};
+
+use super::layer::{Layer, LayerData};
+
+/// how a skipped activation is merged into a later one
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum Merge {
+    /// added element-wise (residual), sizes must match
+    Add,
+    /// appended after the columns of the later activation
+    Concat,
+}
+
+/// extra edge from activation `from` into activation `to`.
+/// activations are numbered like `NN::layers`: 0 is the input and
+/// `level` is the output of layer `level - 1`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Skip {
+    pub from: usize,
+    pub to: usize,
+    pub merge: Merge,
+}
+
+/// chain of layers, output of each layer feeds the next one.
+/// skips merge earlier activations into the output of a layer
+/// before it feeds the next one
+pub struct Sequential {
+    pub layers: Vec<Box<dyn Layer>>,
+    pub skips: Vec<Skip>,
+}
+
+impl Default for Sequential {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sequential {
+    pub fn new() -> Self {
+        Sequential {
+            layers: Vec::new(),
+            skips: Vec::new(),
+        }
+    }
+
+    /// append layer at the end of the chain
+    pub fn push(&mut self, layer: Box<dyn Layer>) {
+        if let Err(msg) = self.try_push(layer) {
+            panic!("{}", msg);
+        }
+    }
+
+    /// same as push, returns why the layer does not fit instead of panicking
+    pub fn try_push(&mut self, layer: Box<dyn Layer>) -> Result<(), String> {
+        if let Some(last) = self.layers.last() {
+            if self.width(self.len()) != layer.input_size() {
+                return Err(format!(
+                    "Sequential>>push: {} does not fit after {}",
+                    layer.name(),
+                    last.name()
+                ));
+            }
+        }
+        self.layers.push(layer);
+        Ok(())
+    }
+
+    /// merge activation `from` into the output of the last layer
+    /// (activation `len`), so it must be called before pushing the next one
+    pub fn connect(&mut self, from: usize, merge: Merge) {
+        if let Err(msg) = self.try_connect(from, merge) {
+            panic!("{}", msg);
+        }
+    }
+
+    /// same as connect, returns why the skip does not fit instead of panicking
+    pub fn try_connect(&mut self, from: usize, merge: Merge) -> Result<(), String> {
+        let to = self.len();
+        if to == 0 || from >= to {
+            return Err(format!("Sequential>>connect: {} -> {}", from, to));
+        }
+        if merge == Merge::Add && self.width(from) != self.width(to) {
+            return Err(format!(
+                "Sequential>>connect: can not add {} wide activation to {} wide one",
+                self.width(from),
+                self.width(to)
+            ));
+        }
+        self.skips.push(Skip { from, to, merge });
+        Ok(())
+    }
+
+    /// width of activation `level` including concatenated skips
+    pub fn width(&self, level: usize) -> usize {
+        if level == 0 {
+            return self.input_size();
+        }
+        let mut width = self.layers[level - 1].output_size();
+        for skip in self.skips.iter().filter(|skip| skip.to == level) {
+            if skip.merge == Merge::Concat {
+                width += self.width(skip.from);
+            }
+        }
+        width
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn input_size(&self) -> usize {
+        self.layers[0].input_size()
+    }
+
+    pub fn output_size(&self) -> usize {
+        self.width(self.len())
+    }
+
+    /// forward batch through every layer.
+    /// returns every activation after the input (output of each layer
+    /// with skips merged in)
+    pub fn forward(&mut self, input: &Matrix<f64>) -> Vec<Matrix<f64>> {
+        let mut outputs: Vec<Matrix<f64>> = Vec::with_capacity(self.len());
+        for level in 1..=self.len() {
+            let mut output = self.layers[level - 1].forward(outputs.last().unwrap_or(input));
+            self.merge_skips(level, input, &outputs, &mut output);
+            outputs.push(output);
+        }
+        outputs
+    }
+
+    /// same as forward in inference mode, without touching layers.
+    /// returns the output only
+    pub fn infer(&self, input: &Matrix<f64>) -> Matrix<f64> {
+        let mut outputs: Vec<Matrix<f64>> = Vec::with_capacity(self.len());
+        for level in 1..=self.len() {
+            let mut output = self.layers[level - 1].infer(outputs.last().unwrap_or(input));
+            self.merge_skips(level, input, &outputs, &mut output);
+            outputs.push(output);
+        }
+        outputs.pop().unwrap()
+    }
+
+    /// merge skips into `level`, given activations computed so far
+    fn merge_skips(
+        &self,
+        level: usize,
+        input: &Matrix<f64>,
+        outputs: &[Matrix<f64>],
+        output: &mut Matrix<f64>,
+    ) {
+        for skip in self.skips.iter().filter(|skip| skip.to == level) {
+            let skipped = match skip.from {
+                0 => input,
+                from => &outputs[from - 1],
+            };
+            match skip.merge {
+                Merge::Add => output.sum(skipped),
+                Merge::Concat => {
+                    for (row, extra) in output.el.iter_mut().zip(skipped.el.iter()) {
+                        row.extend_from_slice(extra);
+                    }
+                }
+            }
+        }
+    }
+
+    /// backward dcost/doutput through every layer, returns dcost/dinput
+    pub fn backward(&mut self, grad: &Matrix<f64>) -> Matrix<f64> {
+        // dcost/dactivation of every level, filled by skips ahead of time
+        let mut grads: Vec<Option<Matrix<f64>>> = vec![None; self.len() + 1];
+        grads[self.len()] = Some(grad.clone());
+
+        for level in (1..=self.len()).rev() {
+            let mut grad = grads[level].take().unwrap();
+            for skip in self.skips.iter().rev().filter(|skip| skip.to == level) {
+                let skipped = match skip.merge {
+                    Merge::Add => grad.clone(),
+                    Merge::Concat => {
+                        let split = grad.len_col() - self.width(skip.from);
+                        let tail: Vec<Vec<f64>> =
+                            grad.el.iter_mut().map(|row| row.split_off(split)).collect();
+                        Matrix::from_rows(&tail)
+                    }
+                };
+                match grads[skip.from].as_mut() {
+                    None => grads[skip.from] = Some(skipped),
+                    Some(acc) => acc.sum(&skipped),
+                }
+            }
+
+            let input_grad = self.layers[level - 1].backward(&grad);
+            match grads[level - 1].as_mut() {
+                None => grads[level - 1] = Some(input_grad),
+                Some(acc) => acc.sum(&input_grad),
+            }
+        }
+        grads[0].take().unwrap()
+    }
+
+    /// record forward of every layer and skip on a tape.
+    /// returns output var and vars of params of every layer,
+    /// or name of the first layer which cannot be traced
+    pub fn trace(&self, tape: &mut Tape, input: Var) -> Result<(Var, Vec<Vec<Var>>), String> {
+        let mut apps = vec![input];
+        let mut params = Vec::with_capacity(self.len());
+        for (idx, layer) in self.layers.iter().enumerate() {
+            let (mut output, vars) = layer.trace(tape, apps[idx]).ok_or_else(|| layer.name())?;
+            for skip in self.skips.iter().filter(|skip| skip.to == idx + 1) {
+                output = match skip.merge {
+                    Merge::Add => tape.add(output, apps[skip.from]),
+                    Merge::Concat => tape.concat(output, apps[skip.from]),
+                };
+            }
+            params.push(vars);
+            apps.push(output);
+        }
+        Ok((apps[self.len()], params))
+    }
+
+    pub fn set_training(&mut self, training: bool) {
+        for layer in self.layers.iter_mut() {
+            layer.set_training(training);
+        }
+    }
+
+    /// whether a layer needs the whole batch at once
+    pub fn batch_dependent(&self) -> bool {
+        self.layers.iter().any(|layer| layer.batch_dependent())
+    }
+
+    pub fn zero_grads(&mut self) {
+        for layer in self.layers.iter_mut() {
+            layer.zero_grads();
+        }
+    }
+
+    pub fn serialize(&self) -> Vec<LayerData> {
+        self.layers.iter().map(|layer| layer.serialize()).collect()
+    }
+}
+
+impl Clone for Sequential {
+    fn clone(&self) -> Self {
+        Sequential {
+            layers: self.serialize().into_iter().map(LayerData::build).collect(),
+            skips: self.skips.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for Sequential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sequential")
+            .field("layers", &self.serialize())
+            .field("skips", &self.skips)
+            .finish()
+    }
+}
+
+/// serialized forms, a plain chain stays a list of layers
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SequentialData {
+    Chain(Vec<LayerData>),
+    Skipped {
+        layers: Vec<LayerData>,
+        skips: Vec<Skip>,
+    },
+}
+
+impl Serialize for Sequential {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let layers = Sequential::serialize(self);
+        if self.skips.is_empty() {
+            SequentialData::Chain(layers).serialize(serializer)
+        } else {
+            SequentialData::Skipped {
+                layers,
+                skips: self.skips.clone(),
+            }
+            .serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Sequential {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (layers, skips) = match SequentialData::deserialize(deserializer)? {
+            SequentialData::Chain(layers) => (layers, Vec::new()),
+            SequentialData::Skipped { layers, skips } => (layers, skips),
+        };
+        if let Some(skip) = skips.iter().find(|skip| skip.to > layers.len()) {
+            return Err(D::Error::custom(format!(
+                "Sequential>>deserialize: skip {} -> {} goes past {} layers",
+                skip.from,
+                skip.to,
+                layers.len()
+            )));
+        }
+        let mut sequential = Sequential::new();
+        for layer in layers {
+            // skips into the last layer pushed must be in place
+            // before the next one is checked against its width
+            let level = sequential.len() + 1;
+            let layer = layer.try_build().map_err(D::Error::custom)?;
+            sequential.try_push(layer).map_err(D::Error::custom)?;
+            for skip in skips.iter().filter(|skip| skip.to == level) {
+                sequential
+                    .try_connect(skip.from, skip.merge)
+                    .map_err(D::Error::custom)?;
+            }
+        }
+        Ok(sequential)
+    }
+}
+
+#[test]
+fn sequential_test_skips() {
+    use super::layer::{dense::Dense, Activation};
+
+    let mut net = Sequential::new();
+    net.push(Box::new(Dense::new(2, 3, Activation::Sigmoid)));
+    net.push(Box::new(Dense::new(3, 3, Activation::Sigmoid)));
+    net.connect(1, Merge::Add);
+    net.push(Box::new(Dense::new(3, 2, Activation::Sigmoid)));
+    net.connect(0, Merge::Concat);
+    assert_eq!(net.output_size(), 4);
+
+    let input = Matrix::from_rows(&[vec![0.5, -0.5]]);
+    let outputs = net.forward(&input);
+    assert_eq!(outputs[2].row(0)[2..], [0.5, -0.5]);
+    // residual of zero initialized layers: sigmoid(0) twice
+    assert_eq!(outputs[1].row(0), &[1.0, 1.0, 1.0]);
+
+    let saved: Sequential = serde_json::from_str(&serde_json::to_string(&net).unwrap()).unwrap();
+    assert_eq!(saved.skips, net.skips);
+
+    // layers or skips which do not fit are rejected instead of panicking
+    let mut data: serde_json::Value = serde_json::to_value(&net).unwrap();
+    data["skips"][0]["from"] = serde_json::json!(0);
+    assert!(serde_json::from_value::<Sequential>(data).is_err());
+    let mut data: serde_json::Value = serde_json::to_value(&net).unwrap();
+    data["skips"][0]["to"] = serde_json::json!(4);
+    assert!(serde_json::from_value::<Sequential>(data).is_err());
+    let mut data: serde_json::Value = serde_json::to_value(&net).unwrap();
+    data["layers"].as_array_mut().unwrap().swap(1, 2);
+    assert!(serde_json::from_value::<Sequential>(data).is_err());
+}
@@ -0,0 +1,246 @@
+use crate::core::matrix::matrix::{Matrix, __Matrix};
+use crate::core::nn::layer::dense::Dense;
+use crate::core::nn::layer::{Activation, Layer, LayerData};
+use crate::core::nn::nn::NN;
+use crate::core::nn::sequential::Sequential;
+
+/// editing layout of a (trained) model while keeping other weights.
+/// only chains of dense layers without skips can be edited
+impl NN {
+    /// add a neuron to hidden `level`. it gets small random incoming
+    /// weights and zero outgoing weights, so outputs do not change
+    pub fn add_neuron(&mut self, level: usize) -> Option<()> {
+        let (prev, next) = self.hidden_pair("add_neuron", level)?;
+        let (mut weights, mut biases, activation, dropout) = prev;
+        for row in weights.el.iter_mut() {
+            row.push((rand::random::<f64>() * 2.0 - 1.0) * 0.1);
+        }
+        biases.el[0].push(0.0);
+
+        let (mut next_weights, next_biases, next_activation, next_dropout) = next;
+        next_weights.el.push(vec![0.0; next_weights.len_col()]);
+
+        self.replace(
+            level - 1,
+            vec![
+                dense(weights, biases, activation, dropout),
+                dense(next_weights, next_biases, next_activation, next_dropout),
+            ],
+        );
+        Some(())
+    }
+
+    /// remove `neuron` of hidden `level` with its incoming and outgoing weights
+    pub fn remove_neuron(&mut self, level: usize, neuron: usize) -> Option<()> {
+        let (prev, next) = self.hidden_pair("remove_neuron", level)?;
+        if neuron >= self.layers[level] || self.layers[level] == 1 {
+            println!(
+                "NN>>remove_neuron: can not remove {} of level {}",
+                neuron, level
+            );
+            return None;
+        }
+        let (mut weights, mut biases, activation, dropout) = prev;
+        for row in weights.el.iter_mut() {
+            row.remove(neuron);
+        }
+        biases.el[0].remove(neuron);
+
+        let (mut next_weights, next_biases, next_activation, next_dropout) = next;
+        next_weights.el.remove(neuron);
+
+        self.replace(
+            level - 1,
+            vec![
+                dense(weights, biases, activation, dropout),
+                dense(next_weights, next_biases, next_activation, next_dropout),
+            ],
+        );
+        Some(())
+    }
+
+    /// insert a dense layer right after `level`, initialised as identity
+    /// (identity weights, zero biases, linear activation)
+    pub fn insert_layer(&mut self, level: usize) -> Option<()> {
+        if !self.editable("insert_layer") {
+            return None;
+        }
+        if level > self.len() {
+            println!("NN>>insert_layer: no level {}", level);
+            return None;
+        }
+        let width = self.layers[level];
+        let mut weights = Matrix::new(width, width);
+        for idx in 0..width {
+            *weights.at_mut(idx, idx) = 1.0;
+        }
+        let identity = Box::new(Dense::from(
+            weights,
+            Matrix::new(1, width),
+            Activation::Linear,
+        ));
+
+        let mut layers: Vec<Box<dyn Layer>> = self.net.layers.drain(..).collect();
+        layers.insert(level, identity);
+        let mut trainable = self.trainable.clone();
+        trainable.insert(level, true);
+        self.rebuild(layers, trainable);
+        Some(())
+    }
+
+    /// remove layer `idx`. its input and output must be of the same width
+    pub fn remove_layer(&mut self, idx: usize) -> Option<()> {
+        if !self.editable("remove_layer") {
+            return None;
+        }
+        if idx >= self.len() || self.len() == 1 {
+            println!("NN>>remove_layer: can not remove layer {}", idx);
+            return None;
+        }
+        let layer = &self.net.layers[idx];
+        if layer.input_size() != layer.output_size() {
+            println!(
+                "NN>>remove_layer: {} changes width, neighbors would not fit",
+                layer.name()
+            );
+            return None;
+        }
+
+        let mut layers: Vec<Box<dyn Layer>> = self.net.layers.drain(..).collect();
+        layers.remove(idx);
+        let mut trainable = self.trainable.clone();
+        trainable.remove(idx);
+        self.rebuild(layers, trainable);
+        Some(())
+    }
+
+    fn editable(&self, op: &str) -> bool {
+        if !self.net.skips.is_empty() {
+            println!("NN>>{}: models with skips can not be edited", op);
+            return false;
+        }
+        true
+    }
+
+    /// dense layers on both sides of hidden `level`
+    fn hidden_pair(&self, op: &str, level: usize) -> Option<(DenseParts, DenseParts)> {
+        if !self.editable(op) {
+            return None;
+        }
+        if level == 0 || level >= self.len() {
+            println!("NN>>{}: level {} is not hidden", op, level);
+            return None;
+        }
+        match (
+            parts(self.net.layers[level - 1].as_ref()),
+            parts(self.net.layers[level].as_ref()),
+        ) {
+            (Some(prev), Some(next)) => Some((prev, next)),
+            _ => {
+                println!("NN>>{}: level {} is not between dense layers", op, level);
+                None
+            }
+        }
+    }
+
+    /// put `layers` in place of layers from `start`
+    fn replace(&mut self, start: usize, layers: Vec<Box<dyn Layer>>) {
+        let mut all: Vec<Box<dyn Layer>> = self.net.layers.drain(..).collect();
+        for (idx, layer) in layers.into_iter().enumerate() {
+            all[start + idx] = layer;
+        }
+        let trainable = self.trainable.clone();
+        self.rebuild(all, trainable);
+    }
+
+    fn rebuild(&mut self, layers: Vec<Box<dyn Layer>>, trainable: Vec<bool>) {
+        let mut net = Sequential::new();
+        for layer in layers {
+            net.push(layer);
+        }
+        let mut nn = NN::from_net(net);
+        nn.trainable = trainable;
+        nn.regularization = self.regularization.clone();
+        *self = nn;
+    }
+}
+
+/// weights, biases, activation and dropout of a dense layer
+type DenseParts = (Matrix<f64>, Matrix<f64>, Activation, f64);
+
+fn parts(layer: &dyn Layer) -> Option<DenseParts> {
+    match layer.serialize() {
+        LayerData::Dense {
+            weights,
+            biases,
+            activation,
+            dropout,
+        } => Some((weights, biases, activation, dropout)),
+        _ => None,
+    }
+}
+
+fn dense(
+    weights: Matrix<f64>,
+    biases: Matrix<f64>,
+    activation: Activation,
+    dropout: f64,
+) -> Box<dyn Layer> {
+    Box::new(Dense::from(weights, biases, activation).with_dropout(dropout))
+}
+
+#[cfg(test)]
+fn outputs(nn: &mut NN) -> Vec<Vec<f64>> {
+    [[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]]
+        .iter()
+        .map(|input| {
+            nn.set(input);
+            nn.process();
+            nn.output().to_vec()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn assert_close(a: &[Vec<f64>], b: &[Vec<f64>]) {
+    for (ra, rb) in a.iter().zip(b) {
+        for (va, vb) in ra.iter().zip(rb) {
+            assert!((va - vb).abs() < 1e-12, "{} != {}", va, vb);
+        }
+    }
+}
+
+#[test]
+fn surgery_test_keeps_outputs() {
+    let mut nn = NN::new(&[2, 3, 1]);
+    nn.rand();
+    let expected = outputs(&mut nn);
+
+    nn.add_neuron(1).unwrap();
+    assert_eq!(nn.layers, vec![2, 4, 1]);
+    assert_close(&outputs(&mut nn), &expected);
+
+    nn.insert_layer(1).unwrap();
+    assert_eq!(nn.layers, vec![2, 4, 4, 1]);
+    assert_close(&outputs(&mut nn), &expected);
+
+    // the added neuron has no outgoing weight, removing it changes nothing
+    nn.remove_layer(1).unwrap();
+    nn.remove_neuron(1, 3).unwrap();
+    assert_eq!(nn.layers, vec![2, 3, 1]);
+    assert_close(&outputs(&mut nn), &expected);
+
+    assert!(nn.remove_layer(0).is_none());
+    assert!(nn.add_neuron(2).is_none());
+}
+
+#[test]
+fn surgery_test_remove_neuron_drops_its_weights() {
+    let mut nn = NN::new(&[2, 3, 1]);
+    nn.rand();
+    let kept = nn.net.layers[1].params()[0].at(2, 0);
+
+    nn.remove_neuron(1, 1).unwrap();
+    assert_eq!(nn.net.layers[0].params()[0].len_col(), 2);
+    assert_eq!(nn.net.layers[1].params()[0].at(1, 0), kept);
+}
@@ -1,6 +1,9 @@
 use std::{thread, time::Duration};
 
-use crate::adapter::context::{Context, State};
+use crate::adapter::{
+    context::{Context, State},
+    learner::LearnerEvent,
+};
 
 use super::{
     ui::controller::{control::Controller, model::ModelWindow},
@@ -91,24 +94,26 @@ impl eframe::App for Manager {
                     });
 
                 ui.label(format!("{:?}", context.state));
-
-                if context.trcv.is_some() {
-                    let w2g = context
-                        .trcv
-                        .as_ref()
-                        .unwrap()
-                        .rec
-                        .recv_timeout(Duration::from_micros(1000));
-                    if w2g.is_ok() {
-                        let w2g = w2g.unwrap();
-                        context.nodes = w2g.nodes;
-                        context.session.as_mut().unwrap().model = w2g.model.unwrap();
-
-                        if w2g.cost_info.is_some() {
-                            context.cost_info.push(w2g.cost_info.unwrap());
-                        }
+                match &context.event {
+                    Some(LearnerEvent::Diverged { cycle, reason }) => {
+                        ui.label(format!(
+                            "Stopped at cycle {}: {} (restored last good model)",
+                            cycle, reason
+                        ));
                     }
+                    Some(LearnerEvent::Finished { cycle, reason }) => {
+                        ui.label(format!("Finished at cycle {}: {}", cycle, reason));
+                    }
+                    Some(LearnerEvent::Failed { cycle, message }) => {
+                        ui.label(format!(
+                            "Failed at cycle {}: {} (restored last good model)",
+                            cycle, message
+                        ));
+                    }
+                    None => {}
                 }
+
+                context.poll();
             },
         );
     }
@@ -5,16 +5,35 @@ use crate::adapter::context::{Context, State};
 pub struct Controller;
 impl Controller {
     pub fn view(ui: &mut Ui, context: &mut Context) {
-        if context.state == State::Ready {
-            if ui.button("Start").clicked() {
-                context.start()
+        match context.state {
+            State::Ready => {
+                if ui.button("Start").clicked() {
+                    context.start()
+                }
             }
+            State::Running => {
+                if ui.button("Pause").clicked() {
+                    context.pause()
+                }
+            }
+            State::Paused => {
+                if ui.button("Resume").clicked() {
+                    context.resume()
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Step").clicked() {
+                        context.step(1)
+                    }
+                    if ui.button("Step 10").clicked() {
+                        context.step(10)
+                    }
+                });
+            }
+            State::Empty | State::Loading => {}
         }
 
-        if context.state == State::Running {
-            if ui.button("Stop").clicked() {
-                context.stop()
-            }
+        if context.is_training() && ui.button("Stop").clicked() {
+            context.stop();
         }
     }
 }
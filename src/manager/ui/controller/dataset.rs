@@ -5,7 +5,7 @@ use eframe::egui::Ui;
 
 use crate::{
     adapter::context::{Context, State},
-    core::nn::dataset::DataSet,
+    core::nn::dataset::{DataSet, SplitMethod, SplitRatio},
 };
 
 #[derive(PartialEq)]
@@ -14,6 +14,7 @@ enum DataSetMenu {
     Edit,
     Load,
     Save,
+    Split,
 }
 
 pub struct DatasetWindow {
@@ -23,6 +24,7 @@ pub struct DatasetWindow {
     dataset_update: Option<DatasetUpdate>,
     dataset_load: DatasetLoad,
     dataset_save: DatasetSave,
+    dataset_split: DatasetSplit,
 }
 
 impl DatasetWindow {
@@ -34,6 +36,7 @@ impl DatasetWindow {
             dataset_update: None,
             dataset_load: DatasetLoad::new(),
             dataset_save: DatasetSave::new(),
+            dataset_split: DatasetSplit::new(),
         }
     }
 
@@ -72,6 +75,7 @@ impl DatasetWindow {
                         DataSetMenu::Save,
                         "Save Current",
                     );
+                    ui.selectable_value(&mut self.menu, DataSetMenu::Split, "Split");
                 });
                 ui.separator();
 
@@ -96,6 +100,9 @@ impl DatasetWindow {
                         DataSetMenu::Save => {
                             self.dataset_save.view(ui, context);
                         }
+                        DataSetMenu::Split => {
+                            self.dataset_split.view(ui, context);
+                        }
                     }
                 })
             });
@@ -280,3 +287,62 @@ impl DatasetLoad {
         }
     }
 }
+
+/// hold out validation and test samples of the current dataset
+pub struct DatasetSplit {
+    ratio: SplitRatio,
+}
+
+impl DatasetSplit {
+    fn new() -> Self {
+        Self {
+            ratio: SplitRatio {
+                validation: 0.2,
+                test: 0.0,
+                method: SplitMethod::Random,
+            },
+        }
+    }
+
+    pub fn view(&mut self, ui: &mut Ui, context: &mut Context) {
+        if context.is_training() {
+            ui.label("Stop training to split the dataset");
+            return;
+        }
+        let session = match context.session.as_mut() {
+            Some(session) if session.dataset.is_some() => session,
+            _ => {
+                ui.label("No dataset");
+                return;
+            }
+        };
+
+        match session.split.as_ref() {
+            None => ui.label("Training on the whole dataset"),
+            Some(split) => ui.label(format!(
+                "Training: {}\nValidation: {}\nTest: {}",
+                split.train.len(),
+                split.validation.len(),
+                split.test.len()
+            )),
+        };
+        ui.separator();
+
+        ui.add(egui::Slider::new(&mut self.ratio.validation, 0.0..=0.9).text("Validation"));
+        ui.add(egui::Slider::new(&mut self.ratio.test, 0.0..=0.9).text("Test"));
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.ratio.method, SplitMethod::Random, "Random");
+            ui.radio_value(&mut self.ratio.method, SplitMethod::Stratified, "Stratified");
+        });
+
+        let valid = self.ratio.validation + self.ratio.test < 1.0;
+        ui.horizontal(|ui| {
+            if ui.add_enabled(valid, egui::Button::new("Split")).clicked() {
+                session.split_dataset(Some(self.ratio.clone()));
+            }
+            if ui.button("Use whole dataset").clicked() {
+                session.split_dataset(None);
+            }
+        });
+    }
+}
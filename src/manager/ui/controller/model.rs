@@ -2,11 +2,20 @@ use eframe::egui::Ui;
 use eframe::egui::{self, Slider};
 
 use crate::adapter::context::Context;
-use crate::adapter::session::{PostX, TrainingMethod};
+use crate::adapter::nodes::Nodes;
+use crate::adapter::session::{PostX, SessionOption, TrainingMethod};
+use crate::adapter::stopping::{EarlyStopping, StopCondition};
+use crate::core::nn::clipping::GradientClip;
+use crate::core::nn::layer::norm::Normalization;
+use crate::core::nn::nn::NN;
+use crate::core::nn::regularization::Regularization;
+use crate::core::nn::sequential::{Merge, Skip};
 
 #[derive(PartialEq)]
 enum ModelMenu {
     Create,
+    Summary,
+    Edit,
     Load,
     Save,
 }
@@ -15,6 +24,7 @@ pub struct ModelWindow {
     is_open: bool,
     menu: ModelMenu,
     model_create: ModelCreate,
+    model_edit: ModelEdit,
 }
 
 impl ModelWindow {
@@ -23,6 +33,7 @@ impl ModelWindow {
             is_open: false,
             menu: ModelMenu::Create,
             model_create: ModelCreate::new(),
+            model_edit: ModelEdit::new(),
         }
     }
     pub fn toggle(&mut self) {
@@ -45,6 +56,12 @@ impl ModelWindow {
                         ModelMenu::Create,
                         "Create New",
                     );
+                    ui.selectable_value(
+                        &mut self.menu,
+                        ModelMenu::Summary,
+                        "Summary",
+                    );
+                    ui.selectable_value(&mut self.menu, ModelMenu::Edit, "Edit");
                     ui.selectable_value(
                         &mut self.menu,
                         ModelMenu::Save,
@@ -58,6 +75,25 @@ impl ModelWindow {
                         ModelMenu::Create => {
                             self.model_create.view(ui, context);
                         }
+                        ModelMenu::Summary => match context.session.as_mut() {
+                            None => {
+                                ui.label("No model");
+                            }
+                            Some(session) => {
+                                ui.monospace(session.model.summary());
+                                ui.separator();
+                                ui.label("Trainable layers (applied on next start)");
+                                let model = &mut session.model;
+                                for (layer, trainable) in
+                                    model.net.layers.iter().zip(model.trainable.iter_mut())
+                                {
+                                    ui.checkbox(trainable, layer.name());
+                                }
+                            }
+                        },
+                        ModelMenu::Edit => {
+                            self.model_edit.view(ui, context);
+                        }
                         _ => {}
                     }
                 })
@@ -65,24 +101,109 @@ impl ModelWindow {
     }
 }
 
+/// layout edits of the current model, keeping trained weights
+pub struct ModelEdit {
+    level: usize,
+    neuron: usize,
+    layer: usize,
+}
+
+impl ModelEdit {
+    fn new() -> Self {
+        ModelEdit {
+            level: 1,
+            neuron: 0,
+            layer: 0,
+        }
+    }
+
+    pub fn view(&mut self, ui: &mut Ui, context: &mut Context) {
+        if context.is_training() {
+            ui.label("Stop training to edit the model");
+            return;
+        }
+        let model = match context.session.as_mut() {
+            None => {
+                ui.label("No model");
+                return;
+            }
+            Some(session) => &mut session.model,
+        };
+        ui.label(format!("Levels: {:?}", model.layers));
+
+        let mut edited = None;
+        ui.horizontal(|ui| {
+            ui.label("Level");
+            ui.add(egui::DragValue::new(&mut self.level).clamp_range(0..=model.len()));
+            if ui.button("Add neuron").clicked() {
+                edited = model.add_neuron(self.level);
+            }
+            ui.label("Neuron");
+            ui.add(egui::DragValue::new(&mut self.neuron));
+            if ui.button("Remove neuron").clicked() {
+                edited = model.remove_neuron(self.level, self.neuron);
+            }
+            if ui.button("Insert layer after").clicked() {
+                edited = model.insert_layer(self.level);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Layer");
+            ui.add(egui::DragValue::new(&mut self.layer).clamp_range(0..=model.len() - 1));
+            if ui.button("Remove layer").clicked() {
+                edited = model.remove_layer(self.layer);
+            }
+        });
+
+        if edited.is_some() {
+            context.nodes = Some(Nodes::from(&context.session.as_ref().unwrap().model));
+        }
+    }
+}
+
 pub struct ModelCreate {
     layout: String,
+    dropout: String,
+    skips: String,
+    normalization: Option<Normalization>,
     train_method: TrainingMethod,
     rate: f64,
     eps: f64,
+    /// step scale of BackProp and AutoDiff
+    learning_rate: f64,
     post_x: PostX,
     cycle: usize,
+    batch: Option<usize>,
+    /// threads sharing each BackProp step
+    threads: usize,
+    regularization: Regularization,
+    clip_value: Option<f64>,
+    clip_norm: Option<f64>,
+    stop: StopCondition,
+    /// why the last Create was refused
+    error: Option<String>,
 }
 
 impl ModelCreate {
     pub fn new() -> Self {
         ModelCreate {
             layout: String::new(),
+            dropout: String::new(),
+            skips: String::new(),
+            normalization: None,
             train_method: TrainingMethod::BackProp,
             rate: 1e-3,
             eps: 1e-3,
+            learning_rate: 1.0,
             post_x: PostX::Sigmoid,
             cycle: 1000,
+            batch: None,
+            threads: 1,
+            regularization: Regularization::default(),
+            clip_value: None,
+            clip_norm: None,
+            stop: StopCondition::default(),
+            error: None,
         }
     }
     pub fn view(&mut self, ui: &mut Ui, context: &mut Context) {
@@ -94,6 +215,37 @@ impl ModelCreate {
             );
         });
 
+        ui.horizontal(|ui| {
+            ui.label("Dropout");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.dropout)
+                    .hint_text("rate of each hidden layer ex) 0.5,0.5"),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Skips");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.skips)
+                    .hint_text("level>level:add|concat ex) 1>2:add,0>3:concat"),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Normalization");
+            ui.radio_value(&mut self.normalization, None, "None");
+            ui.radio_value(
+                &mut self.normalization,
+                Some(Normalization::Batch),
+                "BatchNorm",
+            );
+            ui.radio_value(
+                &mut self.normalization,
+                Some(Normalization::Layer),
+                "LayerNorm",
+            );
+        });
+
         ui.horizontal(|ui| {
             ui.label("TrainingMethod");
             ui.radio_value(
@@ -101,6 +253,18 @@ impl ModelCreate {
                 TrainingMethod::BackProp,
                 "BackProp",
             );
+            // normalization layers can not be traced
+            let traceable = self.normalization.is_none();
+            if !traceable && self.train_method == TrainingMethod::AutoDiff {
+                self.train_method = TrainingMethod::BackProp;
+            }
+            let autodiff = egui::RadioButton::new(
+                self.train_method == TrainingMethod::AutoDiff,
+                "AutoDiff",
+            );
+            if ui.add_enabled(traceable, autodiff).clicked() {
+                self.train_method = TrainingMethod::AutoDiff;
+            }
             ui.radio_value(
                 &mut self.train_method,
                 TrainingMethod::FiniteDiff {
@@ -111,6 +275,16 @@ impl ModelCreate {
             );
         });
 
+        if !matches!(self.train_method, TrainingMethod::FiniteDiff { .. }) {
+            rate_slider(ui, &mut self.learning_rate);
+        }
+        if self.train_method == TrainingMethod::BackProp {
+            ui.horizontal(|ui| {
+                ui.label("Threads");
+                ui.add(egui::DragValue::new(&mut self.threads).clamp_range(1..=64));
+            });
+        }
+
         if let TrainingMethod::FiniteDiff { .. } = self.train_method {
             ui.horizontal(|ui| {
                 ui.label("Rate: ");
@@ -141,15 +315,196 @@ impl ModelCreate {
             ui.label("Cycle Chunk Size");
             ui.add(Slider::new(&mut self.cycle, 1..=10000));
         });
+        batch_size(ui, &mut self.batch);
 
+        ui.horizontal(|ui| {
+            ui.label("L1: ");
+            ui.add(
+                Slider::new(&mut self.regularization.l1, 0.0..=1.0)
+                    .smallest_positive(1e-10)
+                    .logarithmic(true)
+                    .text("l1"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("L2: ");
+            ui.add(
+                Slider::new(&mut self.regularization.l2, 0.0..=1.0)
+                    .smallest_positive(1e-10)
+                    .logarithmic(true)
+                    .text("l2"),
+            );
+        });
+        ui.checkbox(&mut self.regularization.biases, "Penalize biases");
+
+        clip_slider(ui, "Clip by value", &mut self.clip_value);
+        clip_slider(ui, "Clip by norm", &mut self.clip_norm);
+
+        ui.label("Stop conditions");
+        optional(ui, "Target cost", &mut self.stop.target_cost, 1e-3, |ui, cost| {
+            ui.add(Slider::new(cost, 1e-10..=1.0).logarithmic(true));
+        });
+        optional(ui, "Max cycles", &mut self.stop.max_cycles, 100000, |ui, cycles| {
+            ui.add(egui::DragValue::new(cycles).speed(100));
+        });
+        optional(ui, "Time budget", &mut self.stop.time_budget, 60.0, |ui, secs| {
+            ui.add(egui::DragValue::new(secs).suffix(" s"));
+        });
+        optional(
+            ui,
+            "Early stopping",
+            &mut self.stop.early_stopping,
+            EarlyStopping::default(),
+            |ui, early| {
+                ui.label("patience");
+                ui.add(egui::DragValue::new(&mut early.patience).speed(100));
+                ui.checkbox(&mut early.restore_best, "restore best");
+            },
+        );
+
+        if let Some(error) = self.error.as_ref() {
+            ui.label(error);
+        }
         if ui.button("Create").clicked() {
-            let layers: Vec<&str> = self.layout.split(',').collect();
-            let layers: Vec<usize> =
-                layers.iter().map(|s| s.parse::<usize>().unwrap()).collect();
+            let layers: Option<Vec<usize>> = self
+                .layout
+                .split(',')
+                .map(|s| s.trim().parse::<usize>().ok())
+                .collect();
+            let layers = match layers {
+                None => {
+                    println!("ModelCreate>>view: invalid layout {}", self.layout);
+                    self.error = Some(format!("invalid layout {}", self.layout));
+                    return;
+                }
+                Some(layers) => layers,
+            };
             let train_method = self.train_method.clone();
             let post_x = self.post_x.clone();
             let cycle = self.cycle.clone();
-            context.create_model(&layers, train_method, post_x, cycle);
+            let dropout: Option<Vec<f64>> = if self.dropout.trim().is_empty() {
+                Some(vec![0.0; layers.len().saturating_sub(2)])
+            } else {
+                self.dropout
+                    .split(',')
+                    .map(|s| s.trim().parse::<f64>().ok())
+                    .collect()
+            };
+            let dropout = match dropout {
+                None => {
+                    println!("ModelCreate>>view: invalid dropout {}", self.dropout);
+                    self.error = Some(format!("invalid dropout {}", self.dropout));
+                    return;
+                }
+                Some(dropout) => dropout,
+            };
+            let skips = match parse_skips(&self.skips) {
+                None => {
+                    println!("ModelCreate>>view: invalid skips {}", self.skips);
+                    self.error = Some(format!("invalid skips {}", self.skips));
+                    return;
+                }
+                Some(skips) => skips,
+            };
+            let mut model = match NN::try_stack(&layers, &dropout, &skips) {
+                Err(msg) => {
+                    println!("ModelCreate>>view: {}", msg);
+                    self.error = Some(msg);
+                    return;
+                }
+                Ok(model) => model,
+            };
+            self.error = None;
+            if let Some(normalization) = self.normalization {
+                model = model.normalized(normalization);
+            }
+            context.create_session(
+                model,
+                SessionOption {
+                    train_method,
+                    post_x,
+                    cycle,
+                    rate: self.learning_rate,
+                    batch: self.batch,
+                    threads: self.threads,
+                    regularization: self.regularization.clone(),
+                    clip: GradientClip {
+                        value: self.clip_value,
+                        norm: self.clip_norm,
+                    },
+                    stop: self.stop.clone(),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+}
+
+/// checkbox enabling a clipping threshold, with its slider
+fn clip_slider(ui: &mut Ui, label: &str, threshold: &mut Option<f64>) {
+    optional(ui, label, threshold, 1.0, |ui, threshold| {
+        ui.add(
+            Slider::new(threshold, 1e-3..=1e3)
+                .logarithmic(true)
+                .text("max"),
+        );
+    });
+}
+
+/// step scale of BackProp and AutoDiff
+pub fn rate_slider(ui: &mut Ui, rate: &mut f64) {
+    ui.horizontal(|ui| {
+        ui.label("Rate: ");
+        ui.add(
+            Slider::new(rate, 1e-6..=10.0)
+                .logarithmic(true)
+                .text("rate"),
+        );
+    });
+}
+
+/// checkbox enabling mini-batches, with their size
+pub fn batch_size(ui: &mut Ui, batch: &mut Option<usize>) {
+    optional(ui, "Mini-batch", batch, 32, |ui, size| {
+        ui.add(egui::DragValue::new(size).clamp_range(1..=100000));
+    });
+}
+
+/// checkbox enabling an optional setting, with its editor
+fn optional<T>(
+    ui: &mut Ui,
+    label: &str,
+    value: &mut Option<T>,
+    default: T,
+    edit: impl FnOnce(&mut Ui, &mut T),
+) {
+    ui.horizontal(|ui| {
+        let mut enabled = value.is_some();
+        ui.checkbox(&mut enabled, label);
+        match (enabled, value.as_mut()) {
+            (true, Some(value)) => edit(ui, value),
+            (true, None) => *value = Some(default),
+            (false, _) => *value = None,
         }
+    });
+}
+
+/// parse skips written as `from>to:merge`, separated by commas
+fn parse_skips(src: &str) -> Option<Vec<Skip>> {
+    let mut skips = Vec::new();
+    for skip in src.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        let (levels, merge) = skip.split_once(':')?;
+        let (from, to) = levels.split_once('>')?;
+        let merge = match merge.trim() {
+            "add" => Merge::Add,
+            "concat" => Merge::Concat,
+            _ => return None,
+        };
+        skips.push(Skip {
+            from: from.trim().parse().ok()?,
+            to: to.trim().parse().ok()?,
+            merge,
+        });
     }
+    Some(skips)
 }
@@ -1,13 +1,27 @@
 use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
 
-use eframe::egui::Ui;
+use eframe::egui::{self, Ui};
 
 use crate::adapter::context::Context;
+use crate::adapter::cross_validation::{Aggregate, CrossValidation, CrossValidationReport};
+use crate::adapter::data::Savable;
+use crate::adapter::search::{Search, SearchSpace, Strategy, Trial};
+use crate::adapter::session::{OptionUpdate, Session, SessionOption, TrainingMethod};
+use crate::core::nn::dataset::SplitMethod;
+use crate::core::nn::ensemble::{Combine, Ensemble};
+use crate::core::nn::regularization::Regularization;
+
+use super::model::{batch_size, rate_slider};
 
 #[derive(PartialEq)]
 enum SessionMenu {
     Load,
     Save,
+    Options,
+    CrossValidation,
+    Search,
 }
 
 pub struct SessionWindow {
@@ -15,6 +29,9 @@ pub struct SessionWindow {
     menu: SessionMenu,
     session_load: SessionLoad,
     session_save: SessionSave,
+    options: OptionsPanel,
+    cross_validation: CrossValidationPanel,
+    search: SearchPanel,
 }
 
 impl SessionWindow {
@@ -27,6 +44,9 @@ impl SessionWindow {
             menu: SessionMenu::Load,
             session_load: SessionLoad::new(),
             session_save: SessionSave::new(),
+            options: OptionsPanel::new(),
+            cross_validation: CrossValidationPanel::new(),
+            search: SearchPanel::new(),
         }
     }
     pub fn view(
@@ -49,7 +69,14 @@ impl SessionWindow {
                         &mut self.menu,
                         SessionMenu::Save,
                         "Save Current",
-                    )
+                    );
+                    ui.selectable_value(&mut self.menu, SessionMenu::Options, "Options");
+                    ui.selectable_value(
+                        &mut self.menu,
+                        SessionMenu::CrossValidation,
+                        "Cross Validation",
+                    );
+                    ui.selectable_value(&mut self.menu, SessionMenu::Search, "Search")
                 });
                 ui.separator();
 
@@ -61,7 +88,15 @@ impl SessionWindow {
                         SessionMenu::Save => {
                             self.session_save.view(ui, context);
                         }
-                        _ => {}
+                        SessionMenu::Options => {
+                            self.options.view(ui, context);
+                        }
+                        SessionMenu::CrossValidation => {
+                            self.cross_validation.view(ui, context);
+                        }
+                        SessionMenu::Search => {
+                            self.search.view(ui, context);
+                        }
                     }
                 })
             });
@@ -121,3 +156,520 @@ impl SessionSave {
         }
     }
 }
+
+/// options which can be changed while training, applied on the next cycle
+pub struct OptionsPanel {
+    /// edited options, taken from the session until edited
+    draft: Option<SessionOption>,
+    /// options in use when the draft was taken
+    base: Option<SessionOption>,
+}
+
+impl OptionsPanel {
+    fn new() -> Self {
+        Self {
+            draft: None,
+            base: None,
+        }
+    }
+
+    pub fn view(&mut self, ui: &mut Ui, context: &mut Context) {
+        let current = match context.session.as_ref() {
+            None => {
+                ui.label("No session");
+                return;
+            }
+            Some(session) => &session.option,
+        };
+        ui.label(format!(
+            "In use: rate {}, batch {}, cycle {}, l1 {}, l2 {}",
+            current.learning_rate(),
+            current
+                .batch
+                .map_or(String::from("all"), |batch| batch.to_string()),
+            current.cycle,
+            current.regularization.l1,
+            current.regularization.l2
+        ));
+        ui.separator();
+
+        // another session, or options acknowledged by the learner
+        if self.base.as_ref() != Some(current) {
+            self.base = Some(current.clone());
+            self.draft = None;
+        }
+        let draft = self.draft.get_or_insert_with(|| SessionOption {
+            rate: current.learning_rate(),
+            ..current.clone()
+        });
+        rate_slider(ui, &mut draft.rate);
+        batch_size(ui, &mut draft.batch);
+        ui.horizontal(|ui| {
+            ui.label("Cycle Chunk Size");
+            ui.add(egui::Slider::new(&mut draft.cycle, 1..=10000));
+        });
+        ui.horizontal(|ui| {
+            ui.label("L1");
+            ui.add(egui::Slider::new(&mut draft.regularization.l1, 0.0..=1.0).logarithmic(true));
+            ui.label("L2");
+            ui.add(egui::Slider::new(&mut draft.regularization.l2, 0.0..=1.0).logarithmic(true));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Report costs every");
+            ui.add(egui::DragValue::new(&mut draft.report.costs).clamp_range(1..=10000));
+            ui.label("model every");
+            ui.add(egui::DragValue::new(&mut draft.report.snapshot).clamp_range(1..=10000));
+            ui.label("cycles");
+        });
+
+        let (apply, reset) = ui
+            .horizontal(|ui| (ui.button("Apply").clicked(), ui.button("Reset").clicked()))
+            .inner;
+        if apply {
+            context.update_option(OptionUpdate {
+                rate: Some(draft.rate),
+                batch: Some(draft.batch),
+                cycle: Some(draft.cycle),
+                regularization: Some(draft.regularization.clone()),
+                report: Some(draft.report.clone()),
+            });
+        }
+        if reset {
+            self.draft = None;
+        }
+    }
+}
+
+/// k-fold cross-validation of the current model layout, options and dataset
+pub struct CrossValidationPanel {
+    setup: CrossValidation,
+    /// result of a running cross-validation
+    pending: Option<Receiver<Result<CrossValidationReport, String>>>,
+    report: Option<Result<CrossValidationReport, String>>,
+    /// how fold models are combined when saved as an ensemble
+    combine: Combine,
+    path: String,
+}
+
+impl CrossValidationPanel {
+    fn new() -> Self {
+        Self {
+            setup: CrossValidation {
+                folds: 5,
+                method: SplitMethod::Random,
+                max_cycles: 10000,
+            },
+            pending: None,
+            report: None,
+            combine: Combine::Average,
+            path: String::new(),
+        }
+    }
+
+    pub fn view(&mut self, ui: &mut Ui, context: &mut Context) {
+        ui.horizontal(|ui| {
+            ui.label("Folds");
+            ui.add(egui::DragValue::new(&mut self.setup.folds).clamp_range(2..=100));
+            ui.radio_value(&mut self.setup.method, SplitMethod::Random, "Random");
+            ui.radio_value(&mut self.setup.method, SplitMethod::Stratified, "Stratified");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Max cycles");
+            ui.add(egui::DragValue::new(&mut self.setup.max_cycles).speed(100));
+        });
+
+        if let Some(pending) = self.pending.as_ref() {
+            match pending.try_recv() {
+                Ok(report) => {
+                    self.report = Some(report);
+                    self.pending = None;
+                }
+                Err(TryRecvError::Disconnected) => {
+                    self.report = Some(Err(String::from("cross-validation thread panicked")));
+                    self.pending = None;
+                }
+                Err(TryRecvError::Empty) => {
+                    ui.label("Running...");
+                    ui.ctx().request_repaint();
+                }
+            }
+        } else {
+            let session = context.session.as_ref().filter(|s| s.dataset.is_some());
+            if let Some(session) = session {
+                if ui.button("Run").clicked() {
+                    let (snd, rec) = channel();
+                    let setup = self.setup.clone();
+                    let model = session.model.clone();
+                    let dataset = session.dataset.clone().unwrap();
+                    let option = session.option.clone();
+                    thread::spawn(move || {
+                        let _ = snd.send(setup.run(&model, &dataset, &option));
+                    });
+                    self.pending = Some(rec);
+                }
+            } else {
+                ui.label("No dataset");
+            }
+        }
+
+        match self.report.as_ref() {
+            None => {}
+            Some(Err(err)) => {
+                ui.label(err);
+            }
+            Some(Ok(report)) => {
+                ui.separator();
+                fold_grid(ui, report);
+                ensemble_actions(ui, &mut self.combine, &mut self.path, report);
+            }
+        }
+    }
+}
+
+/// results of every fold side by side, with their mean and std
+fn fold_grid(ui: &mut Ui, report: &CrossValidationReport) {
+    let aggregate = |aggregate: Option<Aggregate>| match aggregate {
+        Some(Aggregate { mean, std }) => format!("{:.4} ± {:.4}", mean, std),
+        None => String::from("-"),
+    };
+    let value = |value: Option<f64>| match value {
+        Some(value) => format!("{:.4}", value),
+        None => String::from("-"),
+    };
+
+    egui::Grid::new("folds").striped(true).show(ui, |ui| {
+        ui.label("");
+        for fold in 0..report.folds.len() {
+            ui.label(format!("Fold {}", fold));
+        }
+        ui.label("Mean ± std");
+        ui.end_row();
+
+        ui.label("Cycles");
+        for fold in report.folds.iter() {
+            ui.label(fold.cycle.to_string());
+        }
+        ui.end_row();
+
+        ui.label("Train cost");
+        for fold in report.folds.iter() {
+            ui.label(value(Some(fold.train_cost)));
+        }
+        ui.end_row();
+
+        ui.label("Validation cost");
+        for fold in report.folds.iter() {
+            ui.label(value(Some(fold.validation_cost)));
+        }
+        ui.label(aggregate(report.validation_cost));
+        ui.end_row();
+
+        ui.label("Accuracy");
+        for fold in report.folds.iter() {
+            let classification = fold.metrics.classification.as_ref();
+            ui.label(value(classification.map(|c| c.accuracy)));
+        }
+        ui.label(aggregate(report.accuracy));
+        ui.end_row();
+
+        ui.label("Macro F1");
+        for fold in report.folds.iter() {
+            let classification = fold.metrics.classification.as_ref();
+            ui.label(value(classification.map(|c| c.macro_avg.f1)));
+        }
+        ui.label(aggregate(report.f1));
+        ui.end_row();
+
+        ui.label("Bit accuracy");
+        for fold in report.folds.iter() {
+            ui.label(value(fold.metrics.bit_accuracy));
+        }
+        ui.label(aggregate(report.bit_accuracy));
+        ui.end_row();
+
+        ui.label("RMSE");
+        for fold in report.folds.iter() {
+            ui.label(value(Some(fold.metrics.regression.rmse)));
+        }
+        ui.label(aggregate(report.rmse));
+        ui.end_row();
+
+        ui.label("R2");
+        for fold in report.folds.iter() {
+            ui.label(value(Some(fold.metrics.regression.r2)));
+        }
+        ui.label(aggregate(report.r2));
+        ui.end_row();
+
+        ui.label("Ended by");
+        for fold in report.folds.iter() {
+            ui.label(match (&fold.diverged, &fold.stopped) {
+                (Some(divergence), _) => divergence.to_string(),
+                (None, Some(reason)) => reason.to_string(),
+                (None, None) => String::from("max cycles"),
+            });
+        }
+        ui.end_row();
+    });
+}
+
+/// save fold models as an ensemble
+fn ensemble_actions(
+    ui: &mut Ui,
+    combine: &mut Combine,
+    path: &mut String,
+    report: &CrossValidationReport,
+) {
+    ui.horizontal(|ui| {
+        ui.radio_value(combine, Combine::Average, "Average");
+        ui.radio_value(combine, Combine::Vote, "Vote");
+        ui.add(egui::TextEdit::singleline(path));
+        if ui.button("Save ensemble").clicked() {
+            match report
+                .ensemble(*combine)
+                .and_then(|ensemble| Ensemble::save(&ensemble, Path::new(path.as_str())))
+            {
+                None => println!("saving ensemble failed"),
+                Some(_) => println!("saving ensemble success"),
+            }
+        }
+    });
+}
+
+/// hyperparameter search over layouts and options, from the current session
+pub struct SearchPanel {
+    /// layouts separated by `;` ex) 2,3,1;2,4,1
+    layouts: String,
+    backprop: bool,
+    autodiff: bool,
+    /// rates of BackProp and AutoDiff, separated by `,`
+    learning_rates: String,
+    /// finite difference rates and eps, separated by `,`
+    rates: String,
+    eps: String,
+    cycles: String,
+    l2: String,
+    strategy: Strategy,
+    budget: usize,
+    threads: usize,
+    pending: Option<Receiver<Result<Vec<Trial>, String>>>,
+    trials: Option<Result<Vec<Trial>, String>>,
+    path: String,
+}
+
+impl SearchPanel {
+    fn new() -> Self {
+        Self {
+            layouts: String::new(),
+            backprop: true,
+            autodiff: false,
+            learning_rates: String::from("1"),
+            rates: String::new(),
+            eps: String::new(),
+            cycles: String::from("1000"),
+            l2: String::from("0"),
+            strategy: Strategy::Grid,
+            budget: 10000,
+            threads: 4,
+            pending: None,
+            trials: None,
+            path: String::new(),
+        }
+    }
+
+    /// search of the fields, None if a field can not be parsed
+    fn search(&self) -> Option<Search> {
+        let layouts = self
+            .layouts
+            .split(';')
+            .filter(|s| !s.trim().is_empty())
+            .map(parse_list)
+            .collect::<Option<Vec<Vec<usize>>>>()?;
+        let mut train_methods = Vec::new();
+        if self.backprop {
+            train_methods.push(TrainingMethod::BackProp);
+        }
+        if self.autodiff {
+            train_methods.push(TrainingMethod::AutoDiff);
+        }
+        train_methods.extend(SearchSpace::finite_diff(
+            &parse_list(&self.rates)?,
+            &parse_list(&self.eps)?,
+        ));
+        let regularizations = parse_list(&self.l2)?
+            .into_iter()
+            .map(|l2| Regularization {
+                l2,
+                ..Default::default()
+            })
+            .collect();
+        Some(Search {
+            space: SearchSpace {
+                layouts,
+                train_methods,
+                rates: parse_list(&self.learning_rates)?,
+                cycles: parse_list(&self.cycles)?,
+                regularizations,
+            },
+            strategy: self.strategy,
+            budget: self.budget,
+            threads: self.threads,
+        })
+    }
+
+    pub fn view(&mut self, ui: &mut Ui, context: &mut Context) {
+        let text = |ui: &mut Ui, label: &str, value: &mut String, hint: &str| {
+            ui.horizontal(|ui| {
+                ui.label(label);
+                ui.add(egui::TextEdit::singleline(value).hint_text(hint));
+            });
+        };
+        text(ui, "Layouts", &mut self.layouts, "ex) 2,3,1;2,4,1");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.backprop, "BackProp");
+            ui.checkbox(&mut self.autodiff, "AutoDiff");
+        });
+        text(ui, "Learning rates", &mut self.learning_rates, "ex) 0.1,1");
+        text(ui, "FiniteDiff rates", &mut self.rates, "ex) 1e-3,1e-2");
+        text(ui, "FiniteDiff eps", &mut self.eps, "ex) 1e-3");
+        text(ui, "Cycle chunks", &mut self.cycles, "ex) 100,1000");
+        text(ui, "L2", &mut self.l2, "ex) 0,1e-4");
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.strategy, Strategy::Grid, "Grid");
+            let samples = match self.strategy {
+                Strategy::Random { samples } => samples,
+                Strategy::Grid => 10,
+            };
+            ui.radio_value(&mut self.strategy, Strategy::Random { samples }, "Random");
+            if let Strategy::Random { samples } = &mut self.strategy {
+                ui.add(egui::DragValue::new(samples).clamp_range(1..=1000));
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Budget (cycles)");
+            ui.add(egui::DragValue::new(&mut self.budget).speed(100));
+            ui.label("Threads");
+            ui.add(egui::DragValue::new(&mut self.threads).clamp_range(1..=64));
+        });
+
+        if let Some(pending) = self.pending.as_ref() {
+            match pending.try_recv() {
+                Ok(trials) => {
+                    self.trials = Some(trials);
+                    self.pending = None;
+                }
+                Err(TryRecvError::Disconnected) => {
+                    self.trials = Some(Err(String::from("search thread panicked")));
+                    self.pending = None;
+                }
+                Err(TryRecvError::Empty) => {
+                    ui.label("Running...");
+                    ui.ctx().request_repaint();
+                }
+            }
+        } else {
+            let session = context.session.as_ref().filter(|s| s.dataset.is_some());
+            match (session, self.search()) {
+                (None, _) => {
+                    ui.label("No dataset");
+                }
+                (_, None) => {
+                    ui.label("Invalid search space");
+                }
+                (Some(session), Some(search)) => {
+                    if ui.button("Run").clicked() {
+                        let (snd, rec) = channel();
+                        let base = session.clone();
+                        thread::spawn(move || {
+                            let _ = snd.send(search.run(&base));
+                        });
+                        self.pending = Some(rec);
+                    }
+                }
+            }
+        }
+
+        match self.trials.as_ref() {
+            None => {}
+            Some(Err(err)) => {
+                ui.label(err);
+            }
+            Some(Ok(trials)) => {
+                ui.separator();
+                trial_grid(ui, trials);
+                if let Some(best) = trials.first() {
+                    best_actions(ui, context, &mut self.path, &best.session);
+                }
+            }
+        }
+    }
+}
+
+/// use or save the best session of a search
+fn best_actions(ui: &mut Ui, context: &mut Context, path: &mut String, best: &Session) {
+    ui.horizontal(|ui| {
+        let idle = !context.is_training();
+        if ui.add_enabled(idle, egui::Button::new("Use best")).clicked() {
+            context.set_session(best.clone());
+        }
+        ui.add(egui::TextEdit::singleline(path));
+        if ui.button("Save best").clicked() {
+            match Session::save(best, Path::new(path.as_str())) {
+                None => println!("saving best session failed"),
+                Some(_) => println!("saving best session success"),
+            }
+        }
+    });
+}
+
+/// candidates ranked by validation cost
+fn trial_grid(ui: &mut Ui, trials: &[Trial]) {
+    egui::Grid::new("trials").striped(true).show(ui, |ui| {
+        for head in [
+            "Rank",
+            "Layout",
+            "Method",
+            "Chunk",
+            "L2",
+            "Cycles",
+            "Train cost",
+            "Validation cost",
+            "Ended by",
+        ] {
+            ui.label(head);
+        }
+        ui.end_row();
+
+        for (rank, trial) in trials.iter().enumerate() {
+            let option = &trial.candidate.option;
+            ui.label((rank + 1).to_string());
+            ui.label(format!("{:?}", trial.candidate.layout));
+            ui.label(match option.train_method {
+                TrainingMethod::FiniteDiff { rate, eps } => {
+                    format!("FiniteDiff(rate {}, eps {})", rate, eps)
+                }
+                ref method => format!("{:?}(rate {})", method, option.rate),
+            });
+            ui.label(option.cycle.to_string());
+            ui.label(option.regularization.l2.to_string());
+            ui.label(trial.cycle.to_string());
+            ui.label(format!("{:.6}", trial.train_cost));
+            ui.label(format!("{:.6}", trial.validation_cost));
+            ui.label(match (&trial.diverged, &trial.stopped) {
+                (Some(divergence), _) => divergence.to_string(),
+                (None, Some(reason)) => reason.to_string(),
+                (None, None) => String::from("budget"),
+            });
+            ui.end_row();
+        }
+    });
+}
+
+/// values separated by `,`, empty if there are none
+fn parse_list<T: std::str::FromStr>(src: &str) -> Option<Vec<T>> {
+    src.split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<T>().ok())
+        .collect()
+}
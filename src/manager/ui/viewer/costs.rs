@@ -1,20 +1,27 @@
 use eframe::egui::{Response, Ui};
-use egui_plot::{self, AxisHints, Bar, BarChart, Plot, PlotResponse, PlotUi};
+use egui_plot::{
+    self, AxisHints, Bar, BarChart, Legend, Line, Plot, PlotPoints, PlotResponse, PlotUi,
+};
 
 use crate::{
     adapter::context::{Context, State},
-    core::nn::cost::CostInfo,
+    core::nn::{
+        cost::CostInfo,
+        metrics::{Metrics, Score},
+    },
 };
 
 #[derive(PartialEq)]
 pub enum CostsMenu {
     Chart,
     Detail,
+    Metrics,
 }
 
 pub struct CostsWindow {
     pub is_open: bool,
     pub menu: CostsMenu,
+    /// focused point, index of context.cost_info
     pub focused_cycle: Option<usize>,
 }
 
@@ -39,6 +46,7 @@ impl CostsWindow {
                 ui.horizontal(|ui| {
                     ui.selectable_value(&mut self.menu, CostsMenu::Chart, "Chart");
                     ui.selectable_value(&mut self.menu, CostsMenu::Detail, "Detail");
+                    ui.selectable_value(&mut self.menu, CostsMenu::Metrics, "Metrics");
                 });
                 ui.separator();
 
@@ -47,24 +55,64 @@ impl CostsWindow {
                         draw_cost(ui, context, &mut self.focused_cycle);
                     }
                     CostsMenu::Detail => cost_detail(ui, context, &self.focused_cycle),
+                    CostsMenu::Metrics => metrics_detail(ui, context, &self.focused_cycle),
                 }
             });
     }
 }
 
-fn visualize_costs(plot_ui: &mut PlotUi, costs: &Vec<CostInfo>) {
+/// every point is drawn over the training steps since the previous one
+fn visualize_costs(
+    plot_ui: &mut PlotUi,
+    costs: &Vec<CostInfo>,
+    cycles: &[usize],
+    validation: &[Option<CostInfo>],
+) {
     let chart = BarChart::new(
         costs
             .iter()
+            .zip(cycles)
             .enumerate()
-            .map(|(cycle, cost_info)| {
-                Bar::new(cycle as f64, cost_info.avg.log10())
-                    .width(1.0)
+            .map(|(idx, (cost_info, cycle))| {
+                let since = if idx == 0 { 0 } else { cycles[idx - 1] };
+                let width = (cycle - since).max(1) as f64;
+                Bar::new(*cycle as f64 - width / 2.0, cost_info.avg.log10())
+                    .width(width)
                     .name(format!("cost: {}\ncycle: {}", cost_info.avg, cycle))
             })
             .collect(),
-    );
+    )
+    .name("training");
     plot_ui.bar_chart(chart);
+
+    let points: Vec<[f64; 2]> = validation
+        .iter()
+        .zip(cycles)
+        .filter_map(|(cost_info, cycle)| {
+            cost_info
+                .as_ref()
+                .map(|cost_info| [*cycle as f64, cost_info.avg.log10()])
+        })
+        .collect();
+    if !points.is_empty() {
+        plot_ui.line(Line::new(PlotPoints::new(points)).name("validation"));
+    }
+}
+
+/// index of the point drawn over training step `x`, if any
+fn point_at(cycles: &[usize], x: f64) -> Option<usize> {
+    if x < 0.0 {
+        return None;
+    }
+    let idx = cycles.partition_point(|cycle| (*cycle as f64) < x);
+    (idx < cycles.len()).then_some(idx)
+}
+
+/// avg cost on validation samples of given point, if any
+fn validation_label(ui: &mut Ui, validation: &[Option<CostInfo>], cycle: usize) {
+    if let Some(Some(cost)) = validation.get(cycle) {
+        ui.label(format!("Validation AVG: {}", cost.avg));
+    }
 }
 pub fn draw_cost(
     ui: &mut Ui,
@@ -79,18 +127,18 @@ pub fn draw_cost(
         _ => (),
     }
 
-    let x_axes = vec![AxisHints::default().label(format!(
-        "Cycle / {}",
-        context.session.as_ref().unwrap().option.cycle
-    ))];
+    let x_axes = vec![AxisHints::default().label("Training steps")];
     let y_axes = vec![AxisHints::default().label("Log10 ( Cost )")];
     let plot = Plot::new("costs")
         .height(300.0)
         .clamp_grid(true)
         .custom_x_axes(x_axes)
-        .custom_y_axes(y_axes);
+        .custom_y_axes(y_axes)
+        .legend(Legend::default());
 
     let costs = context.cost_info.clone();
+    let cycles = context.cycles.clone();
+    let validation = context.validation_cost.clone();
 
     let PlotResponse {
         response,
@@ -101,7 +149,7 @@ pub fn draw_cost(
             plot_ui.pointer_coordinate(),
             plot_ui.response().hovered(),
             plot_ui.response().clicked(),
-            visualize_costs(plot_ui, &costs),
+            visualize_costs(plot_ui, &costs, &cycles, &validation),
         )
     });
 
@@ -109,19 +157,21 @@ pub fn draw_cost(
         // if it's in a bound of costs
         let focused = focus.unwrap();
 
-        if 0 < focused && focused <= costs.len().saturating_sub(1) {
+        if focused < costs.len() {
             let cost = &costs[focused];
 
             ui.separator();
             ui.heading("Focused");
 
-            ui.label(format!("Cycle: {}", focused));
+            ui.label(format!("Cycle: {}", cycles[focused]));
             let max = cost.max();
             ui.label(format!("Max index: {}\nMax cost: {}", max.idx, max.val));
             let min = cost.min();
             ui.label(format!("Min index: {}\nMin cost: {}", min.idx, min.val));
             let avg = cost.avg;
             ui.label(format!("AVG: {}", avg));
+            ui.label(format!("Penalty: {}\nLoss: {}", cost.penalty, cost.loss()));
+            validation_label(ui, &validation, focused);
 
             if ui.button("Remove Focus").clicked() {
                 *focus = None;
@@ -133,28 +183,27 @@ pub fn draw_cost(
     }
 
     if hovered && pointer_coordinate.is_some() {
-        // currently hoverd cycle
-        let cycle = pointer_coordinate.unwrap().x.ceil() as usize;
-
-        // if it's in a bound of costs
-        if 0 < cycle && cycle <= costs.len().saturating_sub(1) {
-            let cost = &costs[cycle];
+        // point of currently hoverd cycle
+        if let Some(point) = point_at(&cycles, pointer_coordinate.unwrap().x) {
+            let cost = &costs[point];
 
             ui.separator();
             ui.heading("Hoverd");
 
-            ui.label(format!("Cycle: {}", cycle));
+            ui.label(format!("Cycle: {}", cycles[point]));
             let max = cost.max();
             ui.label(format!("Max index: {}\nMax cost: {}", max.idx, max.val));
             let min = cost.min();
             ui.label(format!("Min index: {}\nMin cost: {}", min.idx, min.val));
             let avg = cost.avg;
             ui.label(format!("AVG: {}", avg));
+            ui.label(format!("Penalty: {}\nLoss: {}", cost.penalty, cost.loss()));
+            validation_label(ui, &validation, point);
 
             ui.separator();
 
             if clicked {
-                *focus = Some(cycle);
+                *focus = Some(point);
             }
         }
     }
@@ -172,8 +221,8 @@ pub fn cost_detail(ui: &mut Ui, context: &mut Context, cycle: &Option<usize>) {
     }
 
     if cycle.is_some() {
-        let cycle = cycle.unwrap();
-        let cost = &context.cost_info[cycle];
+        let point = cycle.unwrap();
+        let cost = &context.cost_info[point];
 
         let x_axes = vec![AxisHints::default().label("Index")];
         let y_axes = vec![AxisHints::default().label("Log10 ( Cost )")];
@@ -197,13 +246,14 @@ pub fn cost_detail(ui: &mut Ui, context: &mut Context, cycle: &Option<usize>) {
         });
 
         ui.separator();
-        ui.label(format!("Cycle: {}", cycle));
+        ui.label(format!("Cycle: {}", context.cycles[point]));
         let max = cost.max();
         ui.label(format!("Max index: {}\nMax cost: {}", max.idx, max.val));
         let min = cost.min();
         ui.label(format!("Min index: {}\nMin cost: {}", min.idx, min.val));
         let avg = cost.avg;
-        ui.label(format!("AVG: {}\n", avg));
+        ui.label(format!("AVG: {}", avg));
+        ui.label(format!("Penalty: {}\nLoss: {}\n", cost.penalty, cost.loss()));
         ui.separator();
 
         ui.label("{Index}:{Cost}");
@@ -214,3 +264,76 @@ pub fn cost_detail(ui: &mut Ui, context: &mut Context, cycle: &Option<usize>) {
         ui.heading("Please set focused cycle");
     }
 }
+
+/// metrics of focused cycle, or of the last one
+pub fn metrics_detail(ui: &mut Ui, context: &mut Context, cycle: &Option<usize>) {
+    let cycle = match cycle {
+        Some(cycle) if *cycle < context.metrics.len() => *cycle,
+        _ if !context.metrics.is_empty() => context.metrics.len() - 1,
+        _ => {
+            ui.heading("No metrics yet");
+            return;
+        }
+    };
+    let Metrics {
+        regression,
+        classification,
+        bit_accuracy,
+    } = &context.metrics[cycle];
+
+    if let Some(cycle) = context.cycles.get(cycle) {
+        ui.label(format!("Cycle: {}", cycle));
+    }
+    ui.separator();
+    ui.label(format!(
+        "MAE: {}\nRMSE: {}\nR2: {}",
+        regression.mae, regression.rmse, regression.r2
+    ));
+    if let Some(bit_accuracy) = bit_accuracy {
+        ui.label(format!("Bit accuracy: {}", bit_accuracy));
+    }
+
+    if let Some(classification) = classification {
+        ui.separator();
+        ui.label(format!("Accuracy: {}", classification.accuracy));
+        if let Some(roc_auc) = classification.roc_auc {
+            ui.label(format!("ROC-AUC: {}", roc_auc));
+        }
+        let score = |ui: &mut Ui, name: String, score: &Score| {
+            ui.label(name);
+            ui.label(format!("{:.4}", score.precision));
+            ui.label(format!("{:.4}", score.recall));
+            ui.label(format!("{:.4}", score.f1));
+            ui.end_row();
+        };
+        eframe::egui::Grid::new("scores").striped(true).show(ui, |ui| {
+            ui.label("Class");
+            ui.label("Precision");
+            ui.label("Recall");
+            ui.label("F1");
+            ui.end_row();
+            for (class, s) in classification.per_class.iter().enumerate() {
+                score(ui, class.to_string(), s);
+            }
+            score(ui, String::from("macro"), &classification.macro_avg);
+            score(ui, String::from("micro"), &classification.micro_avg);
+        });
+
+        ui.separator();
+        ui.label("Confusion (actual \\ predicted)");
+        eframe::egui::Grid::new("confusion").striped(true).show(ui, |ui| {
+            ui.label("");
+            for class in 0..classification.confusion.len() {
+                ui.label(class.to_string());
+            }
+            ui.end_row();
+            for (actual, row) in classification.confusion.iter().enumerate() {
+                ui.label(actual.to_string());
+                for count in row.iter() {
+                    ui.label(count.to_string());
+                }
+                ui.end_row();
+            }
+        });
+    }
+}
@@ -2,11 +2,13 @@ use eframe::{
     egui::{Response, Ui},
     epaint::Color32,
 };
-use egui_plot::{self, AxisHints, Line, Plot, PlotPoint, PlotPoints, PlotResponse, PlotUi, Points};
+use egui_plot::{
+    self, AxisHints, Line, Plot, PlotPoint, PlotPoints, PlotResponse, PlotUi, Points, Polygon,
+};
 
 use crate::adapter::{
     context::{Context, State},
-    nodes::{Node, Nodes, Nodetype},
+    nodes::{Block, Node, Nodes, Nodetype, SkipEdge},
 };
 
 use super::super::gradient::{Color, Gradient};
@@ -64,7 +66,75 @@ fn create_node(pos: PlotPoint, node: &Node) -> Points {
     points
 }
 
+fn create_block(block: &Block) -> Polygon {
+    let pos_x = block.level as f64 * 10.0;
+    // sqrt keeps wide layers on screen while still showing their size
+    let half_height = (block.size as f64).sqrt().max(1.0);
+
+    Polygon::new(PlotPoints::new(vec![
+        [pos_x - 2.0, -half_height],
+        [pos_x + 2.0, -half_height],
+        [pos_x + 2.0, half_height],
+        [pos_x - 2.0, half_height],
+    ]))
+    .name(format!("{}\nSize:{}", block.name, block.size))
+}
+
+/// lines of a skip connection, node to node or center to center of levels
+fn create_skip(skip: &SkipEdge, layers: &[usize]) -> Vec<Line> {
+    let pos_src_x = skip.src_level as f64 * 10.0;
+    let pos_dst_x = skip.dst_level as f64 * 10.0;
+    let len_src = layers[skip.src_level];
+    let len_dst = layers[skip.dst_level];
+
+    let pairs: Vec<(f64, f64)> = if skip.pairs.is_empty() {
+        vec![(0.0, 0.0)]
+    } else {
+        skip.pairs
+            .iter()
+            .map(|(src, dst)| {
+                (
+                    *src as f64 * 2.0 - (len_src - 1) as f64,
+                    *dst as f64 * 2.0 - (len_dst - 1) as f64,
+                )
+            })
+            .collect()
+    };
+
+    pairs
+        .into_iter()
+        .map(|(pos_src_y, pos_dst_y)| {
+            // arc above the straight path so it does not hide middle levels
+            let lift = (pos_dst_x - pos_src_x) / 10.0;
+            Line::new(PlotPoints::from_explicit_callback(
+                move |x| {
+                    let t = (x - pos_src_x) / (pos_dst_x - pos_src_x);
+                    pos_src_y + (pos_dst_y - pos_src_y) * t + lift * 4.0 * t * (1.0 - t)
+                },
+                (pos_src_x + 0.2)..(pos_dst_x - 0.2),
+                256,
+            ))
+            .color(Color32::GRAY)
+            .style(egui_plot::LineStyle::dashed_loose())
+            .name(format!(
+                "skip {:?}\n{}->{}",
+                skip.merge, skip.src_level, skip.dst_level
+            ))
+        })
+        .collect()
+}
+
 fn visualize(plot_ui: &mut PlotUi, nn: Nodes) {
+    for block in nn.blocks.iter() {
+        plot_ui.polygon(create_block(block));
+    }
+
+    for skip in nn.skips.iter() {
+        for line in create_skip(skip, &nn.layers) {
+            plot_ui.line(line);
+        }
+    }
+
     for (level, connections) in nn.connections.iter().enumerate() {
         for (node_idx, node_connections) in connections.iter().enumerate() {
             for con in node_connections.iter() {